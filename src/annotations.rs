@@ -0,0 +1,107 @@
+use log::debug;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+use crate::destinations::{deref_dict, explicit_destination};
+
+/// Rewrite the destinations of every link annotation in `doc` so they survive the merge.
+///
+/// `doc` must already be renumbered into the merged document's ID space. Named destinations are
+/// replaced by the explicit `[page /Fit ...]` array they stand for, because a name another
+/// document also uses is renamed in the merged `Dests` tree. Links whose page is no longer in
+/// `doc` lose their destination rather than pointing at an unrelated object.
+pub(crate) fn resolve_link_destinations(doc: &mut Document) {
+    for page_id in doc.get_pages().into_values() {
+        let Some(annots) = doc
+            .get_dictionary(page_id)
+            .and_then(|page| page.get(b"Annots"))
+            .and_then(|annots| doc.dereference(annots))
+            .and_then(|(_, annots)| annots.as_array())
+            .ok()
+        else {
+            continue;
+        };
+
+        let mut indirect = Vec::new();
+        let mut inline = Vec::new();
+        for (index, annot) in annots.iter().enumerate() {
+            match annot {
+                Object::Reference(id) => {
+                    if let Some(fix) = doc.get_dictionary(*id).ok().and_then(|annot| link_fix(doc, annot)) {
+                        indirect.push((*id, fix));
+                    }
+                }
+                Object::Dictionary(annot) => {
+                    if let Some(fix) = link_fix(doc, annot) {
+                        inline.push((index, fix));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (id, fix) in indirect {
+            if let Ok(annot) = doc.get_dictionary_mut(id) {
+                fix.apply(annot);
+            }
+        }
+        if let Ok(Object::Array(annots)) = doc.get_dictionary_mut(page_id).and_then(|page| page.get_mut(b"Annots")) {
+            for (index, fix) in inline {
+                if let Some(Object::Dictionary(annot)) = annots.get_mut(index) {
+                    fix.apply(annot);
+                }
+            }
+        }
+    }
+}
+
+/// A replacement for a link's `Dest` or `A` entry; `None` removes the entry.
+struct LinkFix {
+    key: &'static [u8],
+    value: Option<Object>,
+}
+
+impl LinkFix {
+    fn apply(self, annot: &mut Dictionary) {
+        match self.value {
+            Some(value) => annot.set(self.key, value),
+            None => {
+                annot.remove(self.key);
+            }
+        }
+    }
+}
+
+/// How to rewrite a link annotation, or `None` if it isn't an internal link.
+fn link_fix(doc: &Document, annot: &Dictionary) -> Option<LinkFix> {
+    if annot.get(b"Subtype").and_then(Object::as_name).ok()? != b"Link" {
+        return None;
+    }
+
+    if let Ok(dest) = annot.get(b"Dest") {
+        let value = resolve(doc, dest).map(Object::Array);
+        return Some(LinkFix { key: b"Dest", value });
+    }
+
+    let action = annot.get(b"A").and_then(|action| deref_dict(doc, action)).ok()?;
+    if action.get(b"S").and_then(Object::as_name).ok()? != b"GoTo" {
+        return None;
+    }
+    let value = resolve(doc, action.get(b"D").ok()?).map(|dest| {
+        // Keep the rest of the action, such as a `Next` chain.
+        let mut action = action.clone();
+        action.set("D", dest);
+        Object::Dictionary(action)
+    });
+    Some(LinkFix { key: b"A", value })
+}
+
+/// The explicit destination for `dest`, if its page still exists in `doc`.
+fn resolve(doc: &Document, dest: &Object) -> Option<Vec<Object>> {
+    let dest = explicit_destination(doc, dest)?;
+    let page: ObjectId = dest.first()?.as_reference().ok()?;
+    if !doc.objects.contains_key(&page) {
+        debug!("Dropping link to missing page {:?}", page);
+        return None;
+    }
+    Some(dest.to_vec())
+}
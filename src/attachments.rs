@@ -0,0 +1,43 @@
+use log::debug;
+use lopdf::{Dictionary, Document};
+
+use crate::destinations::deref_dict;
+use crate::name_tree::{self, NameTree};
+
+/// The files attached to the merged documents, combined into one `/EmbeddedFiles` name tree.
+#[derive(Default)]
+pub(crate) struct Attachments(NameTree);
+
+impl Attachments {
+    /// Take over the attachments of `doc`, which must already be renumbered into the merged
+    /// document's ID space.
+    ///
+    /// An attachment whose name is already taken by an earlier document is renamed to
+    /// `name_2`, `name_3` and so on, as a name tree maps each name to one file.
+    pub(crate) fn add(&mut self, doc: &Document) {
+        let Ok(tree) = doc
+            .catalog()
+            .and_then(|catalog| catalog.get(b"Names"))
+            .and_then(|names| deref_dict(doc, names))
+            .and_then(|names| names.get(b"EmbeddedFiles"))
+        else {
+            return;
+        };
+
+        for (name, file_spec) in name_tree::leaves(doc, tree) {
+            let unique = self.0.insert(name.clone(), file_spec);
+            if unique != name {
+                debug!(
+                    "Renaming attachment {:?} to {:?}",
+                    String::from_utf8_lossy(&name),
+                    String::from_utf8_lossy(&unique)
+                );
+            }
+        }
+    }
+
+    /// The merged `/EmbeddedFiles` name tree, or `None` if no document had attachments.
+    pub(crate) fn into_name_tree(self) -> Option<Dictionary> {
+        self.0.into_dictionary()
+    }
+}
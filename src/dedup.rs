@@ -0,0 +1,213 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+/// Collapse structurally identical fonts and images into a single shared object.
+///
+/// Font programs are merged first, which can make their descriptors and then the fonts
+/// themselves identical, so this repeats until nothing changes. Returns the number of
+/// objects removed.
+pub(crate) fn dedup_objects(doc: &mut Document) -> usize {
+    let mut removed = 0;
+
+    loop {
+        let mut groups: HashMap<u64, Vec<ObjectId>> = HashMap::new();
+        for (id, object) in &doc.objects {
+            if is_candidate(object) {
+                let mut hasher = DefaultHasher::new();
+                hash_object(object, &mut hasher);
+                groups.entry(hasher.finish()).or_default().push(*id);
+            }
+        }
+
+        let mut replace = BTreeMap::new();
+        for ids in groups.values() {
+            let mut kept: Vec<ObjectId> = Vec::new();
+            for id in ids {
+                match kept.iter().find(|kept| same_object(&doc.objects[kept], &doc.objects[id])) {
+                    Some(kept) => {
+                        replace.insert(*id, *kept);
+                    }
+                    None => kept.push(*id),
+                }
+            }
+        }
+
+        if replace.is_empty() {
+            return removed;
+        }
+
+        for id in replace.keys() {
+            doc.objects.remove(id);
+        }
+        doc.traverse_objects(|object| {
+            if let Object::Reference(id) = object {
+                if let Some(kept) = replace.get(id) {
+                    *id = *kept;
+                }
+            }
+        });
+        removed += replace.len();
+    }
+}
+
+/// Fonts, font descriptors, embedded font programs and images.
+fn is_candidate(object: &Object) -> bool {
+    match object {
+        Object::Dictionary(dict) => {
+            matches!(name(dict, b"Type"), Some(b"Font" | b"FontDescriptor"))
+        }
+        Object::Stream(stream) => {
+            let dict = &stream.dict;
+            // FontFile and FontFile2 programs carry their segment lengths.
+            dict.has(b"Length1")
+                || dict.has(b"Length2")
+                || matches!(
+                    name(dict, b"Subtype"),
+                    Some(b"Image" | b"Type1C" | b"CIDFontType0C" | b"OpenType")
+                )
+        }
+        _ => false,
+    }
+}
+
+fn name<'a>(dict: &'a Dictionary, key: &[u8]) -> Option<&'a [u8]> {
+    dict.get(key).and_then(Object::as_name).ok()
+}
+
+/// Streams are compared by dictionary and content only; their position in the source file
+/// doesn't matter.
+fn same_object(a: &Object, b: &Object) -> bool {
+    match (a, b) {
+        (Object::Stream(a), Object::Stream(b)) => a.dict == b.dict && a.content == b.content,
+        _ => a == b,
+    }
+}
+
+/// Hash an object consistently with `same_object`; dictionary keys are hashed in sorted order.
+fn hash_object<H: Hasher>(object: &Object, state: &mut H) {
+    std::mem::discriminant(object).hash(state);
+    match object {
+        Object::Null => {}
+        Object::Boolean(value) => value.hash(state),
+        Object::Integer(value) => value.hash(state),
+        Object::Real(value) => value.to_bits().hash(state),
+        Object::Name(name) => name.hash(state),
+        Object::String(bytes, _) => bytes.hash(state),
+        Object::Array(items) => {
+            items.len().hash(state);
+            for item in items {
+                hash_object(item, state);
+            }
+        }
+        Object::Dictionary(dict) => hash_dictionary(dict, state),
+        Object::Stream(stream) => {
+            hash_dictionary(&stream.dict, state);
+            stream.content.hash(state);
+        }
+        Object::Reference(id) => id.hash(state),
+    }
+}
+
+fn hash_dictionary<H: Hasher>(dict: &Dictionary, state: &mut H) {
+    let mut entries = dict.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries.len().hash(state);
+    for (key, value) in entries {
+        key.hash(state);
+        hash_object(value, state);
+    }
+}
+
+/// The pages of `order` that are identical to the page right before them, each mapped to the
+/// earlier page it repeats.
+///
+/// `pages` holds the page dictionaries and `doc` everything they reference. Only exact
+/// matches count: the same content once decompressed, and the same resources and other page
+/// attributes, compared by value rather than by object number. A run of identical pages
+/// collapses into its first page.
+pub(crate) fn duplicate_pages(doc: &Document, pages: &BTreeMap<ObjectId, Object>, order: &[ObjectId]) -> BTreeMap<ObjectId, ObjectId> {
+    let mut duplicates = BTreeMap::new();
+    let mut kept: Option<(ObjectId, &Dictionary, Vec<u8>)> = None;
+
+    for &id in order {
+        let Some(page) = pages.get(&id).and_then(|page| page.as_dict().ok()) else {
+            continue;
+        };
+        let content = page_content(doc, page);
+        if let Some((kept_id, kept_page, kept_content)) = &kept {
+            if *kept_content == content && same_page(doc, kept_page, page) {
+                duplicates.insert(id, *kept_id);
+                continue;
+            }
+        }
+        kept = Some((id, page, content));
+    }
+
+    duplicates
+}
+
+/// The decompressed content of a page, its content streams joined in order.
+fn page_content(doc: &Document, page: &Dictionary) -> Vec<u8> {
+    let streams = match page.get(b"Contents").and_then(|contents| doc.dereference(contents)) {
+        Ok((_, Object::Array(streams))) => streams.iter().collect(),
+        Ok((_, stream)) => vec![stream],
+        Err(_) => Vec::new(),
+    };
+
+    let mut content = Vec::new();
+    for stream in streams {
+        if let Ok((_, Object::Stream(stream))) = doc.dereference(stream) {
+            match stream.decompressed_content() {
+                Ok(data) => content.extend(data),
+                Err(_) => content.extend_from_slice(&stream.content),
+            }
+        }
+    }
+    content
+}
+
+/// Whether two page dictionaries match in everything but their content and place in the
+/// page tree.
+fn same_page(doc: &Document, a: &Dictionary, b: &Dictionary) -> bool {
+    let attributes = |page: &Dictionary| {
+        let mut page = page.clone();
+        page.remove(b"Contents");
+        page.remove(b"Parent");
+        Object::Dictionary(page)
+    };
+    equivalent(doc, &attributes(a), &attributes(b), &mut HashSet::new())
+}
+
+/// Whether `a` and `b` have the same value, following references into `doc`. `assumed` holds
+/// the pairs of objects under comparison, which are taken to be equal when reached again, so
+/// that reference cycles terminate.
+fn equivalent(doc: &Document, a: &Object, b: &Object, assumed: &mut HashSet<(ObjectId, ObjectId)>) -> bool {
+    if let (Object::Reference(a_id), Object::Reference(b_id)) = (a, b) {
+        if a_id == b_id || !assumed.insert((*a_id, *b_id)) {
+            return true;
+        }
+    }
+    let (Ok((_, a)), Ok((_, b))) = (doc.dereference(a), doc.dereference(b)) else {
+        return false;
+    };
+
+    match (a, b) {
+        (Object::Array(a), Object::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equivalent(doc, a, b, assumed))
+        }
+        (Object::Dictionary(a), Object::Dictionary(b)) => equivalent_dictionaries(doc, a, b, assumed),
+        (Object::Stream(a), Object::Stream(b)) => {
+            a.content == b.content && equivalent_dictionaries(doc, &a.dict, &b.dict, assumed)
+        }
+        _ => a == b,
+    }
+}
+
+fn equivalent_dictionaries(doc: &Document, a: &Dictionary, b: &Dictionary, assumed: &mut HashSet<(ObjectId, ObjectId)>) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .all(|(key, a)| b.get(key).is_ok_and(|b| equivalent(doc, a, b, assumed)))
+}
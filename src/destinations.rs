@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+
+use log::debug;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+use crate::name_tree::{self, NameTree};
+
+/// The named destinations of the merged documents, combined into one `/Dests` name tree.
+#[derive(Default)]
+pub(crate) struct NamedDestinations(NameTree);
+
+impl NamedDestinations {
+    /// Take over the named destinations of `doc`, which must already be renumbered into the
+    /// merged document's ID space, from both its `/Names` tree and its catalog's `/Dests`.
+    ///
+    /// Each is kept as the explicit destination it stands for, unless its page is no longer
+    /// in `doc`. A name already taken by an earlier document is renamed to `name_2`, `name_3`
+    /// and so on; links within the documents don't mind, as their destinations are explicit.
+    pub(crate) fn add(&mut self, doc: &Document) {
+        let Ok(catalog) = doc.catalog() else {
+            return;
+        };
+
+        let mut named = Vec::new();
+        if let Ok(tree) = catalog.get(b"Names").and_then(|names| deref_dict(doc, names)).and_then(|names| names.get(b"Dests")) {
+            named.extend(name_tree::leaves(doc, tree));
+        }
+        if let Ok(dests) = catalog.get(b"Dests").and_then(|dests| deref_dict(doc, dests)) {
+            named.extend(dests.iter().map(|(name, dest)| (name.clone(), dest.clone())));
+        }
+
+        let pages = doc.get_pages().into_values().collect::<HashSet<_>>();
+        let mut seen = HashSet::new();
+        for (name, dest) in named {
+            // A name in both places means the same destination; the name tree's comes first.
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            let Some(dest) = explicit_destination(doc, &dest) else {
+                continue;
+            };
+            if !dest.first().and_then(|page| page.as_reference().ok()).is_some_and(|page| pages.contains(&page)) {
+                debug!("Dropping named destination {:?} to a missing page", String::from_utf8_lossy(&name));
+                continue;
+            }
+
+            let unique = self.0.insert(name.clone(), Object::Array(dest.to_vec()));
+            if unique != name {
+                debug!(
+                    "Renaming destination {:?} to {:?}",
+                    String::from_utf8_lossy(&name),
+                    String::from_utf8_lossy(&unique)
+                );
+            }
+        }
+    }
+
+    /// The merged `/Dests` name tree, or `None` if no document had named destinations.
+    pub(crate) fn into_name_tree(self) -> Option<Dictionary> {
+        self.0.into_dictionary()
+    }
+}
+
+/// Resolve an explicit or named destination to its explicit form, `[page /Fit ...]`.
+pub(crate) fn explicit_destination<'a>(doc: &'a Document, dest: &'a Object) -> Option<&'a [Object]> {
+    let (_, dest) = doc.dereference(dest).ok()?;
+
+    let target = match dest {
+        Object::Name(name) => {
+            let dests = doc.catalog().ok()?.get(b"Dests").ok()?;
+            deref_dict(doc, dests).ok()?.get(name).ok()?
+        }
+        Object::String(name, _) => {
+            let names = doc.catalog().ok()?.get(b"Names").ok()?;
+            let tree = deref_dict(doc, names).ok()?.get(b"Dests").ok()?;
+            let mut visited = HashSet::new();
+            lookup_name(doc, tree, name, &mut visited)?
+        }
+        _ => dest,
+    };
+
+    // Named destinations may map to a dictionary holding the array under `D`.
+    let (_, target) = doc.dereference(target).ok()?;
+    let array = match target {
+        Object::Dictionary(dict) => doc.dereference(dict.get(b"D").ok()?).ok()?.1,
+        _ => target,
+    };
+    array.as_array().ok().map(Vec::as_slice)
+}
+
+/// Resolve an explicit or named destination to its page object.
+pub(crate) fn destination_page(doc: &Document, dest: &Object) -> Option<ObjectId> {
+    explicit_destination(doc, dest)?.first()?.as_reference().ok()
+}
+
+/// Look up `key` in a name tree.
+fn lookup_name<'a>(
+    doc: &'a Document,
+    node: &'a Object,
+    key: &[u8],
+    visited: &mut HashSet<ObjectId>,
+) -> Option<&'a Object> {
+    if let Ok(id) = node.as_reference() {
+        if !visited.insert(id) {
+            return None;
+        }
+    }
+    let node = deref_dict(doc, node).ok()?;
+
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        for pair in names.chunks(2) {
+            if let [name, value] = pair {
+                if name.as_str().is_ok_and(|name| name == key) {
+                    return Some(value);
+                }
+            }
+        }
+    }
+
+    if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+        for kid in kids {
+            if let Some(value) = lookup_name(doc, kid, key, visited) {
+                return Some(value);
+            }
+        }
+    }
+
+    None
+}
+
+pub(crate) fn deref_dict<'a>(doc: &'a Document, object: &'a Object) -> lopdf::Result<&'a Dictionary> {
+    doc.dereference(object).and_then(|(_, object)| object.as_dict())
+}
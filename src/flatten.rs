@@ -0,0 +1,114 @@
+use lopdf::content::{Content, Operation};
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+use crate::destinations::deref_dict;
+use crate::stamp;
+
+/// Annotation flags that keep an annotation off the page: Hidden and NoView.
+const INVISIBLE_FLAGS: i64 = 2 | 32;
+
+/// Draw every visible annotation's normal appearance into its page's content, then remove the
+/// annotations and the interactive form, leaving a non-interactive copy.
+///
+/// Any annotation type with a normal (`/AP /N`) appearance stream is drawn: form field widgets,
+/// using the appearance for their current state (`/AS`), but also stamps, free text, shapes,
+/// ink and so on. Annotations without an appearance, such as links and popups, are just
+/// removed, as are hidden ones.
+///
+/// Returns the number of annotations drawn.
+pub(crate) fn flatten_annotations(doc: &mut Document) -> usize {
+    let mut drawn = 0;
+
+    for page_id in doc.get_pages().into_values() {
+        let annots = match doc.get_dictionary(page_id).and_then(|page| page.get(b"Annots")) {
+            Ok(annots) => match doc.dereference(annots) {
+                Ok((_, Object::Array(annots))) => annots.clone(),
+                _ => Vec::new(),
+            },
+            Err(_) => continue,
+        };
+
+        let mut operations = Vec::new();
+        for (index, annot) in annots.iter().enumerate() {
+            let Ok((_, Object::Dictionary(annot))) = doc.dereference(annot) else {
+                continue;
+            };
+            let Some((appearance_id, cm)) = appearance(doc, annot) else {
+                continue;
+            };
+
+            // The appearance must be a form XObject to be drawn with `Do`.
+            if let Ok(Object::Stream(stream)) = doc.get_object_mut(appearance_id) {
+                stream.dict.set("Type", "XObject");
+                stream.dict.set("Subtype", "Form");
+            }
+
+            let name = format!("MergePdfAnnot{}", index).into_bytes();
+            stamp::add_resource(doc, page_id, b"XObject", &name, appearance_id.into());
+            operations.extend([
+                Operation::new("q", vec![]),
+                Operation::new("cm", cm.iter().map(|&value| value.into()).collect()),
+                Operation::new("Do", vec![Object::Name(name)]),
+                Operation::new("Q", vec![]),
+            ]);
+            drawn += 1;
+        }
+
+        if !operations.is_empty() {
+            stamp::overlay(doc, page_id, Content { operations }.encode().unwrap_or_default());
+        }
+        if let Ok(page) = doc.get_dictionary_mut(page_id) {
+            page.remove(b"Annots");
+        }
+    }
+
+    if let Ok(catalog) = doc.catalog_mut() {
+        catalog.remove(b"AcroForm");
+    }
+    drawn
+}
+
+/// The annotation's normal appearance stream and the matrix that maps it onto `/Rect`, as
+/// described in "Appearance streams" in the PDF specification.
+fn appearance(doc: &Document, annot: &Dictionary) -> Option<(ObjectId, [f32; 6])> {
+    if annot.get(b"F").and_then(Object::as_i64).is_ok_and(|flags| flags & INVISIBLE_FLAGS != 0) {
+        return None;
+    }
+
+    let normal = deref_dict(doc, annot.get(b"AP").ok()?).ok()?.get(b"N").ok()?;
+    // Widgets such as check boxes have an appearance per state, picked by `/AS`.
+    let appearance_id = match doc.dereference(normal).ok()? {
+        (Some(id), Object::Stream(_)) => id,
+        (_, Object::Dictionary(states)) => {
+            let state = annot.get(b"AS").and_then(Object::as_name).ok()?;
+            states.get(state).and_then(Object::as_reference).ok()?
+        }
+        _ => return None,
+    };
+    let form = doc.get_object(appearance_id).ok()?.as_stream().ok()?;
+
+    let [rx0, ry0, rx1, ry1] = numbers::<4>(annot.get(b"Rect").ok()?)?;
+    let [bx0, by0, bx1, by1] = numbers::<4>(form.dict.get(b"BBox").ok()?)?;
+    let matrix = form.dict.get(b"Matrix").ok().and_then(numbers::<6>).unwrap_or([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    // The bounding box as transformed by the form's own matrix, which `Do` applies.
+    let corners = [(bx0, by0), (bx0, by1), (bx1, by0), (bx1, by1)].map(|(x, y)| {
+        let [a, b, c, d, e, f] = matrix;
+        (a * x + c * y + e, b * x + d * y + f)
+    });
+    let (tx0, tx1) = corners.iter().fold((f32::MAX, f32::MIN), |(lo, hi), &(x, _)| (lo.min(x), hi.max(x)));
+    let (ty0, ty1) = corners.iter().fold((f32::MAX, f32::MIN), |(lo, hi), &(_, y)| (lo.min(y), hi.max(y)));
+    if tx1 <= tx0 || ty1 <= ty0 {
+        return None;
+    }
+
+    let (rx0, rx1, ry0, ry1) = (rx0.min(rx1), rx0.max(rx1), ry0.min(ry1), ry0.max(ry1));
+    let (sx, sy) = ((rx1 - rx0) / (tx1 - tx0), (ry1 - ry0) / (ty1 - ty0));
+    Some((appearance_id, [sx, 0.0, 0.0, sy, rx0 - tx0 * sx, ry0 - ty0 * sy]))
+}
+
+/// An array of exactly `N` numbers.
+fn numbers<const N: usize>(object: &Object) -> Option<[f32; N]> {
+    let values = object.as_array().ok()?.iter().map(|value| value.as_float().ok()).collect::<Option<Vec<_>>>()?;
+    values.try_into().ok()
+}
@@ -0,0 +1,178 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use log::{info, warn};
+use lopdf::Document;
+use walkdir::WalkDir;
+
+use crate::sort::{self, SortOrder};
+use crate::MergeError;
+
+/// Which PDFs of a folder to take and in what order, as the command line picks the files of
+/// `--folder`.
+///
+/// Filters apply to file names. A file is taken if it ends in `.pdf`, matches `pattern` and
+/// `regex` if set, matches none of `exclude`, and was modified within `since` and `until`.
+#[derive(Debug, Clone, Default)]
+pub struct FolderOptions {
+    /// Only take files whose name matches this glob, e.g. `report_2024_*.pdf`.
+    pub pattern: Option<glob::Pattern>,
+    /// Only take files whose name matches this regular expression.
+    pub regex: Option<regex::Regex>,
+    /// Leave out files whose name matches any of these globs.
+    pub exclude: Vec<glob::Pattern>,
+    /// Only take files modified at or after this time.
+    pub since: Option<SystemTime>,
+    /// Only take files modified at or before this time.
+    pub until: Option<SystemTime>,
+    /// How many folder levels to look in, 1 being the folder itself; `None` looks in every
+    /// subfolder. Symlinked folders are never descended into.
+    pub max_depth: Option<usize>,
+    /// The order the files are returned in.
+    pub sort: SortOrder,
+}
+
+impl FolderOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set [`FolderOptions::pattern`].
+    pub fn pattern(mut self, pattern: Option<glob::Pattern>) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// Set [`FolderOptions::regex`].
+    pub fn regex(mut self, regex: Option<regex::Regex>) -> Self {
+        self.regex = regex;
+        self
+    }
+
+    /// Set [`FolderOptions::exclude`].
+    pub fn exclude(mut self, exclude: Vec<glob::Pattern>) -> Self {
+        self.exclude = exclude;
+        self
+    }
+
+    /// Set [`FolderOptions::since`].
+    pub fn since(mut self, since: Option<SystemTime>) -> Self {
+        self.since = since;
+        self
+    }
+
+    /// Set [`FolderOptions::until`].
+    pub fn until(mut self, until: Option<SystemTime>) -> Self {
+        self.until = until;
+        self
+    }
+
+    /// Set [`FolderOptions::max_depth`].
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Set [`FolderOptions::sort`].
+    pub fn sort(mut self, sort: SortOrder) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// The PDFs in `folder` and its subfolders that these options take, in `sort` order.
+    pub fn pdf_paths(&self, folder: impl AsRef<Path>) -> Result<Vec<PathBuf>, MergeError> {
+        let folder = folder.as_ref();
+        let mut walker = WalkDir::new(folder).follow_links(false);
+        if let Some(depth) = self.max_depth {
+            walker = walker.max_depth(depth);
+        }
+        let mut files = Vec::new();
+        for entry in walker {
+            let entry = entry.map_err(|err| MergeError::Load { path: folder.to_path_buf(), message: err.to_string() })?;
+            if entry.path().is_file() {
+                files.push(entry.into_path());
+            }
+        }
+        Ok(self.pick(files, |path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok()))
+    }
+
+    /// The PDFs among `files` these options take, in `sort` order, with `modified` telling
+    /// when each was last modified, if that can be told.
+    ///
+    /// This is how `pdf_paths` picks the files it finds, for files found some other way, such
+    /// as the entries of an archive.
+    pub fn pick(&self, files: Vec<PathBuf>, modified: impl Fn(&Path) -> Option<SystemTime>) -> Vec<PathBuf> {
+        let window = self.since.is_some() || self.until.is_some();
+        let mut excluded = 0;
+        let mut outside_window = 0;
+        let mut paths = Vec::new();
+        for path in files {
+            if !path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf")) {
+                continue;
+            }
+            let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            let matches = self.pattern.as_ref().is_none_or(|pattern| pattern.matches(&name))
+                && self.regex.as_ref().is_none_or(|regex| regex.is_match(&name));
+            if !matches {
+                info!("Skipping {:?}: doesn't match the name filter", path.display());
+                continue;
+            }
+            if self.exclude.iter().any(|pattern| pattern.matches(&name)) {
+                excluded += 1;
+                continue;
+            }
+            if window {
+                let Some(modified) = modified(&path) else {
+                    warn!("Can't tell when {:?} was modified; skipping it", path.display());
+                    outside_window += 1;
+                    continue;
+                };
+                let within = self.since.is_none_or(|since| modified >= since)
+                    && self.until.is_none_or(|until| modified <= until);
+                if !within {
+                    info!("Skipping {:?}: modified outside --since/--until", path.display());
+                    outside_window += 1;
+                    continue;
+                }
+            }
+            paths.push(path);
+        }
+
+        if !self.exclude.is_empty() {
+            info!("Excluded {} file(s).", excluded);
+        }
+        if window {
+            info!("{} file(s) modified within --since/--until, {} outside.", paths.len(), outside_window);
+        }
+        sort::sort_paths(&mut paths, self.sort);
+        paths
+    }
+}
+
+/// The total number of pages of the PDFs in `folder` that `options` take, loading each PDF only
+/// to count its pages.
+///
+/// This counts the files `FolderOptions::pdf_paths` finds, nothing more: unlike the command
+/// line's `count`, it doesn't read the folder's `.order` file, take a ZIP archive as `folder` or
+/// leave out files by page count.
+///
+/// ```
+/// use merge_pdf::{count_pages_in_folder, FolderOptions};
+///
+/// # fn main() -> Result<(), merge_pdf::MergeError> {
+/// let options = FolderOptions::new().pattern(Some(glob::Pattern::new("two_*.pdf").unwrap()));
+/// assert_eq!(count_pages_in_folder("tests/fixtures", &options)?, 2);
+/// # Ok(())
+/// # }
+/// ```
+pub fn count_pages_in_folder(folder: impl AsRef<Path>, options: &FolderOptions) -> Result<usize, MergeError> {
+    let mut total = 0;
+    for path in options.pdf_paths(folder)? {
+        let doc = Document::load(&path).map_err(|err| MergeError::Load { path: path.clone(), message: err.to_string() })?;
+        let pages = doc.get_pages().len();
+        info!("{:?}: {} page(s)", path.display(), pages);
+        total += pages;
+    }
+    Ok(total)
+}
@@ -0,0 +1,116 @@
+use std::collections::HashSet;
+
+use log::debug;
+use lopdf::{Dictionary, Document, Object};
+
+use crate::destinations::deref_dict;
+
+/// The interactive form fields of the merged documents, combined into one `/AcroForm`.
+#[derive(Default)]
+pub(crate) struct FormFields {
+    fields: Vec<Object>,
+    names: HashSet<String>,
+    resources: Dictionary,
+    default_appearance: Option<Object>,
+    need_appearances: bool,
+}
+
+impl FormFields {
+    /// Take over the form fields of `doc`, which must already be renumbered into the merged
+    /// document's ID space.
+    ///
+    /// A top-level field whose name is already taken by an earlier document is renamed to
+    /// `name_2`, `name_3` and so on, since fields with the same fully qualified name share one
+    /// value.
+    pub(crate) fn add(&mut self, doc: &mut Document) {
+        let Some(form) = doc
+            .catalog()
+            .and_then(|catalog| catalog.get(b"AcroForm"))
+            .and_then(|form| deref_dict(doc, form))
+            .ok()
+            .cloned()
+        else {
+            return;
+        };
+
+        if let Ok(resources) = form.get(b"DR").and_then(|resources| deref_dict(doc, resources)) {
+            self.add_resources(doc, resources);
+        }
+        if self.default_appearance.is_none() {
+            self.default_appearance = form.get(b"DA").ok().cloned();
+        }
+        self.need_appearances |= form.get(b"NeedAppearances").and_then(Object::as_bool).unwrap_or(false);
+
+        let fields = match form.get(b"Fields").and_then(|fields| doc.dereference(fields)) {
+            Ok((_, Object::Array(fields))) => fields.clone(),
+            _ => Vec::new(),
+        };
+        for field in fields {
+            if let Ok(id) = field.as_reference() {
+                if let Ok(field) = doc.get_dictionary_mut(id) {
+                    self.claim_name(field);
+                }
+            }
+            self.fields.push(field);
+        }
+    }
+
+    /// Record the field's name, renaming it first if an earlier field has it.
+    fn claim_name(&mut self, field: &mut Dictionary) {
+        let Ok(name) = field.get(b"T").and_then(lopdf::decode_text_string) else {
+            return;
+        };
+
+        let mut unique = name.clone();
+        let mut n = 1;
+        while self.names.contains(&unique) {
+            n += 1;
+            unique = format!("{}_{}", name, n);
+        }
+
+        if unique != name {
+            debug!("Renaming form field {:?} to {:?}", name, unique);
+            field.set("T", lopdf::text_string(&unique));
+        }
+        self.names.insert(unique);
+    }
+
+    /// Merge default resources such as fonts; the first document to define a name wins.
+    fn add_resources(&mut self, doc: &Document, resources: &Dictionary) {
+        for (category, entries) in resources.iter() {
+            let Ok(entries) = deref_dict(doc, entries) else {
+                continue;
+            };
+            if !self.resources.has(category) {
+                self.resources.set(category.clone(), Dictionary::new());
+            }
+            if let Ok(merged) = self.resources.get_mut(category).and_then(Object::as_dict_mut) {
+                for (name, value) in entries.iter() {
+                    if !merged.has(name) {
+                        merged.set(name.clone(), value.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// The merged `/AcroForm` dictionary, or `None` if no document had form fields.
+    pub(crate) fn into_dictionary(self) -> Option<Dictionary> {
+        if self.fields.is_empty() {
+            return None;
+        }
+
+        let mut form = Dictionary::new();
+        form.set("Fields", self.fields);
+        if !self.resources.is_empty() {
+            form.set("DR", self.resources);
+        }
+        if let Some(default_appearance) = self.default_appearance {
+            form.set("DA", default_appearance);
+        }
+        if self.need_appearances {
+            form.set("NeedAppearances", true);
+        }
+        Some(form)
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use lopdf::{Document, Object, ObjectId};
+
+/// Whether `doc` has JavaScript actions or a document-level JavaScript name tree.
+pub fn has_javascript(doc: &Document) -> bool {
+    doc.objects.values().any(contains_javascript)
+}
+
+fn contains_javascript(object: &Object) -> bool {
+    match object {
+        Object::Dictionary(dict) => {
+            is_javascript_action(object)
+                || dict.has(b"JavaScript")
+                || dict.iter().any(|(_, value)| contains_javascript(value))
+        }
+        Object::Array(items) => items.iter().any(contains_javascript),
+        Object::Stream(stream) => stream.dict.iter().any(|(_, value)| contains_javascript(value)),
+        _ => false,
+    }
+}
+
+fn is_javascript_action(object: &Object) -> bool {
+    object
+        .as_dict()
+        .and_then(|dict| dict.get(b"S"))
+        .and_then(Object::as_name)
+        .is_ok_and(|name| name == b"JavaScript")
+}
+
+/// Remove every JavaScript action from `doc`, wherever it is triggered from (`OpenAction`,
+/// `A`, `AA` or a `Next` chain), along with the document-level JavaScript name tree.
+///
+/// Returns the number of references removed.
+pub(crate) fn strip_javascript(doc: &mut Document) -> usize {
+    let actions = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| is_javascript_action(object))
+        .map(|(id, _)| *id)
+        .collect::<HashSet<_>>();
+    for id in &actions {
+        doc.objects.remove(id);
+    }
+
+    let mut removed = 0;
+    for object in doc.objects.values_mut() {
+        removed += strip(object, &actions);
+    }
+    removed
+}
+
+fn strip(object: &mut Object, actions: &HashSet<ObjectId>) -> usize {
+    let is_javascript = |value: &Object| match value {
+        Object::Reference(id) => actions.contains(id),
+        _ => is_javascript_action(value),
+    };
+
+    let mut removed = 0;
+    match object {
+        Object::Dictionary(dict) => {
+            let keys = dict
+                .iter()
+                .filter(|(key, value)| key.as_slice() == b"JavaScript" || is_javascript(value))
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            for key in keys {
+                dict.remove(&key);
+                removed += 1;
+            }
+            for (_, value) in dict.iter_mut() {
+                removed += strip(value, actions);
+            }
+        }
+        Object::Array(items) => {
+            let before = items.len();
+            items.retain(|item| !is_javascript(item));
+            removed += before - items.len();
+            for item in items {
+                removed += strip(item, actions);
+            }
+        }
+        Object::Stream(stream) => {
+            let mut dict = Object::Dictionary(std::mem::take(&mut stream.dict));
+            removed += strip(&mut dict, actions);
+            if let Object::Dictionary(dict) = dict {
+                stream.dict = dict;
+            }
+        }
+        _ => {}
+    }
+    removed
+}
@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+
+use crate::destinations::deref_dict;
+
+/// The optional content groups (layers) of the merged documents, combined into one
+/// `/OCProperties`.
+///
+/// Every document's groups are kept, with what its default configuration says about them:
+/// which are off, the order and nesting they are listed in, and which are locked or act as
+/// radio buttons. Alternate configurations (`/Configs`) are dropped, as each only covers the
+/// groups of its own document.
+#[derive(Default)]
+pub(crate) struct OptionalContent {
+    groups: Vec<Object>,
+    seen: HashSet<ObjectId>,
+    order: Vec<Object>,
+    off: Vec<Object>,
+    locked: Vec<Object>,
+    radio_button_groups: Vec<Object>,
+    usage_applications: Vec<Object>,
+    name: Option<Object>,
+}
+
+impl OptionalContent {
+    /// Take over the optional content groups of `doc`, which must already be renumbered into
+    /// the merged document's ID space.
+    pub(crate) fn add(&mut self, doc: &mut Document) {
+        let Ok(properties_ref) = doc.catalog().and_then(|catalog| catalog.get(b"OCProperties")).cloned() else {
+            return;
+        };
+        let Ok(properties) = deref_dict(doc, &properties_ref).cloned() else {
+            return;
+        };
+        let config = properties
+            .get(b"D")
+            .and_then(|config| deref_dict(doc, config))
+            .cloned()
+            .unwrap_or_default();
+
+        let groups = array(doc, properties.get(b"OCGs"));
+        // The merged configuration turns groups on unless it lists them as off, so a group this
+        // one leaves off by its base state has to be listed.
+        if config.get(b"BaseState").and_then(Object::as_name).ok() == Some(b"OFF".as_slice()) {
+            let on = array(doc, config.get(b"ON"));
+            self.off.extend(groups.iter().filter(|group| !on.contains(group)).cloned());
+        } else {
+            self.off.extend(array(doc, config.get(b"OFF")));
+        }
+        self.order.extend(array(doc, config.get(b"Order")));
+        self.locked.extend(array(doc, config.get(b"Locked")));
+        self.radio_button_groups.extend(array(doc, config.get(b"RBGroups")));
+        self.usage_applications.extend(array(doc, config.get(b"AS")));
+        if self.name.is_none() {
+            self.name = config.get(b"Name").ok().cloned();
+        }
+        for group in groups {
+            if group.as_reference().map_or(true, |id| self.seen.insert(id)) {
+                self.groups.push(group);
+            }
+        }
+
+        // The merged properties take the place of this document's.
+        if let Ok(id) = properties_ref.as_reference() {
+            doc.objects.remove(&id);
+        }
+    }
+
+    /// The merged `/OCProperties`, or `None` if no document had optional content.
+    pub(crate) fn into_properties(self) -> Option<Dictionary> {
+        if self.groups.is_empty() {
+            return None;
+        }
+
+        let mut config = Dictionary::new();
+        if let Some(name) = self.name {
+            config.set("Name", name);
+        }
+        for (key, entries) in [
+            ("Order", self.order),
+            ("OFF", self.off),
+            ("Locked", self.locked),
+            ("RBGroups", self.radio_button_groups),
+            ("AS", self.usage_applications),
+        ] {
+            if !entries.is_empty() {
+                config.set(key, entries);
+            }
+        }
+        Some(dictionary! {
+            "OCGs" => self.groups,
+            "D" => config,
+        })
+    }
+}
+
+/// The array `value` is or refers to, or an empty one.
+fn array(doc: &Document, value: lopdf::Result<&Object>) -> Vec<Object> {
+    match value.and_then(|value| doc.dereference(value)) {
+        Ok((_, Object::Array(items))) => items.clone(),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,2809 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use log::{debug, info, warn};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Bookmark};
+
+mod annotations;
+mod attachments;
+mod dedup;
+mod flatten;
+mod folder;
+mod destinations;
+mod forms;
+mod javascript;
+mod layers;
+mod linearize;
+mod name_tree;
+mod nup;
+mod outline;
+mod page_labels;
+mod placeholder;
+mod pretty;
+pub mod pages;
+mod renumber;
+mod resize;
+mod resources;
+pub mod sort;
+mod stamp;
+mod structure;
+mod toc;
+mod validate;
+mod watermark;
+mod xmp;
+
+pub use folder::{count_pages_in_folder, FolderOptions};
+pub use javascript::has_javascript;
+pub use nup::NUp;
+pub use outline::{outline_tree, OutlineItem};
+pub use pages::{page_ids, Orientation, PageSelection, Rotation};
+pub use placeholder::placeholder_document;
+pub use resize::{PageSize, PaperSize};
+pub use watermark::{Watermark, WatermarkContent, WatermarkImage};
+pub use sort::SortOrder;
+pub use stamp::PageNumberPosition;
+pub use validate::{dangling_references, DanglingReference};
+pub use xmp::XmpMetadata;
+
+/// Reasons a merge can fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    /// No documents were given to merge.
+    EmptyInput,
+    /// None of the documents has a "Pages" root.
+    NoPagesRoot,
+    /// None of the documents has a "Catalog" root.
+    NoCatalogRoot,
+    /// Interleaving was requested for this many documents instead of two.
+    InterleaveNeedsTwo(usize),
+    /// With `MergeOptions::strict`, this many references in the merged PDF point at objects
+    /// it doesn't have.
+    DanglingReferences(usize),
+    /// A file given to `MergeOptions::merge`, or a folder or file `count_pages_in_folder`
+    /// looked at, could not be read.
+    Load { path: PathBuf, message: String },
+    /// The merged PDF would need more object numbers than fit in the 32 bits lopdf uses.
+    TooManyObjects,
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::EmptyInput => write!(f, "no documents to merge"),
+            MergeError::NoPagesRoot => write!(f, "Pages root not found"),
+            MergeError::NoCatalogRoot => write!(f, "Catalog root not found"),
+            MergeError::InterleaveNeedsTwo(count) => {
+                write!(f, "interleaving takes exactly two documents, got {}", count)
+            }
+            MergeError::DanglingReferences(count) => {
+                write!(f, "{} reference(s) point at objects missing from the merged PDF", count)
+            }
+            MergeError::Load { path, message } => write!(f, "failed to load {:?}: {}", path.display(), message),
+            MergeError::TooManyObjects => write!(f, "the merged PDF has too many objects to number"),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+/// PDF versions the merged document can declare.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PdfVersion {
+    #[value(name = "1.3")]
+    V1_3,
+    #[value(name = "1.4")]
+    V1_4,
+    #[default]
+    #[value(name = "1.5")]
+    V1_5,
+    #[value(name = "1.6")]
+    V1_6,
+    #[value(name = "1.7")]
+    V1_7,
+    #[value(name = "2.0")]
+    V2_0,
+}
+
+impl PdfVersion {
+    /// The version a file header declares, such as `1.7`, or `None` if it isn't one of these.
+    pub fn from_header(version: &str) -> Option<Self> {
+        <PdfVersion as clap::ValueEnum>::from_str(version.trim(), false).ok()
+    }
+
+    /// The version as written in the file header, e.g. `1.5`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PdfVersion::V1_3 => "1.3",
+            PdfVersion::V1_4 => "1.4",
+            PdfVersion::V1_5 => "1.5",
+            PdfVersion::V1_6 => "1.6",
+            PdfVersion::V1_7 => "1.7",
+            PdfVersion::V2_0 => "2.0",
+        }
+    }
+}
+
+impl fmt::Display for PdfVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Pages inserted between consecutive source documents.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Separator {
+    /// An empty page the size of the preceding page.
+    Blank,
+}
+
+/// How a viewer first shows the merged PDF.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenAction {
+    /// Open at the first page, zoomed to fit the window.
+    FitPage,
+    /// Open at the first page, at the viewer's zoom.
+    FirstPage,
+    /// Open with the bookmarks panel showing.
+    Outline,
+}
+
+/// A function the merge calls with each merged page; see [`MergeOptions::page_hook`].
+#[derive(Clone)]
+pub struct PageHook(Arc<Mutex<PageHookFn>>);
+
+type PageHookFn = dyn FnMut(usize, &mut Dictionary) + Send;
+
+impl PageHook {
+    /// Wrap `hook`, which is shared by clones of the options it is set in.
+    pub fn new(hook: impl FnMut(usize, &mut Dictionary) + Send + 'static) -> Self {
+        PageHook(Arc::new(Mutex::new(hook)))
+    }
+
+    fn call(&self, index: usize, page: &mut Dictionary) {
+        // A hook that panicked on an earlier merge is still called.
+        let mut hook = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        hook(index, page);
+    }
+}
+
+impl fmt::Debug for PageHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PageHook(..)")
+    }
+}
+
+/// How the pages of two documents are interleaved, e.g. the front and back sides of a scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interleave {
+    /// A1, B1, A2, B2, ...
+    Alternate,
+    /// A1, Bn, A2, Bn-1, ..., for back sides scanned from the last page to the first.
+    ReverseSecond,
+}
+
+/// How each source document's top-level bookmark is titled.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BookmarkMode {
+    /// The title given with the document, usually its file name.
+    #[default]
+    Filename,
+    /// `Page_N`, N being the position of the document's first page in the output.
+    Sequential,
+    /// The document's own `/Info` title, or the title given with it if it has none.
+    Title,
+    /// No bookmarks; the output has no outline at all.
+    None,
+}
+
+/// Document properties written to the merged PDF's `/Info` dictionary.
+///
+/// Fields left as `None` are omitted.
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+}
+
+impl Metadata {
+    fn to_dictionary(&self) -> Option<Dictionary> {
+        let mut info = Dictionary::new();
+        let entries = [
+            ("Title", &self.title),
+            ("Author", &self.author),
+            ("Subject", &self.subject),
+            ("Keywords", &self.keywords),
+        ];
+
+        for (key, value) in entries {
+            if let Some(value) = value {
+                info.set(key, lopdf::text_string(value));
+            }
+        }
+
+        (!info.is_empty()).then_some(info)
+    }
+}
+
+/// Settings that control how documents are merged.
+#[derive(Debug, Clone)]
+pub struct MergeOptions {
+    /// The version declared in the merged PDF's header.
+    pub pdf_version: PdfVersion,
+    /// Declare the highest version of the merged documents instead of `pdf_version`, so
+    /// features of newer inputs aren't downgraded. Either way, inputs newer than the merged
+    /// PDF's version are logged with a warning.
+    pub match_input_version: bool,
+    /// How to title the bookmark added per source document, under which its own outline is
+    /// kept; `BookmarkMode::None` adds no bookmarks and keeps no outlines.
+    pub bookmarks: BookmarkMode,
+    /// Document properties for the merged PDF.
+    pub metadata: Metadata,
+    /// The natural language of the merged PDF, such as `en-US`, set as the catalog's `/Lang`.
+    /// Otherwise the last document's language, if any, is kept.
+    pub lang: Option<String>,
+    /// Share identical fonts and images between source documents instead of keeping a copy each.
+    pub dedup: bool,
+    /// Drop each page that is identical to the page right before it in the merged order, such
+    /// as a repeated blank or boilerplate page. Only exact matches of the content and resources
+    /// are dropped; bookmarks and links to a dropped page go to the page it repeats.
+    pub dedup_pages: bool,
+    /// A page to insert between consecutive documents, if any.
+    pub separator: Option<Separator>,
+    /// The size of the separator and table of contents pages. Without it separators are the
+    /// size of the page before them, and the table of contents is Letter.
+    pub generated_page_size: Option<PaperSize>,
+    /// Stamp "Page N of M" on every page, at this position.
+    pub page_numbers: Option<PageNumberPosition>,
+    /// Combine the interactive form fields of every document into the merged form, renaming
+    /// fields whose names clash. Otherwise only the last document's form is kept.
+    pub keep_forms: bool,
+    /// Combine the files attached to every document into the merged PDF's attachments,
+    /// renaming attachments whose names clash. Otherwise only the last document's are kept.
+    pub keep_attachments: bool,
+    /// Keep the `ViewerPreferences`, `PageMode` and `PageLayout` of the first document's
+    /// catalog. Otherwise the merged catalog only has `Type`, `Pages`, `Outlines`,
+    /// `PageLabels`, `Names`, `AcroForm`, `Metadata` and `Lang`, the combined `OCProperties`
+    /// (optional content groups, or layers) of every document, plus what `open_action` and
+    /// `retain_structure_tree` set; other entries of the source catalogs, such as `OpenAction`
+    /// or `URI`, are dropped. When appending, the base document's catalog is kept whole either
+    /// way.
+    pub keep_first_catalog_metadata: bool,
+    /// Combine the structure trees of tagged documents into one, so the merged PDF keeps their
+    /// logical structure and reading order for assistive technology, and mark it as tagged.
+    /// Otherwise structure trees are dropped.
+    pub retain_structure_tree: bool,
+    /// Alternate the pages of exactly two documents instead of appending one after the other.
+    /// Separator pages are not inserted when interleaving.
+    pub interleave: Option<Interleave>,
+    /// Start the merged PDF with a table of contents listing each document and the page it
+    /// starts on.
+    pub toc: bool,
+    /// Turn every merged page by this much, on top of the page's own rotation.
+    pub rotate: Rotation,
+    /// Turn pages displayed the other way round a quarter turn clockwise, after `rotate`, so
+    /// every page has this orientation.
+    pub auto_rotate: Option<Orientation>,
+    /// Flate-compress streams that aren't compressed yet, with zlib's default level. lopdf
+    /// offers no way to choose the level, and never writes object streams.
+    pub compress: bool,
+    /// Remove JavaScript actions and document-level scripts from every document.
+    pub strip_js: bool,
+    /// Resize every page to this size, scaling its content to fit.
+    pub page_size: Option<PageSize>,
+    /// Draw this over every page, including separators and the table of contents.
+    pub watermark: Option<Watermark>,
+    /// Rename page resources whose names collide, so that a name such as `/F1` stands for the
+    /// same font, image or other resource on every merged page, rewriting the content of the
+    /// pages with renamed resources. Resources can then be shared or moved up the page tree
+    /// without one document's `/F1` hiding another's. Pages whose content can't be parsed,
+    /// such as pages with inline images, are left as they are.
+    pub normalize_resources: bool,
+    /// Place several pages on each sheet, in a grid, as the last change to the merged pages:
+    /// after `page_size`, `watermark` and `page_numbers`, which apply to the pages themselves.
+    /// Bookmarks and links to a page go to its sheet; page labels are dropped.
+    pub nup: Option<NUp>,
+    /// Set how a viewer first shows the merged PDF, overriding the `PageMode` that
+    /// `keep_first_catalog_metadata` keeps.
+    pub open_action: Option<OpenAction>,
+    /// Replace the XMP metadata stream. Otherwise the merged PDF has the XMP of the last
+    /// document, if any.
+    pub xmp: Option<XmpMetadata>,
+    /// Draw annotations and form fields into the page content and remove them, leaving a
+    /// non-interactive copy. Annotations without an appearance stream, such as links, are lost.
+    pub flatten: bool,
+    /// The order `merge` loads its paths in. `None` keeps the order they are given in.
+    pub sort: Option<SortOrder>,
+    /// Fail with `MergeError::DanglingReferences` if the merged PDF references objects it
+    /// doesn't have, instead of only logging a warning.
+    pub strict: bool,
+    /// Called with the index of each page in the merged page order, from 0, and its page
+    /// dictionary, in page order, to change the page before it is added to the merged page tree.
+    ///
+    /// Separator and table of contents pages are passed too, but not pages
+    /// `dedup_pages` drops. When the hook runs, every page has already been made
+    /// self-contained, with its inherited attributes copied in and `rotate` and `auto_rotate`
+    /// applied, and every reference in it already points at the object of the merged document
+    /// it stands for. The merged document is renumbered afterwards, so the object IDs the hook
+    /// sees are not those of the returned document, but references it copies or moves between
+    /// entries stay valid; references to IDs it makes up don't. `Parent` is set after the
+    /// hook, and `flatten`, `page_size`, `watermark`, `page_numbers` and `nup` are applied
+    /// after it.
+    pub page_hook: Option<PageHook>,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            pdf_version: PdfVersion::default(),
+            match_input_version: false,
+            bookmarks: BookmarkMode::default(),
+            metadata: Metadata::default(),
+            lang: None,
+            dedup: false,
+            dedup_pages: false,
+            separator: None,
+            generated_page_size: None,
+            page_numbers: None,
+            keep_forms: false,
+            keep_attachments: false,
+            keep_first_catalog_metadata: false,
+            retain_structure_tree: false,
+            interleave: None,
+            toc: false,
+            rotate: Rotation::default(),
+            auto_rotate: None,
+            compress: true,
+            strip_js: false,
+            page_size: None,
+            watermark: None,
+            normalize_resources: false,
+            nup: None,
+            open_action: None,
+            xmp: None,
+            flatten: false,
+            sort: None,
+            strict: false,
+            page_hook: None,
+        }
+    }
+}
+
+/// Builder-style setters, so options can be configured in one expression:
+///
+/// ```
+/// use merge_pdf::{BookmarkMode, MergeOptions, Metadata, PdfVersion};
+///
+/// let options = MergeOptions::new()
+///     .pdf_version(PdfVersion::V1_7)
+///     .bookmarks(BookmarkMode::None)
+///     .compress(false)
+///     .metadata(Metadata { title: Some("Q4 report".into()), ..Metadata::default() });
+///
+/// assert_eq!(options.pdf_version, PdfVersion::V1_7);
+/// assert_eq!(options.bookmarks, BookmarkMode::None);
+/// assert!(!options.compress);
+/// ```
+impl MergeOptions {
+    /// The default options, the same as `MergeOptions::default()`.
+    pub fn new() -> Self {
+        MergeOptions::default()
+    }
+
+    /// Set [`MergeOptions::pdf_version`].
+    pub fn pdf_version(mut self, pdf_version: PdfVersion) -> Self {
+        self.pdf_version = pdf_version;
+        self
+    }
+
+    /// Set [`MergeOptions::match_input_version`].
+    pub fn match_input_version(mut self, match_input_version: bool) -> Self {
+        self.match_input_version = match_input_version;
+        self
+    }
+
+    /// Set [`MergeOptions::bookmarks`].
+    pub fn bookmarks(mut self, bookmarks: BookmarkMode) -> Self {
+        self.bookmarks = bookmarks;
+        self
+    }
+
+    /// Set [`MergeOptions::metadata`].
+    pub fn metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Set [`MergeOptions::lang`].
+    pub fn lang(mut self, lang: Option<String>) -> Self {
+        self.lang = lang;
+        self
+    }
+
+    /// Set [`MergeOptions::dedup`].
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Set [`MergeOptions::dedup_pages`].
+    pub fn dedup_pages(mut self, dedup_pages: bool) -> Self {
+        self.dedup_pages = dedup_pages;
+        self
+    }
+
+    /// Set [`MergeOptions::separator`].
+    pub fn separator(mut self, separator: Option<Separator>) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// Set [`MergeOptions::generated_page_size`].
+    pub fn generated_page_size(mut self, generated_page_size: Option<PaperSize>) -> Self {
+        self.generated_page_size = generated_page_size;
+        self
+    }
+
+    /// Set [`MergeOptions::page_numbers`].
+    pub fn page_numbers(mut self, page_numbers: Option<PageNumberPosition>) -> Self {
+        self.page_numbers = page_numbers;
+        self
+    }
+
+    /// Set [`MergeOptions::keep_forms`].
+    pub fn keep_forms(mut self, keep_forms: bool) -> Self {
+        self.keep_forms = keep_forms;
+        self
+    }
+
+    /// Set [`MergeOptions::keep_attachments`].
+    pub fn keep_attachments(mut self, keep_attachments: bool) -> Self {
+        self.keep_attachments = keep_attachments;
+        self
+    }
+
+    /// Set [`MergeOptions::keep_first_catalog_metadata`].
+    pub fn keep_first_catalog_metadata(mut self, keep_first_catalog_metadata: bool) -> Self {
+        self.keep_first_catalog_metadata = keep_first_catalog_metadata;
+        self
+    }
+
+    /// Set [`MergeOptions::retain_structure_tree`].
+    pub fn retain_structure_tree(mut self, retain_structure_tree: bool) -> Self {
+        self.retain_structure_tree = retain_structure_tree;
+        self
+    }
+
+    /// Set [`MergeOptions::interleave`].
+    pub fn interleave(mut self, interleave: Option<Interleave>) -> Self {
+        self.interleave = interleave;
+        self
+    }
+
+    /// Set [`MergeOptions::toc`].
+    pub fn toc(mut self, toc: bool) -> Self {
+        self.toc = toc;
+        self
+    }
+
+    /// Set [`MergeOptions::rotate`].
+    pub fn rotate(mut self, rotate: Rotation) -> Self {
+        self.rotate = rotate;
+        self
+    }
+
+    /// Set [`MergeOptions::auto_rotate`].
+    pub fn auto_rotate(mut self, auto_rotate: Option<Orientation>) -> Self {
+        self.auto_rotate = auto_rotate;
+        self
+    }
+
+    /// Set [`MergeOptions::compress`].
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Set [`MergeOptions::strip_js`].
+    pub fn strip_js(mut self, strip_js: bool) -> Self {
+        self.strip_js = strip_js;
+        self
+    }
+
+    /// Set [`MergeOptions::page_size`].
+    pub fn page_size(mut self, page_size: Option<PageSize>) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Set [`MergeOptions::watermark`].
+    pub fn watermark(mut self, watermark: Option<Watermark>) -> Self {
+        self.watermark = watermark;
+        self
+    }
+
+    /// Set [`MergeOptions::normalize_resources`].
+    pub fn normalize_resources(mut self, normalize_resources: bool) -> Self {
+        self.normalize_resources = normalize_resources;
+        self
+    }
+
+    /// Set [`MergeOptions::nup`].
+    pub fn nup(mut self, nup: Option<NUp>) -> Self {
+        self.nup = nup;
+        self
+    }
+
+    /// Set [`MergeOptions::open_action`].
+    pub fn open_action(mut self, open_action: Option<OpenAction>) -> Self {
+        self.open_action = open_action;
+        self
+    }
+
+    /// Set [`MergeOptions::xmp`].
+    pub fn xmp(mut self, xmp: Option<XmpMetadata>) -> Self {
+        self.xmp = xmp;
+        self
+    }
+
+    /// Set [`MergeOptions::flatten`].
+    pub fn flatten(mut self, flatten: bool) -> Self {
+        self.flatten = flatten;
+        self
+    }
+
+    /// Set [`MergeOptions::sort`].
+    pub fn sort(mut self, sort: Option<SortOrder>) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// Set [`MergeOptions::strict`].
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Set [`MergeOptions::page_hook`] to call `hook`.
+    pub fn page_hook(mut self, hook: impl FnMut(usize, &mut Dictionary) + Send + 'static) -> Self {
+        self.page_hook = Some(PageHook::new(hook));
+        self
+    }
+
+    /// Load the PDFs at `paths` and merge them with these options, one bookmark per file
+    /// titled with its name.
+    ///
+    /// The files are loaded one at a time, in `self.sort` order if set, and the first that
+    /// fails to load ends the merge with `MergeError::Load`. Encrypted files need
+    /// their password, so load those yourself and use `merge_pdf`.
+    ///
+    /// ```
+    /// use merge_pdf::MergeOptions;
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let merged = MergeOptions::new()
+    ///     .toc(true)
+    ///     .merge(["tests/fixtures/two_pages.pdf", "tests/fixtures/outline.pdf"])?;
+    /// assert_eq!(merge_pdf::page_ids(&merged).len(), 1 + 2 + 3);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn merge<P: AsRef<Path>>(&self, paths: impl IntoIterator<Item = P>) -> Result<Document, MergeError> {
+        let mut paths = paths.into_iter().map(|path| path.as_ref().to_path_buf()).collect::<Vec<_>>();
+        if let Some(order) = self.sort {
+            sort::sort_paths(&mut paths, order);
+        }
+        if paths.is_empty() {
+            return Err(MergeError::EmptyInput);
+        }
+
+        // A file that can't be loaded stops the merge before any later file is loaded.
+        let documents = paths.iter().map(|path| match Document::load(path) {
+            Ok(doc) => {
+                let title = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+                Ok((title, doc))
+            }
+            Err(err) => Err(MergeError::Load { path: path.clone(), message: err.to_string() }),
+        });
+        merge_into(None, documents, self)
+    }
+}
+
+/// The object number after `count` objects numbered from `next_id`, or
+/// `MergeError::TooManyObjects` if they don't all fit below `u32::MAX`.
+fn reserve_ids(next_id: u32, count: usize) -> Result<u32, MergeError> {
+    u32::try_from(count)
+        .ok()
+        .and_then(|count| next_id.checked_add(count))
+        .ok_or(MergeError::TooManyObjects)
+}
+
+fn blank_page(media_box: Object) -> Object {
+    Object::Dictionary(dictionary! {
+        "Type" => "Page",
+        "MediaBox" => media_box,
+        "Resources" => Dictionary::new(),
+    })
+}
+
+/// A self-contained copy of the page, turned by `options.rotate` on top of its own rotation,
+/// then to `options.auto_rotate`'s orientation.
+fn rotated_page(doc: &Document, page_id: ObjectId, options: &MergeOptions) -> Dictionary {
+    let mut page = pages::with_inherited_attributes(doc, page_id);
+    options.rotate.apply(&mut page);
+    if let Some(orientation) = options.auto_rotate {
+        if orientation.apply(&mut page) {
+            debug!("Turned page {:?} to {:?}", page_id, orientation);
+        }
+    }
+    page
+}
+
+/// The document's `/Info` title, unless it has none or it is blank.
+fn info_title(doc: &Document) -> Option<String> {
+    let info = doc.trailer.get(b"Info").and_then(|info| destinations::deref_dict(doc, info)).ok()?;
+    let title = lopdf::decode_text_string(doc.dereference(info.get(b"Title").ok()?).ok()?.1).ok()?;
+    Some(title.trim().to_string()).filter(|title| !title.is_empty())
+}
+
+/// Alternate the pages of `first` and `second`; whatever is left of the longer one goes last.
+fn interleave_pages(first: &[ObjectId], second: &[ObjectId], interleave: Interleave) -> Vec<ObjectId> {
+    let mut second = second.to_vec();
+    if interleave == Interleave::ReverseSecond {
+        second.reverse();
+    }
+
+    let mut pages = Vec::with_capacity(first.len() + second.len());
+    for i in 0..first.len().max(second.len()) {
+        pages.extend(first.get(i));
+        pages.extend(second.get(i));
+    }
+    pages
+}
+
+/// Merge `documents` into a single new PDF, in the order given.
+///
+/// Each source document gets a top-level bookmark pointing at its first page, labeled with the
+/// title paired with it (or `Page_N`, N being the page's position in the output, if the title
+/// is empty), with the document's own outline
+/// (if any) nested underneath it. `options.bookmarks` can title the bookmarks differently, or
+/// leave the output with no outline at all.
+///
+/// Documents with an empty page tree are skipped with a warning; if no document has pages the
+/// merge fails with `MergeError::EmptyInput`.
+pub fn merge_pdf(documents: Vec<(String, Document)>, options: &MergeOptions) -> Result<Document, MergeError>
+{
+    if documents.is_empty() {
+        return Err(MergeError::EmptyInput);
+    }
+    merge_into(None, documents.into_iter().map(Ok), options)
+}
+
+/// Append `documents` to the end of `base`, as `merge_pdf` would merge them.
+///
+/// The base document keeps its catalog, its document properties (with any fields set in
+/// `options.metadata` replaced) and its outline, at the top level ahead of the bookmarks for
+/// the appended documents. Like `merge_pdf_streaming`, the documents are taken one at a time.
+pub fn append_pdf<I>(base: Document, documents: I, options: &MergeOptions) -> Result<Document, MergeError>
+where
+    I: IntoIterator<Item = (String, Document)>,
+{
+    merge_into(Some(base), documents.into_iter().map(Ok), options)
+}
+
+/// Merge `documents` as `merge_pdf` does, taking them one at a time.
+///
+/// Each document's objects are moved into the merged document as soon as it is taken, so with
+/// an iterator that loads the documents lazily only one input is held in memory alongside the
+/// output, rather than every input at once.
+pub fn merge_pdf_streaming<I>(documents: I, options: &MergeOptions) -> Result<Document, MergeError>
+where
+    I: IntoIterator<Item = (String, Document)>,
+{
+    merge_into(None, documents.into_iter().map(Ok), options)
+}
+
+/// Split `doc` into parts of `chunk` pages each, in page order; the last part may be shorter.
+///
+/// Each part is built the way `merge_pdf` builds a merged document, so it only holds the
+/// objects its own pages use. Parts keep the source's PDF version but not its outline.
+pub fn split_pdf(doc: &Document, chunk: usize) -> Result<Vec<Document>, MergeError> {
+    let options = MergeOptions { bookmarks: BookmarkMode::None, ..MergeOptions::default() };
+    let page_ids = pages::page_ids(doc);
+    // Page labels are read once, rather than from the whole document for every part.
+    let labels = page_labels::page_labels(doc);
+
+    let mut parts = Vec::new();
+    for (index, group) in page_ids.chunks(chunk.max(1)).enumerate() {
+        let mut part = pages::extract_pages(doc, group);
+        if let Some(labels) = &labels {
+            let first = index * chunk.max(1);
+            let tree = page_labels::number_tree(labels[first..first + group.len()].iter().cloned());
+            if let Ok(catalog) = part.catalog_mut() {
+                catalog.set("PageLabels", tree);
+            }
+        }
+
+        let mut part = merge_pdf(vec![(String::new(), part)], &options)?;
+        part.version = doc.version.clone();
+        parts.push(part);
+    }
+    Ok(parts)
+}
+
+/// Write `doc` to `target` as a linearized ("fast web view") PDF, which a viewer can show the
+/// first page of before the rest of the file has downloaded.
+///
+/// The hint stream only has the page offset and shared object hint tables. Unlike
+/// `Document::save_to`, this doesn't write objects nothing references differently, nor change
+/// `doc`; references to missing objects are written as `null`.
+pub fn save_linearized<W: std::io::Write>(doc: &Document, target: &mut W) -> std::io::Result<()> {
+    linearize::save_linearized(doc, target)
+}
+
+/// Write `doc` to `target` laid out for reading and `grep`ping while debugging: streams
+/// uncompressed, each object in a section of its own with a comment naming its type, and a
+/// classic cross-reference table instead of object or cross-reference streams.
+///
+/// Unlike `Document::save_to`, this doesn't change `doc`.
+pub fn save_pretty<W: std::io::Write>(doc: &Document, target: &mut W) -> std::io::Result<()> {
+    pretty::save_pretty(doc, target)
+}
+
+/// The catalog entries the merged catalog takes from the last document's catalog, or builds
+/// itself; the merge drops any other.
+const MERGED_CATALOG_KEYS: [&[u8]; 8] =
+    [b"Type", b"Pages", b"Outlines", b"PageLabels", b"Names", b"AcroForm", b"Metadata", b"Lang"];
+
+/// The catalog entries that `MergeOptions::keep_first_catalog_metadata` keeps from the first
+/// document's catalog.
+const VIEWER_SETTINGS: [&[u8]; 3] = [b"ViewerPreferences", b"PageMode", b"PageLayout"];
+
+/// The `VIEWER_SETTINGS` entries of `catalog`.
+fn viewer_settings(catalog: &Dictionary) -> Dictionary {
+    let mut settings = Dictionary::new();
+    for key in VIEWER_SETTINGS {
+        if let Ok(value) = catalog.get(key) {
+            settings.set(key, value.clone());
+        }
+    }
+    settings
+}
+
+fn merge_into<I>(base: Option<Document>, documents: I, options: &MergeOptions) -> Result<Document, MergeError>
+where
+    I: IntoIterator<Item = Result<(String, Document), MergeError>>,
+{
+    // Define a starting `max_id` (will be used as start index for object_ids).
+    let mut max_id = 1;
+    // Collect all Documents pages grouped by a map; everything else goes straight into `document`.
+    let mut documents_pages = BTreeMap::new();
+    let mut document = Document::with_version(options.pdf_version.as_str());
+    // "Catalog" and "Pages" are mandatory.
+    let mut catalog_object: Option<(ObjectId, Object)> = None;
+    let mut pages_object: Option<(ObjectId, Object)> = None;
+    // The MediaBox of the last page merged so far, for sizing separator pages.
+    let mut last_media_box = None;
+    let mut forms = forms::FormFields::default();
+    let mut attachments = attachments::Attachments::default();
+    let mut named_destinations = destinations::NamedDestinations::default();
+    let mut first_xmp = None;
+    let mut first_viewer_settings = None;
+    let mut page_labels = page_labels::PageLabels::default();
+    let mut structure_tree = structure::StructureTree::default();
+    let mut optional_content = layers::OptionalContent::default();
+    // The page IDs of each document, in page order, for interleaving.
+    let mut page_groups = Vec::new();
+    // Each document's title, first page and bookmark, labeled once the page order is final.
+    let mut entries = Vec::new();
+    // The title and declared version of each document, to choose and check the merged version.
+    let mut versions = Vec::new();
+
+    // The base document's pages and document properties, when appending.
+    let appending = base.is_some();
+    let mut base_pages = Vec::new();
+    let mut base_info = None;
+
+    let inputs = base
+        .into_iter()
+        .map(|doc| Ok((None, doc)))
+        .chain(documents.into_iter().map(|input| input.map(|(title, doc)| (Some(title), doc))));
+
+    for input in inputs {
+        let (title, mut doc) = input?;
+        // An empty document has nothing to bookmark or interleave, and its catalog shouldn't
+        // stand in for those of documents with pages.
+        let has_pages = doc.page_iter().next().is_some();
+        let has_page_tree = doc
+            .catalog()
+            .and_then(|catalog| catalog.get(b"Pages"))
+            .and_then(|pages| destinations::deref_dict(&doc, pages))
+            .is_ok();
+        if let Some(title) = title.as_ref().filter(|_| has_page_tree && !has_pages) {
+            warn!("Skipping {:?}: it has no pages", title);
+            continue;
+        }
+        versions.push((title.clone(), doc.version.clone()));
+
+        let separator = options.separator == Some(Separator::Blank) && options.interleave.is_none();
+        if separator && has_pages {
+            if let Some(media_box) = last_media_box.take() {
+                let media_box = options.generated_page_size.map_or(media_box, PaperSize::media_box);
+                // Take the next ID so the separator sorts between this document and the previous one.
+                documents_pages.insert((max_id, 0), blank_page(media_box));
+                max_id = reserve_ids(max_id, 1)?;
+            }
+        }
+
+        // Checked first, so neither renumbering nor taking the ID after it can overflow.
+        reserve_ids(max_id, doc.objects.len())?;
+        renumber::renumber_objects(&mut doc, max_id);
+
+        debug!(
+            "Document {:?}: {} objects renumbered to {}..={}",
+            title,
+            doc.objects.len(),
+            max_id,
+            doc.max_id
+        );
+        max_id = doc.max_id + 1;
+
+        if options.strip_js {
+            let removed = javascript::strip_javascript(&mut doc);
+            debug!("Removed {} JavaScript references from {:?}", removed, title);
+        }
+
+        // Named destinations are resolved against this document's catalog, which may not survive.
+        annotations::resolve_link_destinations(&mut doc);
+        named_destinations.add(&doc);
+
+        if options.keep_forms {
+            forms.add(&mut doc);
+        }
+        if options.keep_attachments {
+            attachments.add(&doc);
+        }
+        if options.xmp == Some(XmpMetadata::First) && first_xmp.is_none() {
+            first_xmp = xmp::valid_metadata(&doc);
+        }
+        page_labels.add(&doc);
+        optional_content.add(&mut doc);
+        if options.retain_structure_tree {
+            structure_tree.add(&mut doc);
+        }
+
+        let pages = doc.get_pages();
+
+        let is_base = title.is_none();
+        if is_base {
+            // The base document keeps its own outline, at the top level.
+            if let Some(&first_page) = pages.values().next() {
+                for id in outline::copy_outline(&doc, &mut document, None, first_page) {
+                    doc.objects.remove(&id);
+                }
+            }
+            base_info = doc.trailer.get(b"Info").and_then(Object::as_reference).ok();
+            base_pages = pages.values().copied().collect();
+        }
+
+        let title = title.map(|title| match options.bookmarks {
+            BookmarkMode::Sequential => String::new(),
+            BookmarkMode::Title => info_title(&doc).unwrap_or(title),
+            BookmarkMode::Filename | BookmarkMode::None => title,
+        });
+        if let Some((title, &first_page)) = title.zip(pages.values().next()) {
+            let mut bookmark = None;
+            if options.bookmarks != BookmarkMode::None {
+                let bookmark_entry = Bookmark::new(title.clone(), [0.0, 0.0, 1.0], 0, first_page);
+                let parent = document.add_bookmark(bookmark_entry, None);
+                bookmark = Some(parent);
+
+                // Keep the document's own outline, nested under its top-level bookmark.
+                for id in outline::copy_outline(&doc, &mut document, Some(parent), first_page) {
+                    doc.objects.remove(&id);
+                }
+            }
+            entries.push((title, first_page, bookmark));
+        }
+
+        if let Some(&last_page) = pages.values().next_back() {
+            let media_box = pages::inherited_attribute(&doc, last_page, b"MediaBox").cloned();
+            last_media_box = Some(media_box.unwrap_or_else(|| PaperSize::LETTER.media_box()));
+        }
+
+        if !is_base {
+            page_groups.push(pages.values().copied().collect::<Vec<_>>());
+        }
+        documents_pages.extend(
+            pages
+                    .into_values()
+                    .map(|object_id| {
+                        (
+                            object_id,
+                            Object::Dictionary(rotated_page(&doc, object_id, options)),
+                        )
+                    })
+                    .collect::<BTreeMap<ObjectId, Object>>(),
+        );
+
+        // Move all other objects into the merged document, so only one source document is
+        // held in memory at a time.
+        for (object_id, object) in doc.objects {
+            // We have to ignore "Page" (as are processed later), "Outlines" and "Outline" objects.
+            // All other objects should be collected and inserted into the main Document.
+            match object.type_name().unwrap_or("") {
+                // When appending, the base document's catalog, which comes first, is kept as it is.
+                "Catalog" if appending && catalog_object.is_some() => {}
+                "Catalog" => {
+                    if first_viewer_settings.is_none() {
+                        first_viewer_settings = object.as_dict().ok().map(viewer_settings);
+                    }
+                    // Collect a first "Catalog" object and use it for the future "Pages".
+                    let id = catalog_object.map_or(object_id, |(id, _)| id);
+                    catalog_object = Some((id, object));
+                }
+                "Pages" => {
+                    // Collect a first "Pages" object and use it for the future "Catalog".
+                    // Its inheritable attributes were already copied onto each page, and merging them
+                    // here would be wrong: `Dictionary::extend` concatenates arrays such as MediaBox.
+                    if pages_object.is_none() {
+                        if let Object::Dictionary(mut dictionary) = object {
+                            for key in pages::INHERITABLE_ATTRIBUTES {
+                                dictionary.remove(key);
+                            }
+                            dictionary.remove(b"Parent");
+
+                            pages_object = Some((object_id, Object::Dictionary(dictionary)));
+                        }
+                    }
+                }
+                "Page" => {}     // Ignored, processed later and separately
+                "Outlines" => {} // Ignored, rebuilt from the bookmarks
+                "Outline" => {}  // Ignored, rebuilt from the bookmarks
+                // Ignored, the input file's cross-reference and object streams, which lopdf never writes
+                "XRef" | "ObjStm" => {}
+                _ => {
+                    document.objects.insert(object_id, object);
+                }
+            }
+        }
+    }
+
+    if !appending && page_groups.is_empty() {
+        return Err(MergeError::EmptyInput);
+    }
+    if options.interleave.is_some() && page_groups.len() != 2 {
+        return Err(MergeError::InterleaveNeedsTwo(page_groups.len()));
+    }
+
+    // Pages are kept in ID order, which is the merge order unless interleaving.
+    let mut page_order = match (options.interleave, page_groups.as_slice()) {
+        (Some(interleave), [first, second]) => {
+            let mut order = base_pages;
+            order.extend(interleave_pages(first, second, interleave));
+            order
+        }
+        _ => documents_pages.keys().copied().collect(),
+    };
+
+    let duplicate_pages = if options.dedup_pages {
+        dedup::duplicate_pages(&document, &documents_pages, &page_order)
+    } else {
+        BTreeMap::new()
+    };
+    if !duplicate_pages.is_empty() {
+        for (position, page) in (1..).zip(&page_order) {
+            if duplicate_pages.contains_key(page) {
+                info!("Dropping merged page {}: it is identical to the page before it", position);
+            }
+        }
+        page_order.retain(|page| !duplicate_pages.contains_key(page));
+        for page in duplicate_pages.keys() {
+            documents_pages.remove(page);
+        }
+        for (_, first_page, _) in &mut entries {
+            *first_page = duplicate_pages.get(first_page).copied().unwrap_or(*first_page);
+        }
+        for bookmark in document.bookmark_table.values_mut() {
+            bookmark.page = duplicate_pages.get(&bookmark.page).copied().unwrap_or(bookmark.page);
+        }
+    }
+
+    // The table of contents comes first, so the documents' page numbers start after it.
+    let toc_page_count = if options.toc { toc::page_count(entries.len()) } else { 0 };
+    let mut toc_entries = Vec::new();
+    for (title, first_page, bookmark) in entries {
+        let Some(position) = page_order.iter().position(|&page| page == first_page) else {
+            continue;
+        };
+        let page_number = toc_page_count + position + 1;
+
+        // Untitled documents are labeled with the position of their first page in the output.
+        let title = if title.is_empty() { format!("Page_{}", page_number) } else { title };
+        if let Some(bookmark) = bookmark.and_then(|id| document.bookmark_table.get_mut(&id)) {
+            bookmark.title = title.clone();
+        }
+        toc_entries.push((title, page_number));
+    }
+
+    if options.toc {
+        reserve_ids(max_id, toc::object_count(toc_entries.len()))?;
+        let size = options.generated_page_size.unwrap_or_default();
+        let toc = toc::toc_pages(&toc_entries, size, &mut max_id);
+
+        page_order.splice(0..0, toc.pages.iter().map(|(id, _)| *id));
+        documents_pages.extend(toc.pages.into_iter().map(|(id, page)| (id, Object::Dictionary(page))));
+        document.objects.extend(toc.objects);
+    }
+
+    // If no "Pages" object found, abort.
+    if pages_object.is_none() {
+        return Err(MergeError::NoPagesRoot);
+    }
+
+    if let Some(hook) = &options.page_hook {
+        for (index, page_id) in page_order.iter().enumerate() {
+            if let Some(Object::Dictionary(page)) = documents_pages.get_mut(page_id) {
+                hook.call(index, page);
+            }
+        }
+    }
+
+    // Iterate over all "Page" objects and collect into the parent "Pages" created before
+    for (object_id, object) in documents_pages.iter() {
+        if let Ok(dictionary) = object.as_dict() {
+            let mut dictionary = dictionary.clone();
+            dictionary.set("Parent", pages_object.as_ref().unwrap().0);
+
+            document
+                    .objects
+                    .insert(*object_id, Object::Dictionary(dictionary));
+        }
+    }
+
+    // If no "Catalog" found, abort.
+    if catalog_object.is_none() {
+        return Err(MergeError::NoCatalogRoot);
+    }
+
+    let catalog_object = catalog_object.unwrap();
+    let pages_object = pages_object.unwrap();
+    debug!(
+        "Using catalog {:?} and pages root {:?} for {} pages",
+        catalog_object.0,
+        pages_object.0,
+        documents_pages.len()
+    );
+
+    let page_label_tree = page_labels.into_number_tree(&page_order);
+
+    // Build a new "Pages" with updated fields
+    if let Ok(dictionary) = pages_object.1.as_dict() {
+        let mut dictionary = dictionary.clone();
+
+        // Set new pages count
+        dictionary.set("Count", documents_pages.len() as i64);
+
+        // Set new "Kids" list (collected from documents pages) for "Pages"
+        dictionary.set(
+            "Kids",
+            page_order
+                    .into_iter()
+                    .map(Object::Reference)
+                    .collect::<Vec<_>>(),
+        );
+
+        document
+                .objects
+                .insert(pages_object.0, Object::Dictionary(dictionary));
+    }
+
+    // Build a new "Catalog" with updated fields
+    if let Ok(dictionary) = catalog_object.1.as_dict() {
+        let mut dictionary = dictionary.clone();
+        if !appending {
+            let dropped = dictionary
+                .iter()
+                .map(|(key, _)| key.clone())
+                .filter(|key| !MERGED_CATALOG_KEYS.contains(&key.as_slice()))
+                .collect::<Vec<_>>();
+            for key in dropped {
+                dictionary.remove(&key);
+            }
+            if options.keep_first_catalog_metadata {
+                for (key, value) in first_viewer_settings.unwrap_or_default() {
+                    dictionary.set(key, value);
+                }
+            }
+        }
+        dictionary.set("Pages", pages_object.0);
+        dictionary.remove(b"Outlines"); // Replaced by the outline built below
+        if let Some(lang) = &options.lang {
+            dictionary.set("Lang", Object::string_literal(lang.as_str()));
+        }
+        if options.retain_structure_tree {
+            dictionary.remove(b"StructTreeRoot");
+            if let Some((id, root)) = structure_tree.into_root() {
+                document.objects.insert(id, Object::Dictionary(root));
+                dictionary.set("StructTreeRoot", id);
+                dictionary.set("MarkInfo", dictionary! { "Marked" => true });
+            }
+        }
+        dictionary.remove(b"OCProperties");
+        if let Some(properties) = optional_content.into_properties() {
+            dictionary.set("OCProperties", properties);
+        }
+        dictionary.remove(b"PageLabels");
+        if let Some(tree) = page_label_tree {
+            dictionary.set("PageLabels", tree);
+        }
+        if options.keep_forms {
+            dictionary.remove(b"AcroForm");
+            if let Some(form) = forms.into_dictionary() {
+                dictionary.set("AcroForm", form);
+            }
+        }
+        let mut names = dictionary
+            .get(b"Names")
+            .and_then(|names| destinations::deref_dict(&document, names))
+            .cloned()
+            .unwrap_or_default();
+        if options.keep_attachments {
+            names.remove(b"EmbeddedFiles");
+            if let Some(tree) = attachments.into_name_tree() {
+                names.set("EmbeddedFiles", tree);
+            }
+        }
+        // Every document's named destinations, in the name tree rather than the older `/Dests`.
+        dictionary.remove(b"Dests");
+        names.remove(b"Dests");
+        if let Some(tree) = named_destinations.into_name_tree() {
+            names.set("Dests", tree);
+        }
+        if names.is_empty() {
+            dictionary.remove(b"Names");
+        } else {
+            dictionary.set("Names", names);
+        }
+        match (options.xmp, first_xmp) {
+            (Some(XmpMetadata::First), Some(id)) => dictionary.set("Metadata", id),
+            (Some(XmpMetadata::First), None) => {
+                dictionary.remove(b"Metadata");
+            }
+            (Some(XmpMetadata::Generate), _) => {
+                reserve_ids(max_id, 1)?;
+                let id = (max_id, 0);
+                document.objects.insert(id, xmp::generate(&options.metadata));
+                dictionary.set("Metadata", id);
+            }
+            (None, _) => {}
+        }
+
+        document
+                .objects
+                .insert(catalog_object.0, Object::Dictionary(dictionary));
+    }
+
+    document.trailer.set("Root", catalog_object.0);
+
+    let merged_version = if options.match_input_version {
+        let highest = versions.iter().filter_map(|(_, version)| PdfVersion::from_header(version)).max();
+        highest.unwrap_or(options.pdf_version)
+    } else {
+        options.pdf_version
+    };
+    document.version = merged_version.as_str().to_string();
+    for (title, version) in &versions {
+        let newer = match PdfVersion::from_header(version) {
+            Some(version) => version > merged_version,
+            // Versions before 1.3 are all older; anything else is newer than we know of.
+            None => !version.trim().starts_with("1."),
+        };
+        if newer {
+            let title = title.as_ref().map_or("The base document".to_string(), |title| format!("{:?}", title));
+            warn!(
+                "{} is PDF {}, newer than the merged PDF's {}; features it uses may not be supported",
+                title, version, merged_version
+            );
+        }
+    }
+
+    // Links and named destinations to a dropped page go to the page it repeats.
+    if !duplicate_pages.is_empty() {
+        document.traverse_objects(|object| {
+            if let Object::Reference(id) = object {
+                if let Some(kept) = duplicate_pages.get(id) {
+                    *id = *kept;
+                }
+            }
+        });
+    }
+    if let Some(info) = base_info.filter(|info| document.objects.contains_key(info)) {
+        document.trailer.set("Info", info);
+    }
+
+    if options.dedup {
+        let removed = dedup::dedup_objects(&mut document);
+        debug!("Deduplication removed {} objects", removed);
+    }
+
+    // Update the max internal ID as wasn't updated before due to direct objects insertion
+    document.max_id = u32::try_from(document.objects.len()).map_err(|_| MergeError::TooManyObjects)?;
+
+    // Reorder all new Document objects. Objects are kept in BTreeMaps throughout, so the
+    // numbering, and the bytes written, only depend on the inputs and their order. Renumbering
+    // each document on its way in can't make this pass unnecessary: it has to close the gaps
+    // left by the catalogs, page trees and outlines the merge dropped since.
+    renumber::renumber_objects(&mut document, 1);
+    debug!("Merged document has {} objects", document.objects.len());
+
+    // The base document's outline is kept even without bookmarks for the appended documents.
+    if options.bookmarks != BookmarkMode::None || appending {
+        // Set any Bookmarks to the First child if they are not set to a page
+        document.adjust_zero_pages();
+
+        // Set all bookmarks to the PDF Object tree then set the Outlines to the Bookmark content map.
+        if let Some(n) = document.build_outline() {
+            outline::encode_titles(&mut document, n);
+
+            if let Ok(Object::Dictionary(ref mut dict)) = document.get_object_mut(catalog_object.0) {
+                dict.set("Outlines", Object::Reference(n));
+            }
+        }
+    }
+
+    if let Some(open_action) = options.open_action {
+        let first_page = document.page_iter().next();
+        if let Ok(catalog) = document.get_dictionary_mut(catalog_object.0) {
+            match (open_action, first_page) {
+                (OpenAction::FitPage, Some(page)) => {
+                    catalog.set("OpenAction", vec![page.into(), "Fit".into()]);
+                }
+                (OpenAction::FirstPage, Some(page)) => {
+                    catalog.set("OpenAction", vec![page.into(), "XYZ".into(), Object::Null, Object::Null, Object::Null]);
+                }
+                (OpenAction::Outline, _) => catalog.set("PageMode", "UseOutlines"),
+                (_, None) => {}
+            }
+        }
+    }
+
+    if options.flatten {
+        let drawn = flatten::flatten_annotations(&mut document);
+        debug!("Flattened {} annotations", drawn);
+    }
+
+    if let Some(size) = options.page_size {
+        resize::normalize_page_sizes(&mut document, size);
+    }
+
+    if let Some(watermark) = &options.watermark {
+        watermark::apply_watermark(&mut document, watermark);
+    }
+
+    if let Some(position) = options.page_numbers {
+        stamp::stamp_page_numbers(&mut document, position);
+    }
+
+    if options.normalize_resources {
+        let renamed = resources::normalize_resource_names(&mut document);
+        debug!("Renamed {} colliding resource(s)", renamed);
+    }
+
+    if let Some(nup) = options.nup {
+        let sheets = nup::impose_pages(&mut document, nup);
+        debug!("Placed the pages on {} sheet(s) of {}", sheets, nup);
+    }
+
+    if let Some(info) = options.metadata.to_dictionary() {
+        // Update the base document's properties rather than replacing them, when appending.
+        let existing = document.trailer.get(b"Info").and_then(Object::as_reference);
+        match existing.and_then(|id| document.get_dictionary_mut(id)) {
+            Ok(existing) => {
+                for (key, value) in info {
+                    existing.set(key, value);
+                }
+            }
+            Err(_) => {
+                let info_id = document.add_object(info);
+                document.trailer.set("Info", info_id);
+            }
+        }
+    }
+
+    if options.compress {
+        document.compress();
+    }
+
+    let dangling = validate::dangling_references(&document);
+    if let Some(first) = dangling.first() {
+        if options.strict {
+            return Err(MergeError::DanglingReferences(dangling.len()));
+        }
+        let from = match first.from {
+            Some((number, generation)) => format!("object {} {}", number, generation),
+            None => "the trailer".to_string(),
+        };
+        warn!(
+            "{} reference(s) in the merged PDF point at missing objects, e.g. {} {} R in {}",
+            dangling.len(),
+            first.to.0,
+            first.to.1,
+            from
+        );
+    }
+
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::content::Content;
+    use lopdf::Stream;
+
+    /// A minimal document with a Catalog, a Pages root and `page_count` empty pages.
+    fn document(page_count: usize) -> Document {
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let kids = (0..page_count)
+            .map(|_| {
+                doc.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                })
+                .into()
+            })
+            .collect::<Vec<Object>>();
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => page_count as i64,
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+        doc
+    }
+
+    fn count_type(doc: &Document, type_name: &str) -> usize {
+        doc.objects
+            .values()
+            .filter(|object| object.type_name().is_ok_and(|name| name == type_name))
+            .count()
+    }
+
+    fn merge(documents: Vec<Document>) -> Result<Document, MergeError> {
+        let documents = documents.into_iter().map(|doc| (String::new(), doc)).collect();
+        merge_pdf(documents, &MergeOptions::default())
+    }
+
+    #[test]
+    fn merges_pages_under_one_catalog_and_pages_root() {
+        let merged = merge(vec![document(1), document(2)]).unwrap();
+
+        assert_eq!(merged.get_pages().len(), 3);
+        assert_eq!(count_type(&merged, "Catalog"), 1);
+        assert_eq!(count_type(&merged, "Pages"), 1);
+
+        let pages_root = merged.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        let pages = merged.get_dictionary(pages_root).unwrap();
+        assert_eq!(pages.get(b"Count").unwrap().as_i64().unwrap(), 3);
+    }
+
+    fn top_level_titles(doc: &Document) -> Vec<String> {
+        doc.bookmarks
+            .iter()
+            .map(|id| doc.bookmark_table[id].title.clone())
+            .collect()
+    }
+
+    #[test]
+    fn untitled_bookmarks_are_labeled_with_their_page_position() {
+        let merged = merge(vec![document(2), document(3), document(1)]).unwrap();
+
+        assert_eq!(top_level_titles(&merged), ["Page_1", "Page_3", "Page_6"]);
+    }
+
+    /// `document(page_count)` with `/PageLabels` ranges `(start page index, style)`.
+    fn labeled_pages(page_count: usize, ranges: Vec<(i64, Dictionary)>) -> Document {
+        let mut doc = document(page_count);
+        let nums = ranges.into_iter().flat_map(|(start, style)| [start.into(), style.into()]).collect::<Vec<Object>>();
+        doc.catalog_mut().unwrap().set("PageLabels", dictionary! { "Nums" => nums });
+        doc
+    }
+
+    fn page_label_ranges(doc: &Document) -> Vec<(i64, Dictionary)> {
+        let tree = doc.catalog().unwrap().get(b"PageLabels").unwrap().as_dict().unwrap();
+        tree.get(b"Nums")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .chunks(2)
+            .map(|pair| (pair[0].as_i64().unwrap(), pair[1].as_dict().unwrap().clone()))
+            .collect()
+    }
+
+    #[test]
+    fn page_labels_are_moved_to_each_documents_pages() {
+        let front_matter = labeled_pages(4, vec![(0, dictionary! { "S" => "r" }), (2, dictionary! { "S" => "D" })]);
+        let appendix = labeled_pages(3, vec![(0, dictionary! { "S" => "D", "P" => Object::string_literal("A-"), "St" => 5 })]);
+        let merged = merge(vec![front_matter, document(2), appendix]).unwrap();
+
+        assert_eq!(
+            page_label_ranges(&merged),
+            [
+                (0, dictionary! { "S" => "r" }),
+                (2, dictionary! { "S" => "D" }),
+                // The unlabeled document is numbered with its pages' positions.
+                (4, dictionary! { "S" => "D", "St" => 5 }),
+                (6, dictionary! { "S" => "D", "P" => Object::string_literal("A-"), "St" => 5 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn selected_pages_keep_their_labels() {
+        let mut doc = labeled_pages(5, vec![(0, dictionary! { "S" => "r" }), (2, dictionary! { "S" => "D" })]);
+        pages::select_pages(&mut doc, &"2,4-5".parse().unwrap()).unwrap();
+
+        assert_eq!(
+            page_label_ranges(&doc),
+            [(0, dictionary! { "S" => "r", "St" => 2 }), (1, dictionary! { "S" => "D", "St" => 2 })]
+        );
+    }
+
+    #[test]
+    fn bookmark_mode_picks_each_documents_title() {
+        let mut titled = document(1);
+        let info = titled.add_object(dictionary! { "Title" => Object::string_literal("Annual report") });
+        titled.trailer.set("Info", info);
+        let titles = |bookmarks| {
+            let documents = vec![("a".to_string(), titled.clone()), ("b".to_string(), document(2))];
+            let merged = merge_pdf(documents, &MergeOptions { bookmarks, ..MergeOptions::default() }).unwrap();
+            top_level_titles(&merged)
+        };
+
+        assert_eq!(titles(BookmarkMode::Filename), ["a", "b"]);
+        assert_eq!(titles(BookmarkMode::Sequential), ["Page_1", "Page_2"]);
+        assert_eq!(titles(BookmarkMode::Title), ["Annual report", "b"]);
+        assert!(titles(BookmarkMode::None).is_empty());
+    }
+
+    #[test]
+    fn documents_without_pages_are_skipped() {
+        let merged = merge(vec![document(2), document(0), document(1)]).unwrap();
+
+        assert_eq!(merged.get_pages().len(), 3);
+        assert_eq!(top_level_titles(&merged), ["Page_1", "Page_3"]);
+        assert_eq!(merge(vec![document(0)]).unwrap_err(), MergeError::EmptyInput);
+    }
+
+    #[test]
+    fn streaming_merge_loads_each_document_when_it_is_taken() {
+        let mut loaded = 0;
+        let documents = [2, 3, 1].into_iter().map(|page_count| {
+            loaded += 1;
+            (String::new(), document(page_count))
+        });
+        let merged = merge_pdf_streaming(documents, &MergeOptions::default()).unwrap();
+
+        assert_eq!(loaded, 3);
+        assert_eq!(merged.get_pages().len(), 6);
+        assert_eq!(top_level_titles(&merged), ["Page_1", "Page_3", "Page_6"]);
+        assert_eq!(
+            merge_pdf_streaming(std::iter::empty(), &MergeOptions::default()).unwrap_err(),
+            MergeError::EmptyInput
+        );
+    }
+
+    /// A two-page document whose first page links to its second, once through an explicit
+    /// destination and once through a named one.
+    fn linked_document() -> Document {
+        let mut doc = document(2);
+        let pages = doc.get_pages();
+        let (first, second) = (pages[&1], pages[&2]);
+
+        let explicit = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 0.into(), 100.into(), 20.into()],
+            "Dest" => vec![second.into(), "Fit".into()],
+        });
+        let named = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 20.into(), 100.into(), 40.into()],
+            "A" => dictionary! { "S" => "GoTo", "D" => Object::string_literal("second") },
+        });
+        doc.get_dictionary_mut(first)
+            .unwrap()
+            .set("Annots", vec![explicit.into(), named.into()]);
+
+        let names = doc.add_object(dictionary! {
+            "Dests" => dictionary! {
+                "Names" => vec![Object::string_literal("second"), vec![second.into(), "Fit".into()].into()],
+            },
+        });
+        let catalog_id = doc.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        doc.get_dictionary_mut(catalog_id).unwrap().set("Names", names);
+        doc
+    }
+
+    /// The page each link annotation on `page_id` jumps to.
+    fn link_targets(doc: &Document, page_id: ObjectId) -> Vec<ObjectId> {
+        let annots = doc.get_dictionary(page_id).unwrap().get(b"Annots").unwrap().as_array().unwrap();
+        annots
+            .iter()
+            .map(|annot| {
+                let annot = doc.get_dictionary(annot.as_reference().unwrap()).unwrap();
+                let dest = match annot.get(b"Dest") {
+                    Ok(dest) => dest,
+                    Err(_) => annot.get(b"A").unwrap().as_dict().unwrap().get(b"D").unwrap(),
+                };
+                dest.as_array().unwrap()[0].as_reference().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn internal_links_point_at_the_merged_pages() {
+        // The linked document comes last, so its catalog and name tree are not the ones kept.
+        let merged = merge(vec![linked_document(), document(1)]).unwrap();
+        let pages = merged.get_pages();
+
+        assert_eq!(link_targets(&merged, pages[&1]), [pages[&2], pages[&2]]);
+
+        let merged = merge(vec![document(1), linked_document()]).unwrap();
+        let pages = merged.get_pages();
+
+        assert_eq!(link_targets(&merged, pages[&2]), [pages[&3], pages[&3]]);
+    }
+
+    #[test]
+    fn named_destinations_of_every_document_are_kept() {
+        let mut old_style = linked_document();
+        let pages = old_style.get_pages();
+        let dests = dictionary! { "chapter" => dictionary! { "D" => vec![pages[&1].into(), "Fit".into()] } };
+        old_style.catalog_mut().unwrap().set("Dests", dests);
+
+        let merged = merge(vec![linked_document(), old_style]).unwrap();
+        let pages = merged.get_pages();
+        let catalog = merged.catalog().unwrap();
+        assert!(!catalog.has(b"Dests"));
+
+        let tree = destinations::deref_dict(&merged, catalog.get(b"Names").unwrap()).unwrap().get(b"Dests").unwrap();
+        let targets = name_tree::leaves(&merged, tree)
+            .into_iter()
+            .map(|(name, dest)| (String::from_utf8(name).unwrap(), destinations::destination_page(&merged, &dest).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            targets,
+            [("chapter".to_string(), pages[&3]), ("second".to_string(), pages[&2]), ("second_2".to_string(), pages[&4])]
+        );
+    }
+
+    #[test]
+    fn links_to_removed_pages_are_dropped() {
+        let mut linked = linked_document();
+        pages::select_pages(&mut linked, &"1".parse().unwrap()).unwrap();
+        let merged = merge(vec![linked]).unwrap();
+        let page = merged.get_pages()[&1];
+
+        let annots = merged.get_dictionary(page).unwrap().get(b"Annots").unwrap().as_array().unwrap();
+        for annot in annots {
+            let annot = merged.get_dictionary(annot.as_reference().unwrap()).unwrap();
+            assert!(!annot.has(b"Dest") && !annot.has(b"A"));
+        }
+    }
+
+    /// A one-page document with a single text field called `name`.
+    fn form_document(name: &str) -> Document {
+        let mut doc = document(1);
+        let page = doc.get_pages()[&1];
+        let field = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "FT" => "Tx",
+            "T" => Object::string_literal(name),
+            "Rect" => vec![0.into(), 0.into(), 100.into(), 20.into()],
+            "P" => page,
+        });
+        doc.get_dictionary_mut(page).unwrap().set("Annots", vec![field.into()]);
+
+        let catalog_id = doc.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        doc.get_dictionary_mut(catalog_id).unwrap().set(
+            "AcroForm",
+            dictionary! { "Fields" => vec![field.into()], "DA" => Object::string_literal("/Helv 0 Tf 0 g") },
+        );
+        doc
+    }
+
+    fn field_names(doc: &Document) -> Vec<String> {
+        let form = doc.catalog().unwrap().get(b"AcroForm").unwrap().as_dict().unwrap();
+        form.get(b"Fields")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| {
+                let field = doc.get_dictionary(field.as_reference().unwrap()).unwrap();
+                lopdf::decode_text_string(field.get(b"T").unwrap()).unwrap()
+            })
+            .collect()
+    }
+
+    fn xmp_document(packet: &str) -> Document {
+        let mut doc = document(1);
+        let metadata = doc.add_object(Stream::new(dictionary! { "Type" => "Metadata", "Subtype" => "XML" }, packet.into()));
+        let catalog_id = doc.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        doc.get_dictionary_mut(catalog_id).unwrap().set("Metadata", metadata);
+        doc
+    }
+
+    fn xmp_packet(doc: &Document) -> String {
+        let id = doc.catalog().unwrap().get(b"Metadata").unwrap().as_reference().unwrap();
+        let stream = doc.get_object(id).unwrap().as_stream().unwrap();
+        String::from_utf8(stream.content.clone()).unwrap()
+    }
+
+    #[test]
+    fn xmp_first_keeps_the_first_well_formed_packet() {
+        let documents = vec![
+            (String::new(), document(1)),
+            (String::new(), xmp_document("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><broken>")),
+            (String::new(), xmp_document("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">first</x:xmpmeta>")),
+            (String::new(), xmp_document("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">last</x:xmpmeta>")),
+        ];
+        let options = MergeOptions { xmp: Some(XmpMetadata::First), ..MergeOptions::default() };
+        let merged = merge_pdf(documents, &options).unwrap();
+
+        assert!(xmp_packet(&merged).contains(">first<"));
+    }
+
+    #[test]
+    fn xmp_generate_writes_well_formed_metadata() {
+        let documents = vec![(String::new(), xmp_document("<old/>"))];
+        let options = MergeOptions {
+            xmp: Some(XmpMetadata::Generate),
+            metadata: Metadata { title: Some("Q1 <draft> & notes".into()), ..Metadata::default() },
+            ..MergeOptions::default()
+        };
+        let merged = merge_pdf(documents, &options).unwrap();
+
+        let packet = xmp_packet(&merged);
+        let xml = roxmltree::Document::parse(&packet).unwrap();
+        let title = xml.descendants().find(|node| node.has_tag_name("li")).unwrap();
+        assert_eq!(title.text(), Some("Q1 <draft> & notes"));
+    }
+
+    #[test]
+    fn lang_sets_the_catalog_language() {
+        let lang = |lang: Option<&str>| {
+            let mut last = document(1);
+            last.catalog_mut().unwrap().set("Lang", Object::string_literal("fr"));
+            let documents = vec![(String::new(), document(1)), (String::new(), last)];
+            let merged = merge_pdf(documents, &MergeOptions::default().lang(lang.map(str::to_string))).unwrap();
+            merged.catalog().unwrap().get(b"Lang").unwrap().as_str().unwrap().to_vec()
+        };
+
+        assert_eq!(lang(Some("en-US")), b"en-US");
+        assert_eq!(lang(None), b"fr");
+    }
+
+    #[test]
+    fn match_input_version_declares_the_highest_input_version() {
+        let version = |match_input_version: bool, versions: &[&str]| {
+            let documents = versions
+                .iter()
+                .map(|version| {
+                    let mut doc = document(1);
+                    doc.version = version.to_string();
+                    (String::new(), doc)
+                })
+                .collect();
+            let options = MergeOptions::default().match_input_version(match_input_version);
+            merge_pdf(documents, &options).unwrap().version
+        };
+
+        assert_eq!(version(true, &["1.4", "1.7", "1.6"]), "1.7");
+        assert_eq!(version(true, &["1.3", "1.4"]), "1.4");
+        // Versions it doesn't know don't count.
+        assert_eq!(version(true, &["1.2", "3.0"]), "1.5");
+        assert_eq!(version(false, &["1.4", "2.0"]), "1.5");
+    }
+
+    #[test]
+    fn open_action_sets_how_the_merged_pdf_opens() {
+        let catalog = |open_action| {
+            let documents = vec![(String::new(), document(2)), (String::new(), document(1))];
+            let merged = merge_pdf(documents, &MergeOptions::default().open_action(Some(open_action))).unwrap();
+            let first_page = merged.page_iter().next().unwrap();
+            (merged.catalog().unwrap().clone(), first_page)
+        };
+
+        let (fit_page, first_page) = catalog(OpenAction::FitPage);
+        assert_eq!(fit_page.get(b"OpenAction").unwrap(), &Object::Array(vec![first_page.into(), "Fit".into()]));
+        let (first, first_page) = catalog(OpenAction::FirstPage);
+        let action = first.get(b"OpenAction").unwrap().as_array().unwrap();
+        assert_eq!(action[..2], [first_page.into(), "XYZ".into()]);
+        let (outline, _) = catalog(OpenAction::Outline);
+        assert_eq!(outline.get(b"PageMode").unwrap().as_name().unwrap(), b"UseOutlines");
+        assert!(!outline.has(b"OpenAction"));
+    }
+
+    #[test]
+    fn catalog_keeps_the_first_viewer_settings_only_when_asked() {
+        let with_settings = |layout: &str| {
+            let mut doc = document(1);
+            let preferences = doc.add_object(dictionary! { "HideToolbar" => true });
+            let catalog = doc.catalog_mut().unwrap();
+            catalog.set("ViewerPreferences", preferences);
+            catalog.set("PageLayout", layout);
+            catalog.set("URI", dictionary! { "Base" => Object::string_literal("https://example.com/") });
+            doc
+        };
+        let catalog = |keep| {
+            let documents = vec![(String::new(), with_settings("TwoColumnLeft")), (String::new(), with_settings("SinglePage"))];
+            let merged = merge_pdf(documents, &MergeOptions::default().keep_first_catalog_metadata(keep)).unwrap();
+            let catalog = merged.catalog().unwrap().clone();
+            let preferences = catalog.get(b"ViewerPreferences").and_then(|value| merged.dereference(value)).map(|(_, value)| value.clone());
+            (catalog, preferences)
+        };
+
+        let (minimal, preferences) = catalog(false);
+        let mut keys = minimal.iter().map(|(key, _)| key.as_slice()).collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, [b"Outlines".as_slice(), b"Pages", b"Type"]);
+        assert!(preferences.is_err());
+
+        let (kept, preferences) = catalog(true);
+        assert_eq!(kept.get(b"PageLayout").unwrap().as_name().unwrap(), b"TwoColumnLeft");
+        assert_eq!(preferences.unwrap(), Object::Dictionary(dictionary! { "HideToolbar" => true }));
+        assert!(!kept.has(b"URI"));
+    }
+
+    #[test]
+    fn renumbering_numbers_objects_like_lopdf() {
+        let mut expected = merge(vec![binder(2), binder(3)]).unwrap();
+        renumber::renumber_objects(&mut expected, 20);
+        // Pages out of ID order trade numbers.
+        let pages_id = expected.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        let pages = expected.get_dictionary_mut(pages_id).unwrap();
+        let mut kids = pages.get(b"Kids").unwrap().as_array().unwrap().clone();
+        kids.reverse();
+        pages.set("Kids", kids);
+        expected.add_bookmark(Bookmark::new("Last".to_string(), [0.0, 0.0, 1.0], 0, page_ids(&expected)[4]), None);
+        let mut renumbered = expected.clone();
+
+        expected.renumber_objects();
+        renumber::renumber_objects(&mut renumbered, 1);
+
+        assert_eq!(renumbered.objects, expected.objects);
+        assert_eq!(renumbered.trailer, expected.trailer);
+        assert_eq!(renumbered.max_id, expected.max_id);
+        let pages = |doc: &Document| doc.bookmark_table.values().map(|bookmark| bookmark.page).collect::<Vec<_>>();
+        assert_eq!(pages(&renumbered), pages(&expected));
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture renumbering_benchmark`.
+    #[test]
+    #[ignore]
+    fn renumbering_benchmark() {
+        // Renumbered to start at 10, so that renumbering again changes every object's number.
+        let documents = (0..2000).map(|index| (index.to_string(), binder(3))).collect();
+        let mut merged = merge_pdf(documents, &MergeOptions::default()).unwrap();
+        renumber::renumber_objects(&mut merged, 10);
+        println!("{} objects, {} bookmarks", merged.objects.len(), merged.bookmark_table.len());
+
+        let mut lopdf = merged.clone();
+        let started = std::time::Instant::now();
+        lopdf.renumber_objects();
+        println!("lopdf renumber_objects: {:?}", started.elapsed());
+
+        let mut linear = merged;
+        let started = std::time::Instant::now();
+        renumber::renumber_objects(&mut linear, 1);
+        println!("renumber::renumber_objects: {:?}", started.elapsed());
+
+        assert_eq!(linear.objects, lopdf.objects);
+    }
+
+    /// `document(1)` with an optional content group named `name`, whose page content is only
+    /// drawn when the group is on, and which a base state of `OFF` turns off unless `on`.
+    fn layered_document(name: &str, on: bool) -> Document {
+        let mut doc = document(1);
+        let group = doc.add_object(dictionary! { "Type" => "OCG", "Name" => Object::string_literal(name) });
+        let content = doc.add_object(Stream::new(Dictionary::new(), b"/OC /L1 BDC 0 0 10 10 re f EMC".to_vec()));
+        let page_id = doc.page_iter().next().unwrap();
+        let page = doc.get_dictionary_mut(page_id).unwrap();
+        page.set("Contents", content);
+        page.set("Resources", dictionary! { "Properties" => dictionary! { "L1" => group } });
+        let on = if on { vec![group.into()] } else { Vec::new() };
+        doc.catalog_mut().unwrap().set(
+            "OCProperties",
+            dictionary! {
+                "OCGs" => vec![group.into()],
+                "D" => dictionary! { "BaseState" => "OFF", "ON" => on, "Order" => vec![group.into()] },
+            },
+        );
+        doc
+    }
+
+    /// `document(1)` drawing text in the font `base_font`, named `/F1` in its resources.
+    fn font_document(base_font: &str) -> Document {
+        let mut doc = document(1);
+        let font = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => base_font });
+        let content = doc.add_object(Stream::new(Dictionary::new(), b"BT /F1 12 Tf (Hello) Tj ET".to_vec()));
+        let page_id = doc.page_iter().next().unwrap();
+        let page = doc.get_dictionary_mut(page_id).unwrap();
+        page.set("Contents", content);
+        page.set("Resources", dictionary! { "Font" => dictionary! { "F1" => font } });
+        doc
+    }
+
+    #[test]
+    fn normalize_resources_renames_fonts_that_share_a_name() {
+        let documents = vec![
+            (String::new(), font_document("Helvetica")),
+            (String::new(), font_document("Courier")),
+            (String::new(), font_document("Helvetica")),
+        ];
+        let options = MergeOptions::default().normalize_resources(true).compress(false).dedup(true);
+        let merged = merge_pdf(documents, &options).unwrap();
+
+        let fonts = merged
+            .page_iter()
+            .map(|page_id| {
+                let content = merged.get_page_content(page_id).unwrap();
+                let operations = Content::decode(&content).unwrap().operations;
+                let name = operations.iter().find(|operation| operation.operator == "Tf").unwrap().operands[0].clone();
+                let name = name.as_name().unwrap().to_vec();
+                let resources = merged.get_dictionary(page_id).unwrap().get(b"Resources").unwrap().as_dict().unwrap();
+                let font = resources.get(b"Font").unwrap().as_dict().unwrap().get(&name).unwrap();
+                let font = merged.get_dictionary(font.as_reference().unwrap()).unwrap();
+                (String::from_utf8(name).unwrap(), font.get(b"BaseFont").unwrap().as_name().unwrap().to_vec())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fonts,
+            [
+                ("F1".to_string(), b"Helvetica".to_vec()),
+                ("F1_2".to_string(), b"Courier".to_vec()),
+                ("F1".to_string(), b"Helvetica".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn optional_content_groups_of_every_document_are_kept() {
+        let documents = vec![
+            (String::new(), layered_document("Notes", true)),
+            (String::new(), layered_document("Draft", false)),
+        ];
+        let merged = merge_pdf(documents, &MergeOptions::default()).unwrap();
+
+        let properties = merged.catalog().unwrap().get(b"OCProperties").unwrap().as_dict().unwrap();
+        let name = |group: &Object| {
+            let group = merged.get_dictionary(group.as_reference().unwrap()).unwrap();
+            group.get(b"Name").unwrap().as_str().unwrap().to_vec()
+        };
+        let names = |key: &[u8]| properties.get(key).unwrap().as_array().unwrap().iter().map(name).collect::<Vec<_>>();
+        assert_eq!(names(b"OCGs"), [b"Notes", b"Draft"]);
+        let config = properties.get(b"D").unwrap().as_dict().unwrap();
+        let config_names = |key: &[u8]| config.get(key).unwrap().as_array().unwrap().iter().map(name).collect::<Vec<_>>();
+        assert_eq!(config_names(b"Order"), [b"Notes", b"Draft"]);
+        // Turned off by its base state, which the merged configuration doesn't have.
+        assert_eq!(config_names(b"OFF"), [b"Draft"]);
+        assert!(!config.has(b"BaseState"));
+        // The pages' marked content still names the groups.
+        for page_id in merged.page_iter() {
+            let resources = merged.get_dictionary(page_id).unwrap().get(b"Resources").unwrap().as_dict().unwrap();
+            let group = resources.get(b"Properties").unwrap().as_dict().unwrap().get(b"L1").unwrap();
+            assert!(properties.get(b"OCGs").unwrap().as_array().unwrap().contains(group));
+        }
+    }
+
+    /// `document(page_count)` tagged with a paragraph element per page, marked content 0 of
+    /// its page.
+    fn tagged_document(page_count: usize) -> Document {
+        let mut doc = document(page_count);
+        let root_id = doc.new_object_id();
+        let mut elements = Vec::new();
+        let mut nums = Vec::new();
+        for (key, page_id) in (0..).zip(doc.get_pages().into_values()) {
+            let element = doc.add_object(dictionary! {
+                "Type" => "StructElem",
+                "S" => "Para",
+                "P" => root_id,
+                "Pg" => page_id,
+                "K" => 0,
+            });
+            doc.get_dictionary_mut(page_id).unwrap().set("StructParents", key);
+            elements.push(element.into());
+            nums.extend([key.into(), vec![element.into()].into()]);
+        }
+        doc.objects.insert(
+            root_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "StructTreeRoot",
+                "K" => elements,
+                "ParentTree" => dictionary! { "Nums" => nums },
+                "ParentTreeNextKey" => page_count as i64,
+                "RoleMap" => dictionary! { "Para" => "P" },
+            }),
+        );
+        let catalog = doc.catalog_mut().unwrap();
+        catalog.set("StructTreeRoot", root_id);
+        catalog.set("MarkInfo", dictionary! { "Marked" => true });
+        doc
+    }
+
+    #[test]
+    fn retain_structure_tree_combines_the_structure_trees() {
+        let documents = vec![(String::new(), tagged_document(2)), (String::new(), tagged_document(1))];
+        let merged = merge_pdf(documents.clone(), &MergeOptions::default().retain_structure_tree(true)).unwrap();
+
+        let catalog = merged.catalog().unwrap();
+        assert!(catalog.get(b"MarkInfo").unwrap().as_dict().unwrap().get(b"Marked").unwrap().as_bool().unwrap());
+        let root_id = catalog.get(b"StructTreeRoot").unwrap().as_reference().unwrap();
+        let root = merged.get_dictionary(root_id).unwrap();
+        assert_eq!(root.get(b"ParentTreeNextKey").unwrap().as_i64().unwrap(), 3);
+        assert_eq!(root.get(b"RoleMap").unwrap().as_dict().unwrap().get(b"Para").unwrap().as_name().unwrap(), b"P");
+
+        let elements = root.get(b"K").unwrap().as_array().unwrap();
+        let nums = root.get(b"ParentTree").unwrap().as_dict().unwrap().get(b"Nums").unwrap().as_array().unwrap();
+        assert_eq!(elements.len(), 3);
+        for ((page_id, element), pair) in page_ids(&merged).into_iter().zip(elements).zip(nums.chunks(2)) {
+            // Each page's parent tree key leads to the element for its marked content.
+            let key = merged.get_dictionary(page_id).unwrap().get(b"StructParents").unwrap().as_i64().unwrap();
+            assert_eq!(pair[0].as_i64().unwrap(), key);
+            assert_eq!(pair[1].as_array().unwrap(), std::slice::from_ref(element));
+            let element = merged.get_dictionary(element.as_reference().unwrap()).unwrap();
+            assert_eq!(element.get(b"Pg").unwrap().as_reference().unwrap(), page_id);
+            assert_eq!(element.get(b"P").unwrap().as_reference().unwrap(), root_id);
+        }
+        assert!(validate::dangling_references(&merged).is_empty());
+
+        let untagged = merge_pdf(documents, &MergeOptions::default()).unwrap();
+        assert!(!untagged.catalog().unwrap().has(b"StructTreeRoot"));
+    }
+
+    #[test]
+    fn keep_forms_combines_fields_and_renames_clashes() {
+        let documents = vec![
+            (String::new(), form_document("name")),
+            (String::new(), form_document("name")),
+            (String::new(), form_document("date")),
+        ];
+        let options = MergeOptions { keep_forms: true, ..MergeOptions::default() };
+        let merged = merge_pdf(documents, &options).unwrap();
+
+        assert_eq!(field_names(&merged), ["name", "name_2", "date"]);
+    }
+
+    #[test]
+    fn flatten_draws_appearances_into_the_page_and_drops_annotations() {
+        let mut doc = form_document("name");
+        let page = doc.get_pages()[&1];
+        let appearance = doc.add_object(Stream::new(
+            dictionary! { "BBox" => vec![0.into(), 0.into(), 100.into(), 20.into()] },
+            b"0 0 1 rg 0 0 100 20 re f".to_vec(),
+        ));
+        let annots = doc.get_dictionary(page).unwrap().get(b"Annots").unwrap();
+        let widget_id = annots.as_array().unwrap()[0].as_reference().unwrap();
+        let widget = doc.get_dictionary_mut(widget_id).unwrap();
+        widget.set("Rect", vec![50.into(), 50.into(), 150.into(), 70.into()]);
+        widget.set("AP", dictionary! { "N" => appearance });
+        let link = Object::Dictionary(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 0.into(), 10.into(), 10.into()],
+        });
+        if let Ok(Object::Array(annots)) = doc.get_dictionary_mut(page).unwrap().get_mut(b"Annots") {
+            annots.push(link);
+        }
+
+        let options = MergeOptions { flatten: true, ..MergeOptions::default() };
+        let merged = merge_pdf(vec![(String::new(), doc)], &options).unwrap();
+
+        let page = merged.get_pages()[&1];
+        assert!(!merged.get_dictionary(page).unwrap().has(b"Annots"));
+        assert!(!merged.catalog().unwrap().has(b"AcroForm"));
+        assert!(has_resource(&merged, page, b"XObject", b"MergePdfAnnot0"));
+
+        let operations = merged.get_and_decode_page_content(page).unwrap().operations;
+        let draws = operations.iter().filter(|operation| operation.operator == "Do").count();
+        assert_eq!(draws, 1);
+        let cm = operations.iter().rfind(|operation| operation.operator == "cm").unwrap();
+        let cm = cm.operands.iter().map(|value| value.as_float().unwrap()).collect::<Vec<_>>();
+        assert_eq!(cm, [1.0, 0.0, 0.0, 1.0, 50.0, 50.0]);
+    }
+
+    /// A document whose pages carry a `Label` entry, `{prefix}1`, `{prefix}2`, ...
+    fn labeled_document(prefix: &str, page_count: usize) -> Document {
+        let mut doc = document(page_count);
+        for (number, page_id) in doc.get_pages() {
+            let label = Object::string_literal(format!("{}{}", prefix, number));
+            doc.get_dictionary_mut(page_id).unwrap().set("Label", label);
+        }
+        doc
+    }
+
+    fn page_labels(doc: &Document) -> Vec<String> {
+        doc.page_iter()
+            .map(|page_id| {
+                let label = doc.get_dictionary(page_id).unwrap().get(b"Label").unwrap();
+                String::from_utf8(label.as_str().unwrap().to_vec()).unwrap()
+            })
+            .collect()
+    }
+
+    fn interleave(interleave: Interleave, documents: Vec<Document>) -> Result<Document, MergeError> {
+        let documents = documents.into_iter().map(|doc| (String::new(), doc)).collect();
+        let options = MergeOptions { interleave: Some(interleave), ..MergeOptions::default() };
+        merge_pdf(documents, &options)
+    }
+
+    fn attachment_document(name: &str, contents: &[u8]) -> Document {
+        let mut doc = document(1);
+        let file = doc.add_object(Stream::new(dictionary! { "Type" => "EmbeddedFile" }, contents.to_vec()));
+        let file_spec = doc.add_object(dictionary! {
+            "Type" => "Filespec",
+            "F" => Object::string_literal(name),
+            "EF" => dictionary! { "F" => file },
+        });
+
+        let catalog_id = doc.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        doc.get_dictionary_mut(catalog_id).unwrap().set(
+            "Names",
+            dictionary! {
+                "EmbeddedFiles" => dictionary! {
+                    "Names" => vec![Object::string_literal(name), file_spec.into()],
+                },
+            },
+        );
+        doc
+    }
+
+    /// The attachment names with the contents of the file each one points at.
+    fn attachments(doc: &Document) -> Vec<(String, Vec<u8>)> {
+        let names = doc.catalog().unwrap().get(b"Names").unwrap().as_dict().unwrap();
+        let tree = names.get(b"EmbeddedFiles").unwrap().as_dict().unwrap();
+        tree.get(b"Names")
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .chunks(2)
+            .map(|pair| {
+                let name = String::from_utf8(pair[0].as_str().unwrap().to_vec()).unwrap();
+                let file_spec = doc.get_dictionary(pair[1].as_reference().unwrap()).unwrap();
+                let file = file_spec.get(b"EF").unwrap().as_dict().unwrap().get(b"F").unwrap();
+                let stream = doc.get_object(file.as_reference().unwrap()).unwrap().as_stream().unwrap();
+                (name, stream.content.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keep_attachments_combines_files_and_renames_clashes() {
+        let documents = vec![
+            (String::new(), attachment_document("data.csv", b"first")),
+            (String::new(), document(1)),
+            (String::new(), attachment_document("data.csv", b"second")),
+            (String::new(), attachment_document("notes.txt", b"third")),
+        ];
+        let options = MergeOptions { keep_attachments: true, compress: false, ..MergeOptions::default() };
+        let merged = merge_pdf(documents, &options).unwrap();
+
+        assert_eq!(
+            attachments(&merged),
+            [
+                ("data.csv".to_string(), b"first".to_vec()),
+                ("data.csv_2".to_string(), b"second".to_vec()),
+                ("notes.txt".to_string(), b"third".to_vec()),
+            ]
+        );
+    }
+
+    fn sorted(names: &[&str], order: SortOrder) -> Vec<String> {
+        let mut paths = names.iter().map(PathBuf::from).collect::<Vec<_>>();
+        sort::sort_paths(&mut paths, order);
+        paths.iter().map(|path| path.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn name_sort_compares_numbers_by_value() {
+        let names = ["page10.pdf", "page2.pdf", "page1.pdf", "Page3.pdf"];
+
+        assert_eq!(sorted(&names, SortOrder::Name), ["page1.pdf", "page2.pdf", "Page3.pdf", "page10.pdf"]);
+        assert_eq!(sorted(&names, SortOrder::NameDesc), ["page10.pdf", "Page3.pdf", "page2.pdf", "page1.pdf"]);
+    }
+
+    #[test]
+    fn name_sort_orders_zero_padded_numbers_by_value_then_padding() {
+        let names = ["scan010.pdf", "scan2.pdf", "scan002.pdf", "scan02.pdf", "scan1.pdf"];
+
+        assert_eq!(
+            sorted(&names, SortOrder::Name),
+            ["scan1.pdf", "scan2.pdf", "scan02.pdf", "scan002.pdf", "scan010.pdf"]
+        );
+    }
+
+    #[test]
+    fn name_sort_handles_mixed_letters_and_numbers() {
+        let names = ["ch2-part10.pdf", "ch10-part1.pdf", "ch2-part9.pdf", "ch2.pdf", "appendix.pdf", "ch2a.pdf"];
+
+        assert_eq!(
+            sorted(&names, SortOrder::Name),
+            ["appendix.pdf", "ch2-part9.pdf", "ch2-part10.pdf", "ch2.pdf", "ch2a.pdf", "ch10-part1.pdf"]
+        );
+    }
+
+    #[test]
+    fn interleave_alternates_pages() {
+        let merged = interleave(Interleave::Alternate, vec![labeled_document("A", 3), labeled_document("B", 2)]);
+
+        assert_eq!(page_labels(&merged.unwrap()), ["A1", "B1", "A2", "B2", "A3"]);
+    }
+
+    #[test]
+    fn interleave_can_reverse_the_second_document() {
+        let merged = interleave(Interleave::ReverseSecond, vec![labeled_document("A", 3), labeled_document("B", 3)]);
+
+        assert_eq!(page_labels(&merged.unwrap()), ["A1", "B3", "A2", "B2", "A3", "B1"]);
+    }
+
+    #[test]
+    fn split_writes_each_chunk_of_pages_to_its_own_document() {
+        let parts = split_pdf(&labeled_document("A", 5), 2).unwrap();
+
+        let labels = parts.iter().map(page_labels).collect::<Vec<_>>();
+        assert_eq!(labels, [vec!["A1", "A2"], vec!["A3", "A4"], vec!["A5"]]);
+        assert!(parts.iter().all(|part| count_type(part, "Catalog") == 1 && part.get_toc().is_err()));
+    }
+
+    #[test]
+    fn split_parts_only_hold_what_their_pages_use() {
+        let mut doc = document(3);
+        for page_id in doc.page_iter().collect::<Vec<_>>() {
+            let content = doc.add_object(Stream::new(Dictionary::new(), b"0 0 m 10 10 l S".to_vec()));
+            let page = doc.get_dictionary_mut(page_id).unwrap();
+            page.remove(b"MediaBox");
+            page.set("Contents", content);
+        }
+        // Every page inherits its size from the page tree.
+        let pages_id = doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        let media_box = vec![0.into(), 0.into(), 300.into(), 400.into()];
+        doc.get_dictionary_mut(pages_id).unwrap().set("MediaBox", media_box);
+
+        let parts = split_pdf(&doc, 1).unwrap();
+        assert_eq!(parts.len(), 3);
+        for part in &parts {
+            let streams = part.objects.values().filter(|object| matches!(object, Object::Stream(_))).count();
+            assert_eq!(streams, 1);
+            assert_eq!(media_boxes(part), [[0.0, 0.0, 300.0, 400.0]]);
+            assert!(validate::dangling_references(part).is_empty());
+        }
+    }
+
+    #[test]
+    fn page_hook_sees_every_page_in_merged_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&seen);
+        let options = MergeOptions::default()
+            .separator(Some(Separator::Blank))
+            .page_hook(move |index, page| {
+                let label = page.get(b"Label").ok().map(|label| label.as_str().unwrap().to_vec());
+                recorded.lock().unwrap().push((index, label));
+                page.set("Rotate", 90);
+            });
+        let documents = vec![(String::new(), labeled_document("A", 2)), (String::new(), labeled_document("B", 1))];
+        let merged = merge_pdf(documents, &options).unwrap();
+
+        let label = |label: &str| Some(label.as_bytes().to_vec());
+        assert_eq!(*seen.lock().unwrap(), [(0, label("A1")), (1, label("A2")), (2, None), (3, label("B1"))]);
+        let rotations = merged
+            .page_iter()
+            .map(|page_id| merged.get_dictionary(page_id).unwrap().get(b"Rotate").unwrap().as_i64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(rotations, [90; 4]);
+    }
+
+    #[test]
+    fn merge_stops_at_a_file_that_does_not_load() {
+        let hooked = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&hooked);
+        let options = MergeOptions::new().page_hook(move |_, _| *counter.lock().unwrap() += 1);
+        let missing = Path::new("tests/fixtures/missing.pdf");
+        let result = options.merge([Path::new("tests/fixtures/two_pages.pdf"), missing, Path::new("tests/fixtures/outline.pdf")]);
+
+        assert!(matches!(result, Err(MergeError::Load { ref path, .. }) if path == missing));
+        // The merge gave up before finishing the pages of the file loaded ahead of it.
+        assert_eq!(*hooked.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn linearized_output_starts_with_the_first_page() {
+        let documents = vec![(String::new(), labeled_document("A", 2)), (String::new(), labeled_document("B", 3))];
+        let merged = merge_pdf(documents, &MergeOptions::default()).unwrap();
+        let mut bytes = Vec::new();
+        save_linearized(&merged, &mut bytes).unwrap();
+
+        let reloaded = Document::load_mem(&bytes).unwrap();
+        assert_eq!(page_labels(&reloaded), ["A1", "A2", "B1", "B2", "B3"]);
+        let linearization = reloaded
+            .objects
+            .values()
+            .find_map(|object| object.as_dict().ok().filter(|dict| dict.has(b"Linearized")))
+            .unwrap();
+        let value = |key: &[u8]| linearization.get(key).unwrap().as_i64().unwrap();
+        assert_eq!(value(b"L"), bytes.len() as i64);
+        assert_eq!(value(b"N"), 5);
+        assert_eq!(value(b"O"), reloaded.page_iter().next().unwrap().0 as i64);
+        // The first page ends the first page section, and the main cross-reference table
+        // follows the rest.
+        let first_page_end = value(b"E") as usize;
+        assert!(bytes[..first_page_end].ends_with(b"endobj\n"));
+        let main_xref = value(b"T") as usize;
+        assert!(bytes[main_xref..].starts_with(b"\n0000000000 65535 f"));
+        let hint_stream = linearization.get(b"H").unwrap().as_array().unwrap();
+        let [hint_offset, hint_length] = [0, 1].map(|index| hint_stream[index].as_i64().unwrap() as usize);
+        assert!(bytes[hint_offset..hint_offset + hint_length].ends_with(b"endstream\nendobj\n"));
+    }
+
+    #[test]
+    fn pretty_output_is_uncompressed_with_a_classic_xref_table() {
+        let documents = vec![(String::new(), font_document("Helvetica")), (String::new(), font_document("Courier"))];
+        let merged = merge_pdf(documents, &MergeOptions::default()).unwrap();
+        let mut bytes = Vec::new();
+        save_pretty(&merged, &mut bytes).unwrap();
+
+        let text = String::from_utf8_lossy(&bytes);
+        assert_eq!(text.matches("BT /F1 12 Tf (Hello) Tj ET").count(), 2);
+        assert_eq!(text.matches("% Page\n").count(), 2);
+        assert!(text.contains("\n  /BaseFont /Courier\n"));
+        assert!(!text.contains("/ObjStm") && !text.contains("/FlateDecode"));
+
+        let reloaded = Document::load_mem(&bytes).unwrap();
+        assert_eq!(reloaded.get_pages().len(), 2);
+        for (number, generation) in reloaded.objects.keys() {
+            let Some(lopdf::xref::XrefEntry::Normal { offset, .. }) = reloaded.reference_table.get(*number) else {
+                panic!("object {} isn't in the cross-reference table", number);
+            };
+            assert!(bytes[*offset as usize..].starts_with(format!("{} {} obj\n", number, generation).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn page_ids_follow_the_merged_page_order() {
+        let merged = interleave(Interleave::Alternate, vec![labeled_document("A", 2), labeled_document("B", 2)]).unwrap();
+
+        let labels = page_ids(&merged)
+            .into_iter()
+            .map(|id| merged.get_dictionary(id).unwrap().get(b"Label").unwrap().as_str().unwrap().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, [b"A1", b"B1", b"A2", b"B2"]);
+    }
+
+    #[test]
+    fn interleave_needs_two_documents() {
+        let documents = vec![document(1), document(1), document(1)];
+
+        assert_eq!(
+            interleave(Interleave::Alternate, documents).unwrap_err(),
+            MergeError::InterleaveNeedsTwo(3)
+        );
+    }
+
+    /// The strings shown on a page, in drawing order.
+    fn page_text(doc: &Document, page_id: ObjectId) -> Vec<String> {
+        doc.get_and_decode_page_content(page_id)
+            .unwrap()
+            .operations
+            .iter()
+            .filter(|operation| operation.operator == "Tj")
+            .map(|operation| String::from_utf8(operation.operands[0].as_str().unwrap().to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn toc_lists_each_document_with_its_first_page() {
+        let documents = vec![
+            ("intro".to_string(), document(2)),
+            ("empty".to_string(), document(0)),
+            ("appendix".to_string(), document(3)),
+        ];
+        let options = MergeOptions { toc: true, ..MergeOptions::default() };
+        let merged = merge_pdf(documents, &options).unwrap();
+        let pages = merged.get_pages();
+
+        assert_eq!(pages.len(), 6);
+        assert_eq!(page_text(&merged, pages[&1]), ["Contents", "intro", "2", "appendix", "4"]);
+        assert_eq!(top_level_titles(&merged), ["intro", "appendix"]);
+    }
+
+    /// A document with a page per entry of `contents`, each with its own content stream and
+    /// its own copy of the same font.
+    fn content_pages(contents: &[&str]) -> Document {
+        let mut doc = document(contents.len());
+        for (page_id, content) in doc.get_pages().into_values().zip(contents) {
+            let content = doc.add_object(Stream::new(Dictionary::new(), content.as_bytes().to_vec()));
+            let font = doc.add_object(dictionary! { "Type" => "Font", "Subtype" => "Type1", "BaseFont" => "Helvetica" });
+            let page = doc.get_dictionary_mut(page_id).unwrap();
+            page.set("Contents", content);
+            page.set("Resources", dictionary! { "Font" => dictionary! { "F1" => font } });
+        }
+        doc
+    }
+
+    #[test]
+    fn dedup_pages_drops_pages_identical_to_the_one_before() {
+        let documents = vec![content_pages(&["A", "A", "B"]), content_pages(&["B", "C", "A"])]
+            .into_iter()
+            .map(|doc| (String::new(), doc))
+            .collect();
+        let merged = merge_pdf(documents, &MergeOptions::default().dedup_pages(true)).unwrap();
+
+        let contents = page_ids(&merged)
+            .into_iter()
+            .map(|id| merged.get_page_content(id).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, [b"A", b"B", b"C", b"A"]);
+        let pages = merged.get_dictionary(merged.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap()).unwrap();
+        assert_eq!(pages.get(b"Count").unwrap().as_i64().unwrap(), 4);
+        // The second document starts with a dropped page, so its bookmark goes to the page it repeats.
+        assert_eq!(top_level_titles(&merged), ["Page_1", "Page_2"]);
+        assert!(validate::dangling_references(&merged).is_empty());
+    }
+
+    #[test]
+    fn generated_pages_have_the_generated_page_size() {
+        let options = MergeOptions {
+            toc: true,
+            separator: Some(Separator::Blank),
+            generated_page_size: Some("a4".parse().unwrap()),
+            ..MergeOptions::default()
+        };
+        let merged = merge_pdf(vec![(String::new(), document(1)), (String::new(), document(1))], &options).unwrap();
+        let sizes = pages::page_ids(&merged)
+            .into_iter()
+            .map(|page_id| {
+                let [_, _, width, height] = pages::media_box(&merged, page_id);
+                (width.round(), height.round())
+            })
+            .collect::<Vec<_>>();
+
+        // The table of contents, the first document, the separator and the second document.
+        assert_eq!(sizes, [(595.0, 842.0), (612.0, 792.0), (595.0, 842.0), (612.0, 792.0)]);
+    }
+
+    #[test]
+    fn paper_sizes_parse_from_names_or_dimensions() {
+        assert_eq!("Letter".parse(), Ok(PaperSize::LETTER));
+        assert_eq!("500 x 700".parse(), Ok(PaperSize { width: 500.0, height: 700.0 }));
+        assert_eq!("legal".parse::<PaperSize>().unwrap().to_string(), "legal");
+        for invalid in ["b5", "500", "0x700", "500x-1", "20000x700"] {
+            assert!(invalid.parse::<PaperSize>().is_err(), "{}", invalid);
+        }
+    }
+
+    /// `document(page_count)` with a one-item outline, a page mode and document properties.
+    fn binder(page_count: usize) -> Document {
+        let mut doc = document(page_count);
+        let first_page = doc.get_pages()[&1];
+        let outlines_id = doc.new_object_id();
+        let item_id = doc.add_object(dictionary! {
+            "Title" => Object::string_literal("Chapter 1"),
+            "Parent" => outlines_id,
+            "Dest" => vec![first_page.into(), "Fit".into()],
+        });
+        doc.objects.insert(
+            outlines_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Outlines",
+                "First" => item_id,
+                "Last" => item_id,
+                "Count" => 1,
+            }),
+        );
+        let info_id = doc.add_object(dictionary! {
+            "Title" => Object::string_literal("Binder"),
+            "Author" => Object::string_literal("Records"),
+        });
+        doc.trailer.set("Info", info_id);
+
+        let catalog_id = doc.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        let catalog = doc.get_dictionary_mut(catalog_id).unwrap();
+        catalog.set("Outlines", outlines_id);
+        catalog.set("PageMode", "UseOutlines");
+        doc
+    }
+
+    fn info_entry(doc: &Document, key: &[u8]) -> String {
+        let info = doc.trailer.get(b"Info").unwrap().as_reference().unwrap();
+        lopdf::decode_text_string(doc.get_dictionary(info).unwrap().get(key).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn append_keeps_the_base_catalog_outline_and_properties() {
+        let options = MergeOptions {
+            metadata: Metadata { author: Some("Archive".to_string()), ..Metadata::default() },
+            ..MergeOptions::default()
+        };
+        let appended = vec![("new".to_string(), labeled_document("N", 2))];
+        let merged = append_pdf(binder(2), appended, &options).unwrap();
+
+        assert_eq!(merged.get_pages().len(), 4);
+        assert_eq!(merged.catalog().unwrap().get(b"PageMode").unwrap().as_name().unwrap(), b"UseOutlines");
+        assert_eq!(top_level_titles(&merged), ["Chapter 1", "new"]);
+        assert_eq!(info_entry(&merged, b"Title"), "Binder");
+        assert_eq!(info_entry(&merged, b"Author"), "Archive");
+
+        let pages = merged.get_pages();
+        let last = merged.get_dictionary(pages[&4]).unwrap().get(b"Label").unwrap();
+        assert_eq!(last.as_str().unwrap(), b"N2");
+    }
+
+    fn rotations(doc: &Document) -> Vec<i64> {
+        doc.page_iter()
+            .map(|page_id| doc.get_dictionary(page_id).unwrap().get(b"Rotate").unwrap().as_i64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rotation_adds_to_each_pages_own_rotation() {
+        // The first page inherits 90 from the page tree, the second overrides it with 270.
+        let mut doc = document(2);
+        let pages = doc.get_pages();
+        let pages_root = doc.catalog().unwrap().get(b"Pages").unwrap().as_reference().unwrap();
+        doc.get_dictionary_mut(pages_root).unwrap().set("Rotate", 90);
+        doc.get_dictionary_mut(pages[&2]).unwrap().set("Rotate", 270);
+
+        let per_file = "-90".parse().unwrap();
+        pages::rotate_pages(&mut doc, per_file);
+        assert_eq!(rotations(&doc), [0, 180]);
+
+        let options = MergeOptions { rotate: Rotation::from_degrees(450).unwrap(), ..MergeOptions::default() };
+        let merged = merge_pdf(vec![(String::new(), doc), (String::new(), document(1))], &options).unwrap();
+
+        assert_eq!(rotations(&merged), [90, 270, 90]);
+    }
+
+    #[test]
+    fn auto_rotate_turns_pages_with_the_other_orientation() {
+        // Portrait, landscape, and landscape already turned to display as portrait.
+        let mut doc = document(3);
+        let pages = doc.get_pages();
+        let landscape = vec![0.into(), 0.into(), 792.into(), 612.into()];
+        doc.get_dictionary_mut(pages[&2]).unwrap().set("MediaBox", landscape.clone());
+        let turned = doc.get_dictionary_mut(pages[&3]).unwrap();
+        turned.set("MediaBox", landscape);
+        turned.set("Rotate", 90);
+
+        let merge = |orientation| {
+            let options = MergeOptions { auto_rotate: Some(orientation), ..MergeOptions::default() };
+            let merged = merge_pdf(vec![(String::new(), doc.clone())], &options).unwrap();
+            merged
+                .page_iter()
+                .map(|page_id| merged.get_dictionary(page_id).unwrap().get(b"Rotate").map_or(0, |r| r.as_i64().unwrap()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(merge(Orientation::Portrait), [0, 90, 90]);
+        assert_eq!(merge(Orientation::Landscape), [90, 0, 180]);
+    }
+
+    #[test]
+    fn rotation_must_be_a_multiple_of_90() {
+        assert!("45".parse::<Rotation>().is_err());
+        assert_eq!("-180".parse::<Rotation>().unwrap().degrees(), 180);
+    }
+
+    #[test]
+    fn no_compress_leaves_generated_streams_unfiltered() {
+        let options = MergeOptions { toc: true, compress: false, ..MergeOptions::default() };
+        let merged = merge_pdf(vec![(String::new(), document(1))], &options).unwrap();
+
+        let streams = merged.objects.values().filter_map(|object| object.as_stream().ok()).collect::<Vec<_>>();
+        assert!(!streams.is_empty());
+        assert!(streams.iter().all(|stream| !stream.dict.has(b"Filter")));
+    }
+
+    /// A document that runs JavaScript when opened, from its name tree and from a link.
+    fn scripted_document() -> Document {
+        let mut doc = document(1);
+        let page = doc.get_pages()[&1];
+        let script = |code: &str| dictionary! { "S" => "JavaScript", "JS" => Object::string_literal(code) };
+
+        let open_action = doc.add_object(script("app.alert(1)"));
+        let link = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 0.into(), 100.into(), 20.into()],
+            "A" => script("app.alert(2)"),
+        });
+        doc.get_dictionary_mut(page).unwrap().set("Annots", vec![link.into()]);
+        let names = dictionary! {
+            "JavaScript" => dictionary! {
+                "Names" => vec![Object::string_literal("init"), script("app.alert(3)").into()],
+            },
+        };
+
+        let catalog_id = doc.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        let catalog = doc.get_dictionary_mut(catalog_id).unwrap();
+        catalog.set("OpenAction", open_action);
+        catalog.set("Names", names);
+        doc
+    }
+
+    #[test]
+    fn strip_js_removes_every_script() {
+        assert!(has_javascript(&scripted_document()));
+        assert!(has_javascript(&merge(vec![scripted_document()]).unwrap()));
+
+        let options = MergeOptions { strip_js: true, ..MergeOptions::default() };
+        let merged = merge_pdf(vec![(String::new(), scripted_document())], &options).unwrap();
+
+        assert!(!has_javascript(&merged));
+        assert!(!merged.catalog().unwrap().has(b"OpenAction"));
+        assert_eq!(merged.get_pages().len(), 1);
+    }
+
+    /// A one-page document with the given MediaBox.
+    fn sized_document(media_box: [i64; 4]) -> Document {
+        let mut doc = document(1);
+        let page = doc.get_pages()[&1];
+        let media_box = media_box.iter().map(|&value| value.into()).collect::<Vec<Object>>();
+        let content = doc.add_object(Stream::new(Dictionary::new(), b"0 0 m 10 10 l S".to_vec()));
+        let page = doc.get_dictionary_mut(page).unwrap();
+        page.set("MediaBox", media_box);
+        page.set("Contents", content);
+        doc
+    }
+
+    fn media_boxes(doc: &Document) -> Vec<[f32; 4]> {
+        doc.page_iter().map(|page_id| pages::media_box(doc, page_id)).collect()
+    }
+
+    fn resized(size: PageSize) -> Document {
+        let documents = vec![
+            (String::new(), sized_document([0, 0, 612, 792])),
+            (String::new(), sized_document([100, 100, 400, 400])),
+            (String::new(), sized_document([0, 0, 1000, 500])),
+        ];
+        let options = MergeOptions { page_size: Some(size), ..MergeOptions::default() };
+        merge_pdf(documents, &options).unwrap()
+    }
+
+    #[test]
+    fn page_size_gives_every_page_the_same_media_box() {
+        let a4 = [0.0, 0.0, 595.28, 841.89];
+        let a4_landscape = [0.0, 0.0, 841.89, 595.28];
+        assert_eq!(media_boxes(&resized(PageSize::A4)), [a4, a4, a4_landscape]);
+
+        let largest = [0.0, 0.0, 1000.0, 792.0];
+        assert_eq!(media_boxes(&resized(PageSize::FitLargest)), [largest, largest, largest]);
+    }
+
+    #[test]
+    fn page_size_scales_and_centres_the_content() {
+        let merged = resized(PageSize::Letter);
+        let page = merged.get_pages()[&2];
+        let content = merged.get_and_decode_page_content(page).unwrap();
+
+        // The 300pt square at (100, 100) is scaled to the sheet width and centred vertically.
+        assert_eq!(content.operations[0].operator, "q");
+        let cm = &content.operations[1];
+        assert_eq!(cm.operator, "cm");
+        let matrix = cm.operands.iter().map(|value| value.as_float().unwrap()).collect::<Vec<_>>();
+        assert_eq!(matrix, [2.04, 0.0, 0.0, 2.04, -204.0, -114.0]);
+    }
+
+    /// The matrices of the `cm` operators in the page's content, in order.
+    fn content_matrices(doc: &Document, page_id: ObjectId) -> Vec<Vec<f32>> {
+        let content = doc.get_and_decode_page_content(page_id).unwrap();
+        content
+            .operations
+            .iter()
+            .filter(|operation| operation.operator == "cm")
+            .map(|operation| operation.operands.iter().map(|value| value.as_float().unwrap()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn nup_puts_a_grid_of_pages_on_each_sheet() {
+        let imposed = |grid: &str, margin: f32| {
+            let documents = (0..5).map(|_| (String::new(), sized_document([0, 0, 612, 792]))).collect();
+            let nup = NUp { margin, ..grid.parse().unwrap() };
+            merge_pdf(documents, &MergeOptions::default().nup(Some(nup))).unwrap()
+        };
+
+        let merged = imposed("2x2", 0.0);
+        assert_eq!(media_boxes(&merged), [[0.0, 0.0, 612.0, 792.0]; 2]);
+        let sheets = merged.page_iter().collect::<Vec<_>>();
+        assert_eq!(sheets.iter().map(|&sheet| content_matrices(&merged, sheet).len()).collect::<Vec<_>>(), [4, 1]);
+        // The first page fills the top left quarter, the fourth the bottom right one.
+        let matrices = content_matrices(&merged, sheets[0]);
+        assert_eq!(matrices[0], [0.5, 0.0, 0.0, 0.5, 0.0, 396.0]);
+        assert_eq!(matrices[3], [0.5, 0.0, 0.0, 0.5, 306.0, 0.0]);
+        // Bookmarks go to the sheets, and no reference is left to the pages they replace.
+        assert!(validate::dangling_references(&merged).is_empty());
+        let catalog = merged.catalog().unwrap();
+        assert!(!catalog.has(b"PageLabels"));
+        let pages = merged.get_toc().unwrap().toc.iter().map(|entry| entry.page).collect::<Vec<_>>();
+        assert_eq!(pages, [1, 1, 1, 1, 2]);
+
+        // Two portrait pages side by side fit a landscape sheet better.
+        let merged = imposed("2x1", 10.0);
+        assert_eq!(media_boxes(&merged), [[0.0, 0.0, 792.0, 612.0]; 3]);
+    }
+
+    #[test]
+    fn nup_draws_rotated_pages_as_they_are_shown() {
+        let mut rotated = sized_document([0, 0, 612, 792]);
+        let page_id = rotated.page_iter().next().unwrap();
+        rotated.get_dictionary_mut(page_id).unwrap().set("Rotate", 90);
+        let documents = vec![(String::new(), rotated), (String::new(), sized_document([0, 0, 612, 792]))];
+        let options = MergeOptions::default().nup(Some("2x1".parse().unwrap()));
+        let merged = merge_pdf(documents, &options).unwrap();
+
+        // The sheet takes the landscape size the first page is shown at.
+        assert_eq!(media_boxes(&merged), [[0.0, 0.0, 792.0, 612.0]]);
+        let sheet = merged.page_iter().next().unwrap();
+        let matrices = content_matrices(&merged, sheet);
+        // Turned a quarter clockwise, the lower left corner of the page goes to the top left.
+        assert_eq!(matrices[0], [0.0, -0.5, 0.5, 0.0, 0.0, 459.0]);
+        assert_eq!(matrices[1], [0.6471, 0.0, 0.0, 0.6471, 396.0, 49.7647]);
+    }
+
+    /// Whether the page's resources have an entry `name` under `category`.
+    fn has_resource(doc: &Document, page_id: ObjectId, category: &[u8], name: &[u8]) -> bool {
+        let page = doc.get_dictionary(page_id).unwrap();
+        let (_, resources) = doc.dereference(page.get(b"Resources").unwrap()).unwrap();
+        resources
+            .as_dict()
+            .and_then(|resources| resources.get(category))
+            .and_then(|category| doc.dereference(category))
+            .and_then(|(_, category)| category.as_dict())
+            .is_ok_and(|category| category.has(name))
+    }
+
+    #[test]
+    fn text_watermark_is_drawn_on_every_page_including_separators() {
+        let options = MergeOptions {
+            separator: Some(Separator::Blank),
+            watermark: Some(Watermark {
+                content: WatermarkContent::Text("CONFIDENTIAL".to_string()),
+                opacity: 0.3,
+                angle: 45.0,
+            }),
+            ..MergeOptions::default()
+        };
+        let documents = vec![(String::new(), document(2)), (String::new(), document(1))];
+        let merged = merge_pdf(documents, &options).unwrap();
+        let pages = merged.get_pages();
+
+        assert_eq!(pages.len(), 4);
+        for page_id in pages.into_values() {
+            assert!(has_resource(&merged, page_id, b"ExtGState", b"MergePdfWatermark"));
+            assert!(has_resource(&merged, page_id, b"Font", b"MergePdfWatermark"));
+            assert_eq!(page_text(&merged, page_id), ["CONFIDENTIAL"]);
+        }
+    }
+
+    #[test]
+    fn image_watermark_keeps_transparency_as_a_soft_mask() {
+        let mut png = Vec::new();
+        let pixels = image::RgbaImage::from_pixel(2, 1, image::Rgba([255, 0, 0, 128]));
+        pixels.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+
+        let options = MergeOptions {
+            watermark: Some(Watermark {
+                content: WatermarkContent::Image(Box::new(WatermarkImage::from_bytes(&png).unwrap())),
+                opacity: 0.5,
+                angle: 0.0,
+            }),
+            ..MergeOptions::default()
+        };
+        let merged = merge_pdf(vec![(String::new(), document(1))], &options).unwrap();
+        let page = merged.get_pages()[&1];
+
+        assert!(has_resource(&merged, page, b"XObject", b"MergePdfWatermark"));
+        let image = merged
+            .objects
+            .values()
+            .filter_map(|object| object.as_stream().ok())
+            .find(|stream| stream.dict.has(b"SMask"))
+            .unwrap();
+        assert_eq!(image.dict.get(b"Width").unwrap().as_i64().unwrap(), 2);
+    }
+
+    #[test]
+    fn object_numbers_past_u32_max_are_an_error() {
+        assert_eq!(reserve_ids(1, 0), Ok(1));
+        assert_eq!(reserve_ids(u32::MAX - 10, 10), Ok(u32::MAX));
+        assert_eq!(reserve_ids(u32::MAX - 10, 11), Err(MergeError::TooManyObjects));
+        assert_eq!(reserve_ids(1, usize::MAX), Err(MergeError::TooManyObjects));
+    }
+
+    #[test]
+    fn empty_input_is_an_error() {
+        assert_eq!(merge(Vec::new()).unwrap_err(), MergeError::EmptyInput);
+    }
+
+    #[test]
+    fn missing_pages_root_is_an_error() {
+        let mut doc = Document::with_version("1.5");
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog" });
+        doc.trailer.set("Root", catalog_id);
+
+        assert_eq!(merge(vec![doc]).unwrap_err(), MergeError::NoPagesRoot);
+    }
+
+    #[test]
+    fn same_inputs_merge_to_identical_bytes() {
+        let options = MergeOptions {
+            dedup: true,
+            toc: true,
+            separator: Some(Separator::Blank),
+            page_numbers: Some(PageNumberPosition::BottomCenter),
+            keep_forms: true,
+            keep_attachments: true,
+            ..MergeOptions::default()
+        };
+        // Each merge uses fresh hash maps, with their own random seeds.
+        let outputs = (0..4)
+            .map(|_| {
+                let documents = vec![
+                    ("binder".to_string(), binder(2)),
+                    ("links".to_string(), linked_document()),
+                    ("scripted".to_string(), scripted_document()),
+                    ("labels".to_string(), labeled_pages(2, vec![(0, dictionary! { "S" => "r" })])),
+                ];
+                let mut bytes = Vec::new();
+                merge_pdf(documents, &options).unwrap().save_to(&mut bytes).unwrap();
+                bytes
+            })
+            .collect::<Vec<_>>();
+
+        assert!(outputs.windows(2).all(|pair| pair[0] == pair[1]));
+    }
+
+    #[test]
+    fn dangling_references_warn_or_fail_when_strict() {
+        let broken = || {
+            let mut doc = document(1);
+            let page = doc.get_pages()[&1];
+            doc.get_dictionary_mut(page).unwrap().set("Contents", (999, 0));
+            doc
+        };
+
+        let merged = merge(vec![document(1), broken()]).unwrap();
+        let dangling = dangling_references(&merged);
+        assert_eq!(dangling.len(), 1);
+        assert_eq!(dangling[0].from, Some(merged.get_pages()[&2]));
+        assert!(dangling_references(&merge(vec![document(2)]).unwrap()).is_empty());
+
+        let options = MergeOptions { strict: true, ..MergeOptions::default() };
+        let merged = merge_pdf(vec![(String::new(), broken())], &options);
+        assert_eq!(merged.unwrap_err(), MergeError::DanglingReferences(1));
+    }
+
+    #[test]
+    fn missing_catalog_is_an_error() {
+        let mut doc = document(1);
+        let catalog_id = doc.trailer.get(b"Root").unwrap().as_reference().unwrap();
+        doc.objects.remove(&catalog_id);
+
+        assert_eq!(merge(vec![doc]).unwrap_err(), MergeError::NoCatalogRoot);
+    }
+}
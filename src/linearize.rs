@@ -0,0 +1,560 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+use lopdf::{Dictionary, Document, Object, ObjectId, StringFormat};
+
+/// The catalog entries a viewer needs to open the document, whose objects go before the
+/// first page.
+const OPEN_DOCUMENT_KEYS: [&[u8]; 5] = [b"ViewerPreferences", b"PageMode", b"Threads", b"OpenAction", b"AcroForm"];
+
+/// The width the offsets and lengths of the linearization dictionary and the first-page
+/// trailer are padded to, as they are only known once everything after them is laid out.
+const NUMBER_WIDTH: usize = 10;
+
+/// Write `doc` as a linearized PDF, laid out as in annex F of the PDF specification.
+///
+/// The objects are written in this order: the linearization dictionary and the cross-reference
+/// table of the first page section; the catalog and what a viewer needs to open the document;
+/// the primary hint stream; the first page with everything it uses; each following page with
+/// the objects only it uses; the objects several pages share; and everything else, such as
+/// the page tree and the outline, followed by the main cross-reference table.
+///
+/// The hint stream has the page offset and shared object hint tables, with one group per
+/// shared object, but no outline or thumbnail hint tables.
+pub(crate) fn save_linearized<W: Write>(doc: &Document, target: &mut W) -> io::Result<()> {
+    let objects = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| {
+            let type_name = object.type_name().ok();
+            !matches!(type_name, Some("ObjStm" | "XRef" | "Linearized"))
+        })
+        .collect::<HashMap<_, _>>();
+    let catalog_id = doc
+        .trailer
+        .get(b"Root")
+        .and_then(Object::as_reference)
+        .ok()
+        .filter(|id| objects.contains_key(id))
+        .ok_or_else(|| invalid("the document has no catalog"))?;
+
+    let mut seen = HashSet::new();
+    let pages = doc
+        .page_iter()
+        .filter(|id| objects.contains_key(id) && seen.insert(*id))
+        .collect::<Vec<_>>();
+    if pages.is_empty() {
+        return Err(invalid("the document has no pages"));
+    }
+    // What only the catalog and the page tree lead to isn't part of any page.
+    let mut stops = pages.iter().copied().collect::<HashSet<_>>();
+    stops.insert(catalog_id);
+    stops.extend(
+        objects
+            .iter()
+            .filter(|(_, object)| object.type_name().ok() == Some("Pages"))
+            .map(|(id, _)| **id),
+    );
+
+    // The pages that use each object, in page order, and for each page the objects it uses,
+    // in the order they are found.
+    let mut users = HashMap::<ObjectId, Vec<usize>>::new();
+    let mut used = Vec::new();
+    for (index, page) in pages.iter().enumerate() {
+        let page_dict = objects[page].as_dict().map_err(|_| invalid("a page is not a dictionary"))?;
+        let mut start = Vec::new();
+        for (key, value) in page_dict.iter() {
+            if key != b"Parent" {
+                collect_references(value, &mut start);
+            }
+        }
+        let reached = reachable(&objects, &stops, start);
+        for id in &reached {
+            users.entry(*id).or_default().push(index);
+        }
+        used.push(reached);
+    }
+
+    // Part 4: the catalog, and what opening the document needs that no page uses.
+    let catalog = objects[&catalog_id].as_dict().map_err(|_| invalid("the catalog is not a dictionary"))?;
+    let mut start = Vec::new();
+    for key in OPEN_DOCUMENT_KEYS {
+        if let Ok(value) = catalog.get(key) {
+            collect_references(value, &mut start);
+        }
+    }
+    let mut open_document = vec![catalog_id];
+    open_document.extend(reachable(&objects, &stops, start).into_iter().filter(|id| !users.contains_key(id)));
+
+    // Part 6: the first page and everything it uses, shared or not.
+    let mut first_page = vec![pages[0]];
+    first_page.extend(&used[0]);
+
+    // Part 7: each other page and the objects only it uses.
+    let other_pages = (1..pages.len())
+        .map(|index| {
+            let mut page = vec![pages[index]];
+            page.extend(used[index].iter().filter(|id| users[*id] == [index]));
+            page
+        })
+        .collect::<Vec<_>>();
+
+    // Part 8: the objects several of the other pages share.
+    let mut shared = Vec::new();
+    let mut in_shared = HashSet::new();
+    for reached in &used[1..] {
+        for id in reached {
+            let page_users = &users[id];
+            if page_users.len() > 1 && page_users[0] != 0 && in_shared.insert(*id) {
+                shared.push(*id);
+            }
+        }
+    }
+
+    // Part 9: everything else, in ID order.
+    let placed = open_document
+        .iter()
+        .chain(&first_page)
+        .chain(other_pages.iter().flatten())
+        .chain(&shared)
+        .copied()
+        .collect::<HashSet<_>>();
+    let mut rest = objects.keys().map(|id| **id).filter(|id| !placed.contains(id)).collect::<Vec<_>>();
+    rest.sort_unstable();
+
+    // The main cross-reference table has the objects from the second page on, numbered from 1
+    // in file order; the first page section's table those before them, numbered after those.
+    let mut numbers = HashMap::new();
+    let main = other_pages.iter().flatten().chain(&shared).chain(&rest).copied().collect::<Vec<_>>();
+    for id in &main {
+        let number = numbers.len() as u32 + 1;
+        numbers.insert(*id, number);
+    }
+    let main_size = main.len() as u32 + 1;
+    let linearization_number = main_size;
+    for id in open_document.iter().chain(&first_page) {
+        let number = numbers.len() as u32 + 2;
+        numbers.insert(*id, number);
+    }
+    let hint_number = numbers.len() as u32 + 2;
+    let size = hint_number + 1;
+
+    let write_all = |ids: &[ObjectId]| {
+        ids.iter()
+            .map(|id| indirect_object(numbers[id], objects[id], &numbers))
+            .collect::<Vec<_>>()
+    };
+    let open_document_bytes = write_all(&open_document);
+    let first_page_bytes = write_all(&first_page);
+    let other_page_bytes = other_pages.iter().map(|page| write_all(page)).collect::<Vec<_>>();
+    let shared_bytes = write_all(&shared);
+    let rest_bytes = write_all(&rest);
+
+    let header = format!("%PDF-{}\n%\u{e2}\u{e3}\u{cf}\u{d3}\n", doc.version);
+    let header = header.chars().map(|c| c as u8).collect::<Vec<_>>();
+    let linearization_length = linearization_dictionary(linearization_number, &[0; 7]).len();
+    let first_xref_length = first_page_xref(doc, linearization_number, size, &numbers, &HashMap::new(), 0).len();
+
+    // Lay the file out without the hint stream, which the hint tables' offsets ignore.
+    let hint_offset = header.len() + linearization_length + first_xref_length + total_length(&open_document_bytes);
+    let first_page_offset = hint_offset;
+    let shared_offset = first_page_offset
+        + total_length(&first_page_bytes)
+        + other_page_bytes.iter().map(|page| total_length(page)).sum::<usize>();
+
+    let page_lengths = std::iter::once(&first_page_bytes)
+        .chain(&other_page_bytes)
+        .map(|page| total_length(page) as u64)
+        .collect::<Vec<_>>();
+    let page_object_counts = std::iter::once(&first_page)
+        .chain(&other_pages)
+        .map(|page| page.len() as u64)
+        .collect::<Vec<_>>();
+    // The shared object hint table lists the first page's objects, then part 8's.
+    let shared_indexes = first_page
+        .iter()
+        .chain(&shared)
+        .enumerate()
+        .map(|(index, id)| (*id, index as u64))
+        .collect::<HashMap<_, _>>();
+    let page_shared = std::iter::once(Vec::new())
+        .chain(used[1..].iter().map(|reached| {
+            reached
+                .iter()
+                .filter(|id| users[*id].len() > 1)
+                .filter_map(|id| shared_indexes.get(id).copied())
+                .collect()
+        }))
+        .collect::<Vec<Vec<u64>>>();
+    let group_lengths = first_page_bytes
+        .iter()
+        .chain(&shared_bytes)
+        .map(|bytes| bytes.len() as u64)
+        .collect::<Vec<_>>();
+
+    let mut hints = BitWriter::default();
+    page_offset_hints(
+        &mut hints,
+        first_page_offset as u64,
+        &page_object_counts,
+        &page_lengths,
+        &page_shared,
+        group_lengths.len() as u64,
+    );
+    let shared_table_offset = hints.bytes.len();
+    let first_shared = shared.first().map_or((0, 0), |id| (numbers[id] as u64, shared_offset as u64));
+    shared_object_hints(&mut hints, first_shared, first_page_bytes.len() as u64, &group_lengths);
+    let hint_stream = hint_stream(hint_number, shared_table_offset, &hints.bytes);
+
+    // The real offsets, with the hint stream in place.
+    let mut offsets = HashMap::new();
+    let mut position = header.len();
+    offsets.insert(linearization_number, position);
+    position += linearization_length;
+    let first_xref_offset = position;
+    position += first_xref_length;
+    let mut place = |ids: &[ObjectId], bytes: &[Vec<u8>], position: &mut usize| {
+        for (id, bytes) in ids.iter().zip(bytes) {
+            offsets.insert(numbers[id], *position);
+            *position += bytes.len();
+        }
+    };
+    place(&open_document, &open_document_bytes, &mut position);
+    let hint_position = position;
+    position += hint_stream.len();
+    place(&first_page, &first_page_bytes, &mut position);
+    let first_page_end = position;
+    for (page, bytes) in other_pages.iter().zip(&other_page_bytes) {
+        place(page, bytes, &mut position);
+    }
+    place(&shared, &shared_bytes, &mut position);
+    place(&rest, &rest_bytes, &mut position);
+    offsets.insert(hint_number, hint_position);
+
+    let main_xref_offset = position;
+    let mut main_xref = format!("xref\n0 {}\n", main_size).into_bytes();
+    // The offset of the end of line before the table's first entry.
+    let main_entries_offset = main_xref_offset + main_xref.len() - 1;
+    main_xref.extend_from_slice(b"0000000000 65535 f\r\n");
+    for number in 1..main_size {
+        main_xref.extend_from_slice(format!("{:010} 00000 n\r\n", offsets[&number]).as_bytes());
+    }
+    main_xref.extend_from_slice(format!("trailer\n<</Size {}>>\nstartxref\n{}\n%%EOF\n", main_size, first_xref_offset).as_bytes());
+    let file_length = main_xref_offset + main_xref.len();
+
+    let linearization = linearization_dictionary(
+        linearization_number,
+        &[
+            file_length,
+            hint_position,
+            hint_stream.len(),
+            numbers[&pages[0]] as usize,
+            first_page_end,
+            pages.len(),
+            main_entries_offset,
+        ],
+    );
+    let first_xref = first_page_xref(doc, linearization_number, size, &numbers, &offsets, main_xref_offset);
+    debug_assert_eq!(linearization.len(), linearization_length);
+    debug_assert_eq!(first_xref.len(), first_xref_length);
+
+    let mut output = Vec::with_capacity(file_length);
+    output.extend_from_slice(&header);
+    output.extend_from_slice(&linearization);
+    output.extend_from_slice(&first_xref);
+    open_document_bytes.iter().for_each(|bytes| output.extend_from_slice(bytes));
+    output.extend_from_slice(&hint_stream);
+    first_page_bytes.iter().for_each(|bytes| output.extend_from_slice(bytes));
+    other_page_bytes.iter().flatten().for_each(|bytes| output.extend_from_slice(bytes));
+    shared_bytes.iter().for_each(|bytes| output.extend_from_slice(bytes));
+    rest_bytes.iter().for_each(|bytes| output.extend_from_slice(bytes));
+    output.extend_from_slice(&main_xref);
+    target.write_all(&output)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn total_length(objects: &[Vec<u8>]) -> usize {
+    objects.iter().map(Vec::len).sum()
+}
+
+/// The objects reachable from `start` that aren't in `stops`, in the order they are found.
+fn reachable(objects: &HashMap<&ObjectId, &Object>, stops: &HashSet<ObjectId>, mut start: Vec<ObjectId>) -> Vec<ObjectId> {
+    start.reverse();
+    let mut stack = start;
+    let mut visited = HashSet::new();
+    let mut found = Vec::new();
+    while let Some(id) = stack.pop() {
+        if stops.contains(&id) || !visited.insert(id) {
+            continue;
+        }
+        let Some(object) = objects.get(&id) else {
+            continue;
+        };
+        found.push(id);
+        let mut references = Vec::new();
+        collect_references(object, &mut references);
+        stack.extend(references.into_iter().rev());
+    }
+    found
+}
+
+fn collect_references(object: &Object, references: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => references.push(*id),
+        Object::Array(items) => items.iter().for_each(|item| collect_references(item, references)),
+        Object::Dictionary(dict) => dict.iter().for_each(|(_, value)| collect_references(value, references)),
+        Object::Stream(stream) => stream.dict.iter().for_each(|(_, value)| collect_references(value, references)),
+        _ => {}
+    }
+}
+
+/// The linearization dictionary with `values` for `L`, `H` (two), `O`, `E`, `N` and `T`.
+fn linearization_dictionary(number: u32, values: &[usize; 7]) -> Vec<u8> {
+    let [length, hint_offset, hint_length, first_page, first_page_end, page_count, main_xref] =
+        values.map(|value| format!("{:width$}", value, width = NUMBER_WIDTH));
+    format!(
+        "{} 0 obj\n<</Linearized 1/L {}/H [{} {}]/O {}/E {}/N {}/T {}>>\nendobj\n",
+        number, length, hint_offset, hint_length, first_page, first_page_end, page_count, main_xref
+    )
+    .into_bytes()
+}
+
+/// The first page section's cross-reference table and the full trailer. Without `offsets`,
+/// the entries are zero, which doesn't change the length.
+fn first_page_xref(
+    doc: &Document,
+    first: u32,
+    size: u32,
+    numbers: &HashMap<ObjectId, u32>,
+    offsets: &HashMap<u32, usize>,
+    main_xref_offset: usize,
+) -> Vec<u8> {
+    let mut xref = format!("xref\n{} {}\n", first, size - first).into_bytes();
+    for number in first..size {
+        let offset = offsets.get(&number).copied().unwrap_or(0);
+        xref.extend_from_slice(format!("{:010} 00000 n\r\n", offset).as_bytes());
+    }
+
+    let mut trailer = Dictionary::new();
+    trailer.set("Size", size as i64);
+    for key in [b"Root".as_slice(), b"Info", b"ID"] {
+        if let Ok(value) = doc.trailer.get(key) {
+            trailer.set(key.to_vec(), renumbered(value, numbers));
+        }
+    }
+    xref.extend_from_slice(b"trailer\n<<");
+    for (key, value) in trailer.iter() {
+        write_name(&mut xref, key);
+        xref.push(b' ');
+        write_object(&mut xref, value);
+    }
+    xref.extend_from_slice(format!("/Prev {:width$}>>\nstartxref\n0\n%%EOF\n", main_xref_offset, width = NUMBER_WIDTH).as_bytes());
+    xref
+}
+
+fn hint_stream(number: u32, shared_table_offset: usize, data: &[u8]) -> Vec<u8> {
+    let mut bytes = format!("{} 0 obj\n<</Length {}/S {}>>\nstream\n", number, data.len(), shared_table_offset).into_bytes();
+    bytes.extend_from_slice(data);
+    bytes.extend_from_slice(b"\nendstream\nendobj\n");
+    bytes
+}
+
+/// Write the page offset hint table. Content stream offsets and lengths aren't tracked: like
+/// other writers, this gives each page's content as starting at the page and as long as it.
+fn page_offset_hints(
+    hints: &mut BitWriter,
+    first_page_offset: u64,
+    object_counts: &[u64],
+    lengths: &[u64],
+    shared: &[Vec<u64>],
+    shared_total: u64,
+) {
+    let min_objects = object_counts.iter().copied().min().unwrap_or(0);
+    let object_bits = bits_for(object_counts.iter().max().unwrap_or(&0) - min_objects);
+    let min_length = lengths.iter().copied().min().unwrap_or(0);
+    let length_bits = bits_for(lengths.iter().max().unwrap_or(&0) - min_length);
+    let shared_count_bits = bits_for(shared.iter().map(|page| page.len() as u64).max().unwrap_or(0));
+    let shared_index_bits = bits_for(shared_total);
+
+    hints.write(min_objects, 32);
+    hints.write(first_page_offset, 32);
+    hints.write(object_bits as u64, 16);
+    hints.write(min_length, 32);
+    hints.write(length_bits as u64, 16);
+    // Content stream offsets.
+    hints.write(0, 32);
+    hints.write(0, 16);
+    // Content stream lengths.
+    hints.write(min_length, 32);
+    hints.write(length_bits as u64, 16);
+    hints.write(shared_count_bits as u64, 16);
+    hints.write(shared_index_bits as u64, 16);
+    // Numerators of the fractions of shared objects a page needs before it can be drawn.
+    hints.write(0, 16);
+    hints.write(4, 16);
+
+    object_counts.iter().for_each(|&count| hints.write(count - min_objects, object_bits));
+    hints.align();
+    lengths.iter().for_each(|&length| hints.write(length - min_length, length_bits));
+    hints.align();
+    shared.iter().for_each(|page| hints.write(page.len() as u64, shared_count_bits));
+    hints.align();
+    shared.iter().flatten().for_each(|&index| hints.write(index, shared_index_bits));
+    hints.align();
+    lengths.iter().for_each(|&length| hints.write(length - min_length, length_bits));
+    hints.align();
+}
+
+/// Write the shared object hint table, with one group per object and no signatures.
+fn shared_object_hints(hints: &mut BitWriter, (first_number, first_offset): (u64, u64), first_page_count: u64, lengths: &[u64]) {
+    let min_length = lengths.iter().copied().min().unwrap_or(0);
+    let length_bits = bits_for(lengths.iter().max().unwrap_or(&0) - min_length);
+
+    hints.write(first_number, 32);
+    hints.write(first_offset, 32);
+    hints.write(first_page_count, 32);
+    hints.write(lengths.len() as u64, 32);
+    hints.write(0, 16);
+    hints.write(min_length, 32);
+    hints.write(length_bits as u64, 16);
+
+    lengths.iter().for_each(|&length| hints.write(length - min_length, length_bits));
+    hints.align();
+    lengths.iter().for_each(|_| hints.write(0, 1));
+    hints.align();
+}
+
+/// The number of bits needed to write `value`.
+fn bits_for(value: u64) -> u32 {
+    u64::BITS - value.leading_zeros()
+}
+
+/// Packs values into bytes, most significant bit first, as hint tables are.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// The number of bits of the last byte in use, or 0 if it's full.
+    used: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        for bit in (0..bits).rev() {
+            if self.used == 0 {
+                self.bytes.push(0);
+            }
+            let last = self.bytes.last_mut().expect("a byte was just pushed");
+            *last |= (((value >> bit) & 1) as u8) << (7 - self.used);
+            self.used = (self.used + 1) % 8;
+        }
+    }
+
+    /// Start the next value on a new byte.
+    fn align(&mut self) {
+        self.used = 0;
+    }
+}
+
+fn indirect_object(number: u32, object: &Object, numbers: &HashMap<ObjectId, u32>) -> Vec<u8> {
+    let mut bytes = format!("{} 0 obj\n", number).into_bytes();
+    write_object(&mut bytes, &renumbered(object, numbers));
+    bytes.extend_from_slice(b"\nendobj\n");
+    bytes
+}
+
+/// `object` with its references renumbered, and those to objects that aren't written replaced
+/// by `null`, so they can't point at whatever takes their number.
+fn renumbered(object: &Object, numbers: &HashMap<ObjectId, u32>) -> Object {
+    let mut object = object.clone();
+    renumber(&mut object, numbers);
+    object
+}
+
+fn renumber(object: &mut Object, numbers: &HashMap<ObjectId, u32>) {
+    match object {
+        Object::Reference(id) => {
+            *object = match numbers.get(id) {
+                Some(number) => Object::Reference((*number, 0)),
+                None => Object::Null,
+            }
+        }
+        Object::Array(items) => items.iter_mut().for_each(|item| renumber(item, numbers)),
+        Object::Dictionary(dict) => dict.iter_mut().for_each(|(_, value)| renumber(value, numbers)),
+        Object::Stream(stream) => stream.dict.iter_mut().for_each(|(_, value)| renumber(value, numbers)),
+        _ => {}
+    }
+}
+
+/// Serialize `object` as lopdf's writer does, which isn't public.
+pub(crate) fn write_object(bytes: &mut Vec<u8>, object: &Object) {
+    match object {
+        Object::Null => bytes.extend_from_slice(b"null"),
+        Object::Boolean(value) => bytes.extend_from_slice(if *value { b"true" } else { b"false" }),
+        Object::Integer(value) => bytes.extend_from_slice(value.to_string().as_bytes()),
+        Object::Real(value) => bytes.extend_from_slice(value.to_string().as_bytes()),
+        Object::Name(name) => write_name(bytes, name),
+        Object::String(text, StringFormat::Literal) => {
+            bytes.push(b'(');
+            for &byte in text {
+                match byte {
+                    b'(' | b')' | b'\\' => bytes.extend_from_slice(&[b'\\', byte]),
+                    b'\r' => bytes.extend_from_slice(b"\\r"),
+                    _ => bytes.push(byte),
+                }
+            }
+            bytes.push(b')');
+        }
+        Object::String(text, StringFormat::Hexadecimal) => {
+            bytes.push(b'<');
+            text.iter().for_each(|byte| bytes.extend_from_slice(format!("{:02X}", byte).as_bytes()));
+            bytes.push(b'>');
+        }
+        Object::Array(items) => {
+            bytes.push(b'[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    bytes.push(b' ');
+                }
+                write_object(bytes, item);
+            }
+            bytes.push(b']');
+        }
+        Object::Dictionary(dict) => write_dictionary(bytes, dict),
+        Object::Stream(stream) => {
+            let mut dict = stream.dict.clone();
+            dict.set("Length", stream.content.len() as i64);
+            write_dictionary(bytes, &dict);
+            bytes.extend_from_slice(b"stream\n");
+            bytes.extend_from_slice(&stream.content);
+            bytes.extend_from_slice(b"\nendstream");
+        }
+        Object::Reference((number, generation)) => {
+            bytes.extend_from_slice(format!("{} {} R", number, generation).as_bytes());
+        }
+    }
+}
+
+fn write_dictionary(bytes: &mut Vec<u8>, dict: &Dictionary) {
+    bytes.extend_from_slice(b"<<");
+    for (key, value) in dict.iter() {
+        write_name(bytes, key);
+        bytes.push(b' ');
+        write_object(bytes, value);
+    }
+    bytes.extend_from_slice(b">>");
+}
+
+pub(crate) fn write_name(bytes: &mut Vec<u8>, name: &[u8]) {
+    bytes.push(b'/');
+    for &byte in name {
+        // Delimiters, white-space and anything outside `!` to `~` are written as `#XX`.
+        if b" \t\n\r\x0C()<>[]{}/%#".contains(&byte) || !(33..=126).contains(&byte) {
+            bytes.extend_from_slice(format!("#{:02X}", byte).as_bytes());
+        } else {
+            bytes.push(byte);
+        }
+    }
+}
@@ -1,224 +1,1928 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
 use clap::{Parser};
-use walkdir::WalkDir;
-use std::fs::File;
-use std::path::PathBuf;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{info, warn, LevelFilter};
+use rayon::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime};
 
-use std::collections::BTreeMap;
-use lopdf::{Document, Object, ObjectId, Bookmark};
+use lopdf::{Document, Object};
+use merge_pdf::{
+    append_pdf, has_javascript, FolderOptions, merge_pdf_streaming, pages, placeholder_document, BookmarkMode, Interleave, MergeOptions, Metadata, NUp, Orientation, PaperSize,
+    MergeError, OpenAction, OutlineItem, PageNumberPosition, PageSelection, PageSize, PdfVersion, Rotation, Separator, SortOrder,
+    Watermark, WatermarkContent, WatermarkImage, XmpMetadata,
+};
+
+// Exit codes, so that scripts can tell failures apart.
+/// Anything else that went wrong, such as an input or the output that can't be read or
+/// written, or invalid arguments.
+const FAILURE_EXIT_CODE: i32 = 1;
+/// There is nothing to merge.
+const NO_INPUT_EXIT_CODE: i32 = 2;
+/// The inputs were loaded but couldn't be merged, e.g. as none has a catalog.
+const MERGE_FAILED_EXIT_CODE: i32 = 3;
+/// The merged PDF was written but failed --verify.
+const VERIFY_FAILED_EXIT_CODE: i32 = 4;
+
+/// The size of the buffer the merged PDF is written through.
+const OUTPUT_BUFFER_SIZE: usize = 1 << 20;
+
+/// How many seconds to wait for a PDF given as a URL, unless --timeout says otherwise.
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// The name of the file in a scanned folder that lists the order to merge its PDFs in.
+const ORDER_FILE: &str = ".order";
+
+/// The content types a server may send a PDF with.
+const PDF_CONTENT_TYPES: [&str; 3] = ["application/pdf", "application/x-pdf", "application/octet-stream"];
 
 #[derive(Parser, Debug)]
 #[clap(name = "PDF Merger", about = "A tool to merge all PDFs in a given directory.")]
+#[clap(args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// The folder to search for PDF files. Uses the current folder if not specified.
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Without a command, the options are those of `merge`.
+    #[clap(flatten)]
+    merge: MergeArgs,
+
+    /// Log more details: -v for each file merged, -vv for merge internals.
+    #[clap(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Merge PDF files into one. This is what runs without a command.
+    Merge(Box<MergeArgs>),
+    /// Split a PDF into one file per page, or per --chunk pages.
+    Split(SplitArgs),
+    /// Show a PDF's page count, page sizes, document properties and what else it contains.
+    Info(InfoArgs),
+    /// Print the total number of pages of the PDFs a merge would take, picked with the same
+    /// input options, without merging them.
+    Count(Box<CountArgs>),
+}
+
+/// The options that pick the PDFs to merge and their order, which `count` takes too.
+#[derive(clap::Args, Debug)]
+struct InputArgs {
+    /// Folders and PDF files to merge, in this order, instead of --folder: each folder's PDFs
+    /// are found and sorted as --folder's would be, in its place in the list, and each file is
+    /// merged where it is given, with the same suffixes as --files. E.g.
+    /// `merge_pdf cover.pdf chapters/ appendix/ --output book.pdf`.
+    #[clap(value_name = "PATH", conflicts_with_all = ["folder", "files", "manifest"])]
+    paths: Vec<PathBuf>,
+
+    /// The folder to search for PDF files. Uses the current folder if not specified. May also be
+    /// a .zip archive, whose PDF entries are merged as if it were a folder.
     #[clap(long, value_parser, default_value = ".")]
     folder: PathBuf,
 
+    /// Only merge files whose name matches this glob, e.g. "report_2024_*.pdf".
+    #[clap(long)]
+    pattern: Option<glob::Pattern>,
+
+    /// Only merge files whose name matches this regular expression.
+    #[clap(long)]
+    regex: Option<regex::Regex>,
+
+    /// Skip files whose name matches this glob, e.g. "*_draft.pdf". Can be given more than once.
+    #[clap(long)]
+    exclude: Vec<glob::Pattern>,
+
+    /// Only merge scanned files modified at or after this time, given as RFC 3339, e.g.
+    /// "2024-01-01T09:00:00+01:00", or as a date, e.g. "2024-01-01", meaning its start in local
+    /// time. Entries of a ZIP archive count as modified when the archive was.
+    #[clap(long, value_name = "TIME", value_parser = parse_since)]
+    since: Option<SystemTime>,
+
+    /// Only merge scanned files modified at or before this time, given like --since; a date
+    /// means its end, so `--until 2024-06-30` includes that whole day.
+    #[clap(long, value_name = "TIME", value_parser = parse_until)]
+    until: Option<SystemTime>,
+
+    /// The order in which the found PDF files are merged.
+    #[clap(long, value_enum, default_value_t = SortOrder::Name)]
+    sort: SortOrder,
+
+    /// Merge the scanned PDFs named in this file first, one file name (or path relative to the
+    /// folder) per line, in that order; the others follow in --sort order. A file named
+    /// ".order" in the scanned folder is used the same way without this option. Blank lines and
+    /// lines starting with `#` are ignored.
+    #[clap(long, conflicts_with_all = ["manifest", "files"])]
+    preserve_source_order_file: Option<PathBuf>,
+
+    /// Merge the PDFs listed in this file, one path per line, in order, instead of scanning a
+    /// folder. Paths are relative to the manifest's folder and take the same suffixes as
+    /// --files. Blank lines and lines starting with `#` are ignored.
+    #[clap(long, conflicts_with_all = ["folder", "sort", "files"])]
+    manifest: Option<PathBuf>,
+
+    /// Reverse the final merge order. Applied after --sort, so `--sort name --reverse` merges in
+    /// descending name order; with --files it reverses the order the files were given in.
+    #[clap(long)]
+    reverse: bool,
+
+    /// Merge each file only once, even if it is listed twice or found twice through symlinks.
+    /// Paths are compared after resolving `.`, `..` and symlinks; the first occurrence is kept.
+    /// The same file with different page ranges or rotations still counts as distinct inputs.
+    #[clap(long)]
+    unique: bool,
+
+    /// Merge only the first N files, after filtering, sorting, --unique and --reverse. Handy
+    /// for previewing the merge of a large folder.
+    #[clap(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    limit: Option<usize>,
+
+    /// Skip input PDFs with fewer pages than this, e.g. `--min-pages 2` to leave out
+    /// single-page cover scans. Pages are counted as each file is loaded, so --limit counts the
+    /// files this skips; --cover and --back are never skipped.
+    #[clap(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    min_pages: Option<usize>,
+
+    /// Skip input PDFs with more pages than this, e.g. `--max-pages 50` to leave out huge files.
+    /// Counted like --min-pages.
+    #[clap(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_pages: Option<usize>,
+
+    /// Put all pages of this PDF at the very front, before the inputs. It isn't filtered,
+    /// sorted, reversed or counted by --limit, and keeps all its pages with --first-page-only.
+    #[clap(long)]
+    cover: Option<PathBuf>,
+
+    /// Put all pages of this PDF at the very end, after the inputs, e.g. for an appendix. Like
+    /// --cover it bypasses the ordering and filtering of the inputs.
+    #[clap(long)]
+    back: Option<PathBuf>,
+
+    /// Merge exactly these PDF files, in the order given, instead of scanning a folder.
+    /// Append `:RANGES` to a file to take only some of its pages, e.g. `report.pdf:1-3,7,10-`,
+    /// and `:rotate=DEGREES` to turn its pages, e.g. `scan.pdf:rotate=90`. One file may be
+    /// `-`, read from standard input, e.g. `cat a.pdf | merge_pdf --files - b.pdf`; it is merged
+    /// at its place in the list (which --reverse reverses too), with a `Page_N` bookmark. Files
+    /// may also be http:// or https:// URLs, downloaded before merging. A file with `*`, `?` or
+    /// `[` in it is a glob, e.g. "reports/*.pdf", expanded to the files it matches in name order,
+    /// for shells that don't expand it; each match takes the entry's suffixes.
+    #[clap(long, value_parser = parse_input, num_args = 1.., conflicts_with_all = ["folder", "sort"])]
+    files: Vec<Input>,
+
+    /// Don't fail when a glob in --files matches no file.
+    #[clap(long, requires = "files")]
+    allow_empty_glob: bool,
+
+    /// Scan subfolders of the folder too. This is the default.
+    #[clap(long, overrides_with = "no_recursive")]
+    recursive: bool,
+
+    /// Only merge PDFs directly inside the folder, not in its subfolders.
+    #[clap(long, overrides_with = "recursive")]
+    no_recursive: bool,
+
+    /// Only descend this many folder levels below the folder (1 = the folder itself). Unlimited by default.
+    #[clap(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), conflicts_with = "no_recursive")]
+    max_depth: Option<usize>,
+}
+
+#[derive(clap::Args, Debug)]
+struct MergeArgs {
+    #[clap(flatten)]
+    input: InputArgs,
+
     /// The output file to save the merged PDF. Defaults to "merged_output.pdf" in the current directory.
+    /// Use "-" to write the PDF to standard output.
     #[clap(long, value_parser)]
     output: Option<PathBuf>,
+
+    /// Save the merged PDF into this folder as "merged_YYYYMMDD_HHMMSS.pdf", named after the
+    /// current local time, instead of to --output. The folder is created if needed.
+    #[clap(long, value_parser, conflicts_with = "output")]
+    output_dir: Option<PathBuf>,
+
+    /// Split the merged PDF into files of at most this many pages each, named after the output
+    /// file: "merged_output_part1.pdf", "merged_output_part2.pdf" and so on. Each part is a
+    /// complete PDF, split like the split subcommand does.
+    #[clap(
+        long,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        conflicts_with_all = ["append_to", "verify", "print_hash"],
+    )]
+    chunk_pages: Option<usize>,
+
+    /// Read bookmark titles from this file, one `FILE = TITLE` per line, e.g.
+    /// `report.pdf = Q4 Financials`. FILE is matched against the path as given or just the file
+    /// name; files without a line are titled with their name. Blank lines and lines starting with
+    /// `#` are ignored.
+    #[clap(long)]
+    titles: Option<PathBuf>,
+
+    /// Merge only the first page of each input, or of its `:RANGES`, e.g. for an overview of
+    /// many reports. Combine with --toc to list them.
+    #[clap(long)]
+    first_page_only: bool,
+
+    /// Append the inputs to the end of this existing PDF, keeping its catalog, document
+    /// properties and outline. The result overwrites it (with --force) unless --output is given.
+    #[clap(long)]
+    append_to: Option<PathBuf>,
+
+    /// Alternate the pages of exactly two inputs: A1, B1, A2, B2, ... For scans with the odd pages
+    /// in one PDF and the even pages in another.
+    #[clap(long, conflicts_with_all = ["separator", "cover", "back"])]
+    interleave: bool,
+
+    /// With --interleave, take the second input's pages from last to first, as back sides are
+    /// usually scanned in reverse.
+    #[clap(long, requires = "interleave")]
+    interleave_reverse_second: bool,
+
+    /// Start the merged PDF with a table of contents page listing each input and the page it
+    /// starts on.
+    #[clap(long)]
+    toc: bool,
+
+    /// Turn every page clockwise by this many degrees (a multiple of 90), on top of its own
+    /// rotation and any `:rotate=` given with --files.
+    #[clap(long, allow_hyphen_values = true)]
+    rotate: Option<Rotation>,
+
+    /// Turn pages a quarter turn clockwise where needed so that every page is displayed as
+    /// portrait, or as landscape if given. Pages that already are, and square ones, are left
+    /// alone.
+    #[clap(long, value_enum, num_args = 0..=1, default_missing_value = "portrait")]
+    auto_rotate: Option<Orientation>,
+
+    /// How to title each input's bookmark: its file name (or its --titles entry), Page_N for
+    /// its first page's position, its own document title falling back to the file name, or
+    /// none for no outline at all.
+    #[clap(long, value_enum, default_value_t = BookmarkMode::Filename)]
+    bookmark_mode: BookmarkMode,
+
+    /// Don't generate bookmarks; the merged PDF has no outline. Short for --bookmark-mode none.
+    #[clap(long, conflicts_with = "bookmark_mode")]
+    no_bookmarks: bool,
+
+    /// How viewers first show the merged PDF: at the first page zoomed to fit, at the first
+    /// page, or with the bookmarks panel open.
+    #[clap(long, value_enum)]
+    open_action: Option<OpenAction>,
+
+    /// The PDF version declared by the merged file: 1.5 by default, or with
+    /// --match-input-version the highest input version.
+    #[clap(long, value_enum)]
+    pdf_version: Option<PdfVersion>,
+
+    /// Declare the highest PDF version of the inputs instead of 1.5, so newer inputs aren't
+    /// downgraded. --pdf-version takes precedence. Inputs newer than the merged file are
+    /// warned about either way, and -v logs how many inputs have each version.
+    #[clap(long)]
+    match_input_version: bool,
+
+    /// Insert a separator page between consecutive documents, e.g. for double-sided printing.
+    #[clap(long, value_enum)]
+    separator: Option<Separator>,
+
+    /// The size of the pages the merge generates: separators, the --toc pages and
+    /// --placeholder-on-error pages. One of a3, a4, a5, letter, legal or tabloid, or
+    /// WIDTHxHEIGHT in points, e.g. 500x700. Without it separators are the size of the page
+    /// before them, and the others are Letter.
+    #[clap(long)]
+    gen_page_size: Option<PaperSize>,
+
+    /// Stamp "Page N of M" at the bottom of every page.
+    #[clap(long)]
+    page_numbers: bool,
+
+    /// Where --page-numbers draws the page number.
+    #[clap(long, value_enum, default_value_t = PageNumberPosition::BottomCenter, requires = "page_numbers")]
+    page_numbers_pos: PageNumberPosition,
+
+    /// Write page contents and other streams uncompressed, for debugging or for tools that can't
+    /// read Flate streams. Streams that were already compressed in the inputs stay compressed.
+    #[clap(long)]
+    no_compress: bool,
+
+    /// Rename page resources whose names collide between inputs, e.g. two different fonts both
+    /// called /F1, so each name stands for one resource throughout the merged PDF, rewriting
+    /// the affected pages' content to match. Pages with content that can't be parsed, such as
+    /// inline images, are left as they are.
+    #[clap(long)]
+    normalize_resources: bool,
+
+    /// Place several pages on each sheet of the merged PDF, in a grid of COLUMNSxROWS such as
+    /// 2x2, left to right, then top to bottom. Pages are scaled to fit their cell, and each
+    /// sheet takes the size of its first page, turned landscape if that fits the grid better.
+    /// Applied after --page-size, --watermark and --page-numbers.
+    #[clap(long, value_name = "GRID")]
+    nup: Option<NUp>,
+
+    /// The space around and between the pages on an --nup sheet, in points.
+    #[clap(long, default_value_t = 0.0, value_parser = parse_margin, requires = "nup")]
+    nup_margin: f32,
+
+    /// Write a linearized ("fast web view") PDF, so viewers can show the first page before the
+    /// whole file has downloaded. Its hint stream has no outline or thumbnail hint tables.
+    #[clap(long, conflicts_with = "chunk_pages")]
+    linearize: bool,
+
+    /// Write a PDF laid out for reading while debugging: nothing compressed, one object per
+    /// section with a comment naming its type, one dictionary entry per line, and a classic
+    /// cross-reference table instead of object streams. Unlike --no-compress, streams that were
+    /// compressed in the inputs are decompressed too.
+    #[clap(long, visible_alias = "uncompressed-debug", conflicts_with = "linearize")]
+    pretty: bool,
+
+    /// Remove JavaScript from the merged PDF: scripts run on opening, from links and form
+    /// fields, and document-level scripts. Without this, inputs that contain JavaScript are
+    /// reported with a warning.
+    #[clap(long)]
+    strip_js: bool,
+
+    /// Resize every page to this size, scaling its content to fit. A4 and Letter are turned
+    /// landscape for landscape pages; fit-largest uses the largest width and height of any page.
+    #[clap(long, value_enum, ignore_case = true)]
+    page_size: Option<PageSize>,
+
+    /// Draw this text, e.g. "CONFIDENTIAL", across the middle of every page.
+    #[clap(long, conflicts_with = "watermark_image")]
+    watermark: Option<String>,
+
+    /// Draw this PNG or JPEG image across the middle of every page.
+    #[clap(long)]
+    watermark_image: Option<PathBuf>,
+
+    /// How opaque the watermark is, from 0 (invisible) to 1 (opaque).
+    #[clap(long, default_value_t = 0.3, value_parser = parse_opacity)]
+    watermark_opacity: f32,
+
+    /// The watermark's angle in degrees, counter-clockwise.
+    #[clap(long, default_value_t = 45.0, allow_hyphen_values = true)]
+    watermark_angle: f32,
+
+    /// Share identical fonts and images across the merged documents. Slower, but shrinks the output.
+    #[clap(long)]
+    dedup: bool,
+
+    /// Report pages whose content is byte-identical across the inputs, e.g. a report that is in
+    /// the folder twice, listing each file and page. Nothing is removed. Pages without content
+    /// aren't reported.
+    #[clap(long)]
+    find_duplicates: bool,
+
+    /// Drop each page that is identical to the page right before it, e.g. repeated blank or
+    /// boilerplate pages. Only exact matches of the content and resources are dropped, and
+    /// each is logged with -v.
+    #[clap(long)]
+    dedup_pages: bool,
+
+    /// Keep the form fields of every input, renaming fields whose names clash. Without this only
+    /// the last input's form fields stay fillable.
+    #[clap(long)]
+    keep_forms: bool,
+
+    /// Keep the files attached to every input, renaming attachments whose names clash. Without
+    /// this only the last input's attachments are kept.
+    #[clap(long)]
+    keep_attachments: bool,
+
+    /// Keep the viewer settings of the first input's catalog: ViewerPreferences, PageMode and
+    /// PageLayout. Otherwise the merged catalog only carries the page tree, outline, page
+    /// labels, names, form, XMP metadata, language and layers; other catalog entries of the inputs
+    /// are dropped. --append-to keeps the existing PDF's catalog whole either way.
+    #[clap(long)]
+    keep_first_catalog_metadata: bool,
+
+    /// Keep the structure trees of tagged (accessible) inputs, which describe their reading
+    /// order for screen readers, combined into one. Without this the merged PDF isn't tagged.
+    #[clap(long)]
+    retain_structure_tree: bool,
+
+    /// Draw form fields and annotations into the pages and remove them, for a final,
+    /// non-interactive copy. Any annotation with an appearance is drawn: filled-in form fields,
+    /// stamps, comments, highlights and so on. Links and annotations without an appearance are
+    /// removed.
+    #[clap(long)]
+    flatten: bool,
+
+    /// Replace the merged PDF's XMP metadata: keep the first input's, or write a fresh one
+    /// with --title, --author, --subject and --keywords. Without this the last input's is kept.
+    #[clap(long, value_enum)]
+    xmp: Option<XmpMetadata>,
+
+    /// The title stored in the merged PDF's document properties.
+    #[clap(long)]
+    title: Option<String>,
+
+    /// The author stored in the merged PDF's document properties.
+    #[clap(long)]
+    author: Option<String>,
+
+    /// The subject stored in the merged PDF's document properties.
+    #[clap(long)]
+    subject: Option<String>,
+
+    /// The keywords stored in the merged PDF's document properties.
+    #[clap(long)]
+    keywords: Option<String>,
+
+    /// The natural language of the merged PDF, as a language tag such as `en-US`, for screen
+    /// readers. Without it the last input's language, if any, is kept.
+    #[clap(long, value_parser = parse_lang)]
+    lang: Option<String>,
+
+    /// Overwrite the output file if it already exists.
+    #[clap(long)]
+    force: bool,
+
+    /// List the files that would be merged with their page counts, without writing anything.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Print the outline the merged PDF would have, as an indented tree of bookmark titles and
+    /// their target pages, without writing anything. Unlike --dry-run this runs the whole merge.
+    #[clap(long, conflicts_with_all = ["dry_run", "json"])]
+    dry_run_tree: bool,
+
+    /// Don't show a progress bar while loading files.
+    #[clap(long)]
+    quiet: bool,
+
+    /// Load at most this many PDFs in parallel. Defaults to the number of CPUs.
+    #[clap(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    jobs: Option<usize>,
+
+    /// Load and merge the inputs one at a time instead of loading them all in parallel first.
+    /// Slower, but only one input is held in memory alongside the merged PDF.
+    #[clap(long, conflicts_with = "jobs")]
+    low_memory: bool,
+
+    /// The password used to decrypt encrypted input PDFs.
+    #[clap(long)]
+    password: Option<String>,
+
+    /// The password for one specific input, as FILE=PASSWORD. FILE is matched against the path
+    /// as given or just the file name. Can be given more than once.
+    #[clap(long, value_parser = parse_file_password)]
+    file_password: Vec<(String, String)>,
+
+    /// Fail instead of only warning when the merged PDF has references to objects it doesn't
+    /// contain, which readers silently treat as missing content.
+    #[clap(long)]
+    strict: bool,
+
+    /// How many seconds to wait for each input given as a URL to download.
+    #[clap(long, default_value_t = DEFAULT_TIMEOUT_SECS)]
+    timeout: u64,
+
+    /// Skip PDFs that fail to load instead of aborting the whole merge.
+    #[clap(long)]
+    skip_errors: bool,
+
+    /// Put a page reading "Could not load FILE: ERROR" in place of each PDF that fails to
+    /// load, instead of aborting the whole merge, so the gap shows in the output.
+    #[clap(long, conflicts_with = "skip_errors")]
+    placeholder_on_error: bool,
+
+    /// Reload the merged PDF after writing it and check that it has every expected page and a
+    /// valid catalog. Also reports the output size against the inputs', which -v logs too.
+    #[clap(long)]
+    verify: bool,
+
+    /// Print the SHA-256 of the merged PDF once it is written. The same inputs and options give
+    /// the same bytes, and so the same hash, except with --xmp generate, which records the time.
+    #[clap(long)]
+    print_hash: bool,
+
+    /// Print a JSON summary of the run instead of the usual messages: the inputs with their page
+    /// counts and sizes, the totals, and the output path and size, or the error that stopped the
+    /// merge.
+    #[clap(long)]
+    json: bool,
 }
 
-fn merge_pdf(documents: Vec<Document>) -> Option<Document>
-{
-    // Define a starting `max_id` (will be used as start index for object_ids).
-    let mut max_id = 1;
-    let mut page_num = 1;
-    // Collect all Documents Objects grouped by a map
-    let mut documents_pages = BTreeMap::new();
-    let mut documents_objects = BTreeMap::new();
-    let mut document = Document::with_version("1.5");
-
-    for mut doc in documents {
-        let mut first = false;
-        doc.renumber_objects_with(max_id);
-
-        max_id = doc.max_id + 1;
-
-        documents_pages.extend(
-            doc
-                    .get_pages()
-                    .into_values()
-                    .map(|object_id| {
-                        if !first {
-                            let bookmark = Bookmark::new(format!("Page_{}", page_num), [0.0, 0.0, 1.0], 0, object_id);
-                            document.add_bookmark(bookmark, None);
-                            first = true;
-                            page_num += 1;
-                        }
-
-                        (
-                            object_id,
-                            doc.get_object(object_id).unwrap().to_owned(),
-                        )
-                    })
-                    .collect::<BTreeMap<ObjectId, Object>>(),
-        );
-        documents_objects.extend(doc.objects);
-    }
-
-    // "Catalog" and "Pages" are mandatory.
-    let mut catalog_object: Option<(ObjectId, Object)> = None;
-    let mut pages_object: Option<(ObjectId, Object)> = None;
-
-    // Process all objects except "Page" type
-    for (object_id, object) in documents_objects.iter() {
-        // We have to ignore "Page" (as are processed later), "Outlines" and "Outline" objects.
-        // All other objects should be collected and inserted into the main Document.
-        match object.type_name().unwrap_or("") {
-            "Catalog" => {
-                // Collect a first "Catalog" object and use it for the future "Pages".
-                catalog_object = Some((
-                    if let Some((id, _)) = catalog_object {
-                        id
-                    } else {
-                        *object_id
-                    },
-                    object.clone(),
-                ));
-            }
-            "Pages" => {
-                // Collect and update a first "Pages" object and use it for the future "Catalog"
-                // We have also to merge all dictionaries of the old and the new "Pages" object
-                if let Ok(dictionary) = object.as_dict() {
-                    let mut dictionary = dictionary.clone();
-                    if let Some((_, ref object)) = pages_object {
-                        if let Ok(old_dictionary) = object.as_dict() {
-                            dictionary.extend(old_dictionary);
-                        }
-                    }
+#[derive(clap::Args, Debug)]
+struct SplitArgs {
+    /// The PDF file to split.
+    input: PathBuf,
 
-                    pages_object = Some((
-                        if let Some((id, _)) = pages_object {
-                            id
-                        } else {
-                            *object_id
-                        },
-                        Object::Dictionary(dictionary),
-                    ));
-                }
+    /// The folder to write the parts to, as "page_0001.pdf" and so on, each numbered by its
+    /// first page. Created if needed.
+    #[clap(long, value_parser, default_value = ".")]
+    output_dir: PathBuf,
+
+    /// Put this many pages in each part.
+    #[clap(long, default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    chunk: usize,
+
+    /// Overwrite part files that already exist.
+    #[clap(long)]
+    force: bool,
+
+    /// The password used to decrypt the input PDF, if it is encrypted.
+    #[clap(long)]
+    password: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct CountArgs {
+    #[clap(flatten)]
+    input: InputArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct InfoArgs {
+    /// The PDF file to inspect, or `-` to read it from standard input.
+    input: PathBuf,
+
+    /// The password used to decrypt the input PDF, if it is encrypted.
+    #[clap(long)]
+    password: Option<String>,
+
+    /// Print the details as JSON.
+    #[clap(long)]
+    json: bool,
+}
+
+/// What a run did, printed by --json.
+#[derive(Serialize, Default)]
+struct Summary {
+    /// `ok` or `error`.
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    inputs: Vec<InputSummary>,
+    skipped: Vec<SkippedInput>,
+    /// Inputs that failed to load and were merged as a placeholder page, with --placeholder-on-error.
+    replaced: Vec<SkippedInput>,
+    total_pages: usize,
+    /// The combined size of the input files; inputs read from standard input aren't counted.
+    input_bytes: u64,
+    output: Option<String>,
+    /// The files written with --chunk-pages, in page order, instead of `output`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    chunks: Vec<String>,
+    output_bytes: Option<u64>,
+    /// The SHA-256 of the output, in hex, with --print-hash.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_sha256: Option<String>,
+    /// Each set of pages with identical content, with --find-duplicates.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    duplicate_pages: Vec<Vec<PageRef>>,
+}
+
+#[derive(Serialize)]
+struct InputSummary {
+    path: String,
+    pages: usize,
+    bytes: Option<u64>,
+}
+
+/// A page of an input file.
+#[derive(Serialize)]
+struct PageRef {
+    path: String,
+    page: u32,
+}
+
+#[derive(Serialize)]
+struct SkippedInput {
+    path: String,
+    error: String,
+}
+
+/// What `info` found in a PDF.
+#[derive(Serialize)]
+struct DocumentInfo {
+    path: String,
+    version: String,
+    pages: usize,
+    /// Each distinct page size, in points, in the order it first appears.
+    page_sizes: Vec<PageSizeCount>,
+    encrypted: bool,
+    outline: bool,
+    forms: bool,
+    attachments: bool,
+    /// The `/Info` entries that are text, such as `Title` and `Author`.
+    metadata: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct PageSizeCount {
+    width: f32,
+    height: f32,
+    pages: usize,
+}
+
+/// Passes writes on to `inner`, hashing what was written if `hasher` is set.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Option<Sha256>,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A PDF to merge, optionally restricted to some of its pages.
+#[derive(Clone, Debug)]
+struct Input {
+    path: PathBuf,
+    pages: Option<PageSelection>,
+    rotation: Rotation,
+}
+
+impl Input {
+    fn new(path: PathBuf) -> Self {
+        Input { path, pages: None, rotation: Rotation::default() }
+    }
+}
+
+/// Parse `path[:ranges][:rotate=degrees]`, with the suffixes in either order. A suffix only
+/// counts as page ranges if it looks like one, so paths that contain a colon (such as
+/// `C:\scans\a.pdf`) still work.
+fn parse_input(s: &str) -> Result<Input, String> {
+    let mut path = s;
+    let mut pages = None;
+    let mut rotation = None;
+
+    while let Some((rest, suffix)) = path.rsplit_once(':') {
+        if let Some(degrees) = suffix.strip_prefix("rotate=") {
+            if rotation.replace(degrees.parse()?).is_some() {
+                return Err(format!("{:?} has more than one rotation", s));
             }
-            "Page" => {}     // Ignored, processed later and separately
-            "Outlines" => {} // Ignored, not supported yet
-            "Outline" => {}  // Ignored, not supported yet
-            _ => {
-                document.objects.insert(*object_id, object.clone());
+        } else if !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit() || c == '-' || c == ',') {
+            if pages.replace(suffix.parse()?).is_some() {
+                return Err(format!("{:?} has more than one page selection", s));
             }
+        } else {
+            break;
         }
+        path = rest;
     }
 
-    // If no "Pages" object found, abort.
-    if pages_object.is_none() {
-        println!("Pages root not found.");
+    Ok(Input { path: PathBuf::from(path), pages, rotation: rotation.unwrap_or_default() })
+}
 
-        return None;
+fn parse_since(s: &str) -> Result<SystemTime, String> {
+    parse_time(s, false)
+}
+
+fn parse_until(s: &str) -> Result<SystemTime, String> {
+    parse_time(s, true)
+}
+
+/// An RFC 3339 time, or the start (or with `end_of_day` the last moment) of a `YYYY-MM-DD`
+/// date in local time.
+fn parse_time(s: &str, end_of_day: bool) -> Result<SystemTime, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.into());
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| format!("expected a date such as 2024-01-31 or an RFC 3339 time, got {:?}", s))?;
+    let day_start = |date: NaiveDate| date.and_time(NaiveTime::MIN).and_local_timezone(Local).earliest();
+    let time = if end_of_day {
+        date.succ_opt().and_then(day_start).map(|next_day| SystemTime::from(next_day) - Duration::from_nanos(1))
+    } else {
+        day_start(date).map(SystemTime::from)
+    };
+    time.ok_or_else(|| format!("{} doesn't exist in the local time zone", s))
+}
+
+fn parse_margin(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(margin) if margin.is_finite() && margin >= 0.0 => Ok(margin),
+        _ => Err(format!("expected a number of points, 0 or more, got {:?}", s)),
     }
+}
+
+fn parse_opacity(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(opacity) if (0.0..=1.0).contains(&opacity) => Ok(opacity),
+        _ => Err(format!("expected a number from 0 to 1, got {:?}", s)),
+    }
+}
 
-    // Iterate over all "Page" objects and collect into the parent "Pages" created before
-    for (object_id, object) in documents_pages.iter() {
-        if let Ok(dictionary) = object.as_dict() {
-            let mut dictionary = dictionary.clone();
-            dictionary.set("Parent", pages_object.as_ref().unwrap().0);
+/// Language tags aren't checked beyond being plain ASCII, e.g. `en-US` or `de`.
+fn parse_lang(s: &str) -> Result<String, String> {
+    if s.is_empty() || !s.bytes().all(|byte| byte.is_ascii_graphic()) {
+        return Err(format!("expected a language tag such as en-US, got {:?}", s));
+    }
+    Ok(s.to_string())
+}
 
-            document
-                    .objects
-                    .insert(*object_id, Object::Dictionary(dictionary));
+fn parse_file_password(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(file, password)| (file.to_string(), password.to_string()))
+        .ok_or_else(|| format!("expected FILE=PASSWORD, got {:?}", s))
+}
+
+impl InputArgs {
+    /// Whether input `index` of the `count` that `select_inputs` picked is --cover or --back,
+    /// which are taken whole rather than filtered by page count.
+    fn is_whole(&self, index: usize, count: usize) -> bool {
+        (self.cover.is_some() && index == 0) || (self.back.is_some() && index + 1 == count)
+    }
+
+    /// How --folder and the folders given as arguments are scanned.
+    fn folder_options(&self) -> FolderOptions {
+        FolderOptions {
+            pattern: self.pattern.clone(),
+            regex: self.regex.clone(),
+            exclude: self.exclude.clone(),
+            since: self.since,
+            until: self.until,
+            max_depth: self.max_depth.or(self.no_recursive.then_some(1)),
+            sort: self.sort,
+        }
+    }
+}
+
+impl MergeArgs {
+    /// Write `doc` to `target`, linearized with --linearize and readable with --pretty.
+    fn save_to<W: Write>(&self, doc: &mut Document, target: &mut W) -> io::Result<()> {
+        if self.linearize {
+            merge_pdf::save_linearized(doc, target)
+        } else if self.pretty {
+            merge_pdf::save_pretty(doc, target)
+        } else {
+            doc.save_to(target)
+        }
+    }
+
+    fn to_stdout(&self) -> bool {
+        self.output.as_deref() == Some(Path::new("-"))
+    }
+
+    /// Print `summary` as JSON, on stderr if the PDF itself goes to stdout.
+    fn print_json(&self, summary: &Summary) {
+        let json = serde_json::to_string_pretty(summary).expect("summary is serializable");
+        if self.to_stdout() {
+            eprintln!("{}", json);
+        } else {
+            println!("{}", json);
         }
     }
 
-    // If no "Catalog" found, abort.
-    if catalog_object.is_none() {
-        println!("Catalog root not found.");
+    /// Report a fatal error and exit, as a JSON summary with --json.
+    fn fail(&self, message: String) -> ! {
+        self.fail_with_code(message, FAILURE_EXIT_CODE)
+    }
+
+    /// Like `fail`, with a specific exit code.
+    fn fail_with_code(&self, message: String, code: i32) -> ! {
+        if self.json {
+            self.print_json(&Summary { status: "error", error: Some(message), ..Summary::default() });
+        } else {
+            eprintln!("{}.", message);
+        }
+        process::exit(code);
+    }
+
+    /// The password to try for `path`: a matching --file-password, else --password.
+    fn password_for(&self, path: &Path) -> Option<&str> {
+        let name = path.file_name().map(|name| name.to_string_lossy());
+        self.file_password
+            .iter()
+            .find(|(file, _)| Path::new(file) == path || name.as_deref() == Some(file.as_str()))
+            .map(|(_, password)| password.as_str())
+            .or(self.password.as_deref())
+    }
+
+    /// Whether `inputs` is a single PDF that nothing asks to change, so it can be written as it
+    /// is rather than merged, which would rebuild its catalog without the entries the merge
+    /// drops. Options that only choose how the output is written, such as --no-compress,
+    /// --linearize or --chunk-pages, or that keep parts of the inputs, such as --keep-forms,
+    /// don't count as changes. The default --bookmark-mode doesn't either: a lone input keeps its
+    /// own outline instead of having it nested under a bookmark for the file, while any other
+    /// mode, --no-bookmarks included, asks for the outline to be rebuilt.
+    fn passes_through(&self, inputs: &[Input]) -> bool {
+        let [input] = inputs else {
+            return false;
+        };
+        let metadata = [&self.title, &self.author, &self.subject, &self.keywords, &self.lang];
+        input.pages.is_none()
+            && input.rotation == Rotation::default()
+            && self.append_to.is_none()
+            && self.titles.is_none()
+            && self.bookmark_mode == BookmarkMode::default()
+            && !self.no_bookmarks
+            && !self.first_page_only
+            && !self.toc
+            && self.rotate.is_none()
+            && self.auto_rotate.is_none()
+            && self.open_action.is_none()
+            && self.pdf_version.is_none()
+            && self.separator.is_none()
+            && !self.page_numbers
+            && !self.normalize_resources
+            && self.nup.is_none()
+            && !self.strip_js
+            && self.page_size.is_none()
+            && self.watermark.is_none()
+            && self.watermark_image.is_none()
+            && !self.dedup
+            && !self.dedup_pages
+            && !self.flatten
+            && self.xmp.is_none()
+            && metadata.iter().all(|value| value.is_none())
+            && !self.strict
+    }
+}
+
+/// Load a PDF, decrypting it if it is encrypted.
+///
+/// Many encrypted PDFs only restrict permissions and open with an empty user password, so that
+/// is tried when no password was supplied.
+fn load_document(path: &Path, password: Option<&str>, timeout: Duration) -> Result<Document, String> {
+    let mut doc = read_document(path, timeout)?;
+    decrypt(&mut doc, password)?;
+    Ok(doc)
+}
+
+/// Load `path` without decrypting it; `-` reads standard input, and URLs are downloaded,
+/// giving up after `timeout`.
+fn read_document(path: &Path, timeout: Duration) -> Result<Document, String> {
+    if is_stdin(path) {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data).map_err(|err| err.to_string())?;
+        Document::load_mem(&data)
+    } else if let Some(url) = url(path) {
+        Document::load_mem(&download(url, timeout)?)
+    } else if let Some((archive, entry)) = zip_entry(path) {
+        Document::load_mem(&read_zip_entry(archive, &entry)?)
+    } else {
+        Document::load(path)
+    }
+    .map_err(|err| err.to_string())
+}
+
+/// Fetch the PDF at `url`, failing unless the server answers 200 with a PDF content type.
+fn download(url: &str, timeout: Duration) -> Result<Vec<u8>, String> {
+    let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+    let response = match agent.get(url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(code, response)) => {
+            return Err(format!("the server answered {} {}", code, response.status_text()))
+        }
+        Err(err) => return Err(err.to_string()),
+    };
+    if response.status() != 200 {
+        return Err(format!("the server answered {} {}", response.status(), response.status_text()));
+    }
+    let content_type = response.header("Content-Type").unwrap_or("");
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    if !PDF_CONTENT_TYPES.iter().any(|pdf| mime.eq_ignore_ascii_case(pdf)) {
+        return Err(format!("the server sent {:?}, not a PDF", content_type));
+    }
+
+    let mut data = Vec::new();
+    response.into_reader().read_to_end(&mut data).map_err(|err| err.to_string())?;
+    Ok(data)
+}
+
+fn decrypt(doc: &mut Document, password: Option<&str>) -> Result<(), String> {
+    if doc.is_encrypted() {
+        doc.decrypt(password.unwrap_or("")).map_err(|_| match password {
+            Some(_) => "the password is incorrect".to_string(),
+            None => "it is encrypted; supply its password with --password or --file-password".to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+/// `doc`, the only input, made ready to be written as it is instead of merged: its trailer
+/// keeps only what describes the document rather than the file it was read from, and its
+/// streams are compressed with `compress`.
+fn pass_through(mut doc: Document, compress: bool) -> Document {
+    let mut trailer = lopdf::Dictionary::new();
+    for key in [b"Root".as_slice(), b"Info", b"ID"] {
+        if let Ok(value) = doc.trailer.get(key) {
+            trailer.set(key, value.clone());
+        }
+    }
+    doc.trailer = trailer;
+    if compress {
+        doc.compress();
+    }
+    doc
+}
+
+/// Check that a reloaded output has a catalog with a page tree and `expected_pages` pages.
+fn verify_output(doc: &Document, expected_pages: usize) -> Result<(), String> {
+    let catalog = doc.catalog().map_err(|err| format!("its catalog doesn't resolve: {}", err))?;
+    catalog
+        .get(b"Pages")
+        .and_then(Object::as_reference)
+        .and_then(|pages| doc.get_dictionary(pages))
+        .map_err(|err| format!("its page tree doesn't resolve: {}", err))?;
+
+    let page_count = doc.get_pages().len();
+    if page_count != expected_pages {
+        return Err(format!("it has {} page(s), expected {}", page_count, expected_pages));
+    }
+    Ok(())
+}
+
+fn is_pdf(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
+
+fn is_zip(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+/// The paths of the files in a ZIP archive, as `archive/entry`, in archive order. Entries
+/// nested deeper than `max_depth` are left out, as they would be in a folder.
+fn zip_entries(archive: &Path, max_depth: Option<usize>) -> Result<Vec<PathBuf>, String> {
+    let file = File::open(archive).map_err(|err| err.to_string())?;
+    let mut zip = zip::ZipArchive::new(file).map_err(|err| err.to_string())?;
+
+    let mut entries = Vec::new();
+    for index in 0..zip.len() {
+        let entry = zip.by_index_raw(index).map_err(|err| err.to_string())?;
+        // Names that would escape the archive, such as `../a.pdf`, are skipped.
+        let Some(name) = entry.enclosed_name().filter(|_| entry.is_file()) else {
+            continue;
+        };
+        if max_depth.is_none_or(|depth| name.components().count() <= depth) {
+            entries.push(archive.join(name));
+        }
+    }
+    Ok(entries)
+}
 
+/// The ZIP archive `path` is an entry of, and the entry's name within it, if `path` isn't a
+/// file itself but one of its ancestors is a ZIP archive.
+fn zip_entry(path: &Path) -> Option<(&Path, PathBuf)> {
+    if path.exists() {
         return None;
     }
+    let archive = path.ancestors().skip(1).find(|ancestor| is_zip(ancestor))?;
+    Some((archive, path.strip_prefix(archive).ok()?.to_path_buf()))
+}
 
-    let catalog_object = catalog_object.unwrap();
-    let pages_object = pages_object.unwrap();
+fn read_zip_entry(archive: &Path, entry: &Path) -> Result<Vec<u8>, String> {
+    let file = File::open(archive).map_err(|err| err.to_string())?;
+    let mut zip = zip::ZipArchive::new(file).map_err(|err| err.to_string())?;
 
-    // Build a new "Pages" with updated fields
-    if let Ok(dictionary) = pages_object.1.as_dict() {
-        let mut dictionary = dictionary.clone();
+    let index = (0..zip.len())
+        .find(|&index| zip.by_index_raw(index).is_ok_and(|file| file.enclosed_name().as_deref() == Some(entry)))
+        .ok_or_else(|| format!("{:?} has no entry {:?}", archive.display(), entry.display()))?;
+    let mut data = Vec::new();
+    let mut file = zip.by_index(index).map_err(|err| err.to_string())?;
+    file.read_to_end(&mut data).map_err(|err| err.to_string())?;
+    Ok(data)
+}
 
-        // Set new pages count
-        dictionary.set("Count", documents_pages.len() as u32);
+/// Read the inputs listed in a --manifest file.
+fn read_manifest(manifest: &Path) -> Result<Vec<Input>, String> {
+    let contents = fs::read_to_string(manifest)
+        .map_err(|err| format!("can't read manifest {:?}: {}", manifest.display(), err))?;
+    let base = manifest.parent().unwrap_or(Path::new(""));
 
-        // Set new "Kids" list (collected from documents pages) for "Pages"
-        dictionary.set(
-            "Kids",
-            documents_pages
-                    .into_keys()
-                    .map(Object::Reference)
-                    .collect::<Vec<_>>(),
-        );
+    let mut inputs = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
 
-        document
-                .objects
-                .insert(pages_object.0, Object::Dictionary(dictionary));
+        let location = format!("{}:{}", manifest.display(), index + 1);
+        let mut input = parse_input(line).map_err(|err| format!("{}: {}", location, err))?;
+        input.path = base.join(&input.path);
+        check_input(&input.path).map_err(|err| format!("{}: {}", location, err))?;
+        inputs.push(input);
     }
+    Ok(inputs)
+}
 
-    // Build a new "Catalog" with updated fields
-    if let Ok(dictionary) = catalog_object.1.as_dict() {
-        let mut dictionary = dictionary.clone();
-        dictionary.set("Pages", pages_object.0);
-        dictionary.remove(b"Outlines"); // Outlines not supported in merged PDFs
+/// Read the `FILE = TITLE` lines of a --titles file.
+fn read_titles(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("can't read titles {:?}: {}", path.display(), err))?;
 
-        document
-                .objects
-                .insert(catalog_object.0, Object::Dictionary(dictionary));
+    let mut titles = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.split_once('=') {
+            Some((file, title)) if !file.trim().is_empty() => {
+                titles.push((file.trim().to_string(), title.trim().to_string()));
+            }
+            _ => return Err(format!("{}:{}: expected FILE = TITLE", path.display(), index + 1)),
+        }
     }
+    Ok(titles)
+}
+
+/// The title mapped to `path`, matching the path as given or just the file name.
+fn title_for<'a>(titles: &'a [(String, String)], path: &Path) -> Option<&'a str> {
+    let name = path.file_name().map(|name| name.to_string_lossy());
+    titles
+        .iter()
+        .find(|(file, _)| Path::new(file) == path || name.as_deref() == Some(file.as_str()))
+        .map(|(_, title)| title.as_str())
+}
 
-    document.trailer.set("Root", catalog_object.0);
+/// Whether `path` is `-`, standing for standard input.
+fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// The URL `path` stands for, if it is an http:// or https:// URL.
+fn url(path: &Path) -> Option<&str> {
+    let path = path.to_str()?;
+    let scheme = path.split_once("://")?.0;
+    (scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")).then_some(path)
+}
 
-    // Update the max internal ID as wasn't updated before due to direct objects insertion
-    document.max_id = document.objects.len() as u32;
+/// Why `select_inputs` picked no inputs, and the exit code to fail with.
+struct InputError {
+    message: String,
+    code: i32,
+}
 
-    // Reorder all new Document objects
-    document.renumber_objects();
+impl From<String> for InputError {
+    fn from(message: String) -> Self {
+        InputError { message, code: FAILURE_EXIT_CODE }
+    }
+}
 
-    // Set any Bookmarks to the First child if they are not set to a page
-    document.adjust_zero_pages();
+/// The inputs `args` picks, in merge order with --cover first and --back last: the PDFs of the
+/// folders, ZIP archives, manifest or --files given, filtered, sorted, made unique, reversed
+/// and limited. `append_to` is left out of the folders scanned. Merge and count both take
+/// their inputs from here, so that a count is of what a merge would take.
+fn select_inputs(
+    args: &InputArgs,
+    append_to: Option<&Path>,
+    status: &mut dyn Write,
+) -> Result<Vec<Input>, InputError> {
+    if let (Some(since), Some(until)) = (args.since, args.until) {
+        if since > until {
+            return Err("--since is later than --until".to_string().into());
+        }
+    }
+    if let (Some(min), Some(max)) = (args.min_pages, args.max_pages) {
+        if min > max {
+            return Err(format!("--min-pages {} is more than --max-pages {}", min, max).into());
+        }
+    }
 
-    // Set all bookmarks to the PDF Object tree then set the Outlines to the Bookmark content map.
-    if let Some(n) = document.build_outline() {
-        if let Ok(x) = document.get_object_mut(catalog_object.0) {
-            if let Object::Dictionary(ref mut dict) = x {
-                dict.set("Outlines", Object::Reference(n));
+    let mut inputs = Vec::new();
+    if let Some(manifest) = &args.manifest {
+        inputs = read_manifest(manifest).map_err(|err| format!("Invalid input: {}", err))?;
+    } else if !args.paths.is_empty() {
+        // Each folder's PDFs are sorted among themselves; files keep their place in the list.
+        for path in &args.paths {
+            if path.is_dir() || is_zip(path) {
+                inputs.extend(folder_pdfs(path, args, append_to)?.into_iter().map(Input::new));
+            } else if path.is_file() {
+                inputs.push(Input::new(path.clone()));
+            } else {
+                let input = parse_input(&path.to_string_lossy()).map_err(|err| format!("Invalid input: {}", err))?;
+                inputs.push(input);
             }
         }
+        check_inputs(&inputs)?;
+    } else if args.files.is_empty() {
+        inputs.extend(folder_pdfs(&args.folder, args, append_to)?.into_iter().map(Input::new));
+    } else {
+        inputs = expand_globs(&args.files, args.allow_empty_glob).map_err(|err| format!("Invalid input: {}", err))?;
+        check_inputs(&inputs)?;
     }
 
-    document.compress();
+    if inputs.is_empty() {
+        let message = match &args.manifest {
+            Some(manifest) => format!("No PDF files listed in {:?}", manifest.display()),
+            None if !args.paths.is_empty() => "No PDF files found in the given folders".to_string(),
+            None if !args.files.is_empty() => "No PDF files matched --files".to_string(),
+            None => format!("No PDF files found in {:?}", args.folder.display()),
+        };
+        return Err(InputError { message, code: NO_INPUT_EXIT_CODE });
+    }
 
-    Some(document)
+    if args.unique {
+        let mut seen = HashSet::new();
+        inputs.retain(|input| {
+            let path = fs::canonicalize(&input.path).unwrap_or_else(|_| input.path.clone());
+            let key = (path, input.pages.as_ref().map(PageSelection::to_string), input.rotation.degrees());
+            let first = seen.insert(key);
+            if !first {
+                info!("Skipping {:?}: it is already being merged", input.path.display());
+            }
+            first
+        });
+    }
+
+    if args.reverse {
+        inputs.reverse();
+    }
+
+    if let Some(limit) = args.limit {
+        let available = inputs.len();
+        inputs.truncate(limit);
+        writeln!(status, "Using {} of {} file(s).", inputs.len(), available).map_err(|err| err.to_string())?;
+    }
+
+    for path in [&args.cover, &args.back].into_iter().flatten() {
+        check_input(path).map_err(|message| format!("Invalid input: {}", message))?;
+    }
+    if let Some(cover) = &args.cover {
+        inputs.insert(0, Input::new(cover.clone()));
+    }
+    if let Some(back) = &args.back {
+        inputs.push(Input::new(back.clone()));
+    }
+    Ok(inputs)
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Cli::parse();
-    let mut documents = Vec::new();
+/// The PDFs in `folder`, or in the ZIP archive `folder`, that the filters of `args` take,
+/// sorted by --sort and then by the folder's order file, without `append_to`.
+fn folder_pdfs(folder: &Path, args: &InputArgs, append_to: Option<&Path>) -> Result<Vec<PathBuf>, String> {
+    let options = args.folder_options();
+    let mut paths = if is_zip(folder) {
+        let entries = zip_entries(folder, options.max_depth)
+            .map_err(|err| format!("Failed to read {:?}: {}", folder.display(), err))?;
+        // The entries count as modified when the archive was.
+        let modified = fs::metadata(folder).and_then(|metadata| metadata.modified()).ok();
+        options.pick(entries, |_| modified)
+    } else {
+        options.pdf_paths(folder).map_err(|err| format!("Failed to read {:?}: {}", folder.display(), err))?
+    };
+
+    // The file being appended to may well live in the folder being scanned.
+    if let Some(base) = append_to.and_then(|base| base.canonicalize().ok()) {
+        paths.retain(|path| path.canonicalize().ok().as_ref() != Some(&base));
+    }
+    if let Some(depth) = args.max_depth {
+        info!("Found {} PDF file(s) within depth {}.", paths.len(), depth);
+    }
+
+    let order_file = args
+        .preserve_source_order_file
+        .clone()
+        .or_else(|| Some(folder.join(ORDER_FILE)).filter(|path| path.is_file()));
+    if let Some(order_file) = order_file {
+        let names = read_order(&order_file).map_err(|err| format!("Invalid input: {}", err))?;
+        info!("Ordering {:?} by {:?}", folder.display(), order_file.display());
+        for name in &names {
+            if !is_zip(folder) && !folder.join(name).exists() {
+                warn!("{:?} lists {:?}, which doesn't exist", order_file.display(), name);
+            }
+        }
+        paths = order_paths(paths, folder, &names);
+    }
+    Ok(paths)
+}
+
+/// Read the file names listed in an order file.
+fn read_order(path: &Path) -> Result<Vec<String>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("can't read order file {:?}: {}", path.display(), err))?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// `paths`, with those `names` lists first, in the order listed. A name matches a path
+/// relative to `folder` or just a file name.
+fn order_paths(paths: Vec<PathBuf>, folder: &Path, names: &[String]) -> Vec<PathBuf> {
+    let mut rest = paths.into_iter().map(Some).collect::<Vec<_>>();
+    let mut ordered = Vec::new();
+    for name in names {
+        let listed = rest.iter_mut().find(|path| {
+            path.as_deref().is_some_and(|path| {
+                path.strip_prefix(folder).is_ok_and(|relative| relative == Path::new(name))
+                    || path.file_name().is_some_and(|file_name| file_name == name.as_str())
+            })
+        });
+        if let Some(path) = listed.and_then(Option::take) {
+            ordered.push(path);
+        }
+    }
+    ordered.extend(rest.into_iter().flatten());
+    ordered
+}
+
+/// Replace each of `files` whose path is a glob with the files it matches, in name order, each
+/// taking the glob's pages and rotation. A path that names an existing file is never a glob.
+fn expand_globs(files: &[Input], allow_empty: bool) -> Result<Vec<Input>, String> {
+    let mut expanded = Vec::new();
+    for input in files {
+        let pattern = input.path.to_string_lossy();
+        let is_glob = pattern.contains(['*', '?', '['])
+            && !is_stdin(&input.path)
+            && url(&input.path).is_none()
+            && !input.path.exists();
+        if !is_glob {
+            expanded.push(input.clone());
+            continue;
+        }
+
+        let entries = glob::glob(&pattern).map_err(|err| format!("invalid glob {:?}: {}", pattern, err))?;
+        let mut matches = entries.filter_map(Result::ok).collect::<Vec<_>>();
+        matches.sort();
+        if matches.is_empty() {
+            if !allow_empty {
+                return Err(format!("{:?} matches no file; use --allow-empty-glob to allow that", pattern));
+            }
+            warn!("{:?} matches no file", pattern);
+        }
+        info!("{:?} matches {} file(s)", pattern, matches.len());
+        expanded.extend(matches.into_iter().map(|path| Input { path, ..input.clone() }));
+    }
+    Ok(expanded)
+}
+
+/// Check that every input given explicitly exists and is a PDF, and that at most one is `-`.
+fn check_inputs(inputs: &[Input]) -> Result<(), String> {
+    for input in inputs.iter().filter(|input| !is_stdin(&input.path)) {
+        check_input(&input.path).map_err(|message| format!("Invalid input: {}", message))?;
+    }
+    if inputs.iter().filter(|input| is_stdin(&input.path)).count() > 1 {
+        return Err("Invalid input: only one input can be read from standard input".to_string());
+    }
+    Ok(())
+}
+
+/// Check that a path given on the command line is an existing PDF file. URLs and entries of
+/// ZIP archives are only checked once they are read.
+fn check_input(path: &Path) -> Result<(), String> {
+    if url(path).is_some() || zip_entry(path).is_some_and(|_| is_pdf(path)) {
+        return Ok(());
+    }
+    if !path.exists() {
+        return Err(format!("{:?} does not exist", path.display()));
+    }
+    if !path.is_file() || !is_pdf(path) {
+        return Err(format!("{:?} is not a PDF file", path.display()));
+    }
+    Ok(())
+}
+
+/// Log how many inputs declare each PDF version, e.g. `1.4 (2 files), 1.7 (1 file)`.
+fn log_versions(versions: &BTreeMap<String, usize>) {
+    let versions = versions
+        .iter()
+        .map(|(version, count)| format!("{} ({} file{})", version, count, if *count == 1 { "" } else { "s" }))
+        .collect::<Vec<_>>();
+    if !versions.is_empty() {
+        info!("Input PDF versions: {}", versions.join(", "));
+    }
+}
+
+/// Print each set of identical pages, dropping the pages whose content is unique from
+/// `duplicates`.
+fn report_duplicates(status: &mut dyn Write, duplicates: &mut Vec<Vec<PageRef>>) -> io::Result<()> {
+    duplicates.retain(|pages| pages.len() > 1);
+    if duplicates.is_empty() {
+        return writeln!(status, "No duplicate pages found.");
+    }
+
+    writeln!(status, "Found {} set(s) of identical pages:", duplicates.len())?;
+    for pages in duplicates.iter() {
+        let pages = pages
+            .iter()
+            .map(|page| format!("{:?} page {}", page.path, page.page))
+            .collect::<Vec<_>>();
+        writeln!(status, "  {}", pages.join(" = "))?;
+    }
+    Ok(())
+}
+
+/// Print `items` as an indented tree, each with the page it jumps to, for --dry-run-tree.
+fn print_outline(output: &mut dyn Write, items: &[OutlineItem], depth: usize) -> io::Result<()> {
+    for item in items {
+        let page = item.page.map_or("no page".to_string(), |page| format!("page {}", page));
+        writeln!(output, "{}{} ({})", "  ".repeat(depth), item.title, page)?;
+        print_outline(output, &item.children, depth + 1)?;
+    }
+    Ok(())
+}
+
+/// The path of part `number` of `output` with --chunk-pages: "merged_part1.pdf" for
+/// "merged.pdf", and so on.
+fn part_path(output: &Path, number: usize) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = output.extension().map_or("pdf".into(), |extension| extension.to_string_lossy());
+    output.with_file_name(format!("{}_part{}.{}", stem, number, extension))
+}
 
-    for entry in WalkDir::new(&args.folder) {
-        let entry = entry?;
-        let path = entry.path();
+/// Write each part of `args.input` to its own file.
+fn split(args: &SplitArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let fail = |message: String| -> ! {
+        eprintln!("{}.", message);
+        process::exit(FAILURE_EXIT_CODE);
+    };
 
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "pdf") {
-            println!("Merging: {:?}", path.display());
-            let doc = Document::load(path)?;
-            documents.push(doc);
+    let doc = load_document(&args.input, args.password.as_deref(), Duration::from_secs(DEFAULT_TIMEOUT_SECS))
+        .unwrap_or_else(|err| fail(format!("Failed to load {:?}: {}", args.input.display(), err)));
+    let parts = merge_pdf::split_pdf(&doc, args.chunk)
+        .unwrap_or_else(|err| fail(format!("Failed to split {:?}: {}", args.input.display(), err)));
+
+    let paths = (0..parts.len())
+        .map(|index| args.output_dir.join(format!("page_{:04}.pdf", index * args.chunk + 1)))
+        .collect::<Vec<_>>();
+    if !args.force {
+        if let Some(path) = paths.iter().find(|path| path.exists()) {
+            fail(format!("Output file {:?} already exists; use --force to overwrite it", path.display()));
         }
     }
+    if let Err(err) = fs::create_dir_all(&args.output_dir) {
+        fail(format!("Failed to create the output folder {:?}: {}", args.output_dir.display(), err));
+    }
+
+    for (mut part, path) in parts.into_iter().zip(&paths) {
+        part.save(path)?;
+        info!("Wrote {:?}", path.display());
+    }
+    eprintln!("Split {:?} into {} file(s) in {:?}", args.input.display(), paths.len(), args.output_dir.display());
+    Ok(())
+}
 
+/// Print the number of pages a merge with the input options of `args` would take: the pages
+/// of the inputs it picks, within their `:RANGES`, skipping those --min-pages and --max-pages
+/// leave out.
+fn count(args: &CountArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let fail = |message: String, code: i32| -> ! {
+        eprintln!("{}.", message);
+        process::exit(code);
+    };
+    let inputs = select_inputs(&args.input, None, &mut io::stderr())
+        .unwrap_or_else(|err| fail(err.message, err.code));
+
+    let mut total = 0;
+    for (index, input) in inputs.iter().enumerate() {
+        let path = &input.path;
+        let mut doc = load_document(path, None, Duration::from_secs(DEFAULT_TIMEOUT_SECS))
+            .unwrap_or_else(|err| fail(format!("Failed to load {:?}: {}", path.display(), err), FAILURE_EXIT_CODE));
+        let page_count = doc.get_pages().len();
+        let too_few = args.input.min_pages.is_some_and(|min| page_count < min);
+        let too_many = args.input.max_pages.is_some_and(|max| page_count > max);
+        if (too_few || too_many) && !args.input.is_whole(index, inputs.len()) {
+            info!("Skipping {:?}: it has {} page(s)", path.display(), page_count);
+            continue;
+        }
+        if let Some(selection) = &input.pages {
+            if let Err(err) = pages::select_pages(&mut doc, selection) {
+                fail(format!("Invalid input: {:?}: {}", path.display(), err), FAILURE_EXIT_CODE);
+            }
+        }
+        let pages = doc.get_pages().len();
+        info!("{:?}: {} page(s)", path.display(), pages);
+        total += pages;
+    }
+    println!("{}", total);
+    Ok(())
+}
+
+/// Print what `args.input` contains.
+fn info(args: &InfoArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let fail = |message: String| -> ! {
+        if args.json {
+            println!("{}", serde_json::json!({ "status": "error", "error": message }));
+        } else {
+            eprintln!("{}.", message);
+        }
+        process::exit(FAILURE_EXIT_CODE);
+    };
+
+    let path = &args.input;
+    let mut doc = read_document(path, Duration::from_secs(DEFAULT_TIMEOUT_SECS)).unwrap_or_else(|err| fail(format!("Failed to load {:?}: {}", path.display(), err)));
+    let encrypted = doc.is_encrypted();
+    if let Err(err) = decrypt(&mut doc, args.password.as_deref()) {
+        fail(format!("Failed to load {:?}: {}", path.display(), err));
+    }
+
+    let pages = doc.get_pages();
+    let mut page_sizes: Vec<PageSizeCount> = Vec::new();
+    for &page_id in pages.values() {
+        let [llx, lly, urx, ury] = pages::media_box(&doc, page_id);
+        let (width, height) = ((urx - llx).abs().round(), (ury - lly).abs().round());
+        match page_sizes.iter_mut().find(|size| size.width == width && size.height == height) {
+            Some(size) => size.pages += 1,
+            None => page_sizes.push(PageSizeCount { width, height, pages: 1 }),
+        }
+    }
+
+    let catalog = doc.catalog().ok();
+    let catalog_entry = |key: &[u8]| {
+        catalog
+            .and_then(|catalog| catalog.get(key).ok())
+            .and_then(|value| doc.dereference(value).ok())
+            .map(|(_, value)| value)
+    };
+    let has_fields = |form: &Object| {
+        form.as_dict()
+            .and_then(|form| form.get(b"Fields"))
+            .and_then(|fields| doc.dereference(fields))
+            .and_then(|(_, fields)| fields.as_array())
+            .is_ok_and(|fields| !fields.is_empty())
+    };
+    let has_attachments = |names: &Object| names.as_dict().is_ok_and(|names| names.has(b"EmbeddedFiles"));
+
+    let metadata = doc
+        .trailer
+        .get(b"Info")
+        .and_then(|info| doc.dereference(info))
+        .and_then(|(_, info)| info.as_dict())
+        .map(|info| {
+            info.iter()
+                .filter_map(|(key, value)| {
+                    let value = lopdf::decode_text_string(doc.dereference(value).ok()?.1).ok()?;
+                    Some((String::from_utf8_lossy(key).into_owned(), value))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let info = DocumentInfo {
+        path: path.display().to_string(),
+        version: doc.version.clone(),
+        pages: pages.len(),
+        page_sizes,
+        encrypted,
+        outline: catalog_entry(b"Outlines").is_some(),
+        forms: catalog_entry(b"AcroForm").is_some_and(has_fields),
+        attachments: catalog_entry(b"Names").is_some_and(has_attachments),
+        metadata,
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+    println!("File:         {}", info.path);
+    println!("PDF version:  {}", info.version);
+    println!("Pages:        {}", info.pages);
+    for (index, size) in info.page_sizes.iter().enumerate() {
+        let label = if index == 0 { "Page sizes:" } else { "" };
+        println!("{:<13} {} x {} pt ({} page(s))", label, size.width, size.height, size.pages);
+    }
+    println!("Encrypted:    {}", yes_no(info.encrypted));
+    println!("Outline:      {}", yes_no(info.outline));
+    println!("Forms:        {}", yes_no(info.forms));
+    println!("Attachments:  {}", yes_no(info.attachments));
+    for (key, value) in &info.metadata {
+        println!("{:<13} {}", format!("{}:", key), value);
+    }
+    Ok(())
+}
+
+/// The exit code for a merge that failed with `err`.
+fn merge_exit_code(err: &MergeError) -> i32 {
+    match err {
+        MergeError::EmptyInput => NO_INPUT_EXIT_CODE,
+        MergeError::Load { .. } => FAILURE_EXIT_CODE,
+        MergeError::NoPagesRoot
+        | MergeError::NoCatalogRoot
+        | MergeError::InterleaveNeedsTwo(_)
+        | MergeError::DanglingReferences(_)
+        | MergeError::TooManyObjects => MERGE_FAILED_EXIT_CODE,
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // clap exits with 2 for invalid arguments, which here means there was nothing to merge.
+    let args = Cli::try_parse().unwrap_or_else(|err| {
+        let code = if err.use_stderr() { FAILURE_EXIT_CODE } else { 0 };
+        let _ = err.print();
+        process::exit(code);
+    });
+
+    let level = match args.verbose {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        _ => LevelFilter::Debug,
+    };
+    // Logs go to stderr; RUST_LOG still overrides the level chosen here.
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format_timestamp(None)
+        .format_target(false)
+        .parse_default_env()
+        .init();
+
+    match &args.command {
+        Some(Command::Merge(merge_args)) => merge(merge_args),
+        Some(Command::Split(split_args)) => split(split_args),
+        Some(Command::Info(info_args)) => info(info_args),
+        Some(Command::Count(count_args)) => count(count_args),
+        None => merge(&args.merge),
+    }
+}
+
+/// Merge the inputs `args` selects into one PDF.
+fn merge(args: &MergeArgs) -> Result<(), Box<dyn std::error::Error>> {
     // Determine output file path
-    let output_path = args.output.unwrap_or_else(|| PathBuf::from("merged_output.pdf"));
-    let mut output_file = File::create(&output_path)?;
+    let output_path = args
+        .output
+        .clone()
+        .or_else(|| {
+            let name = Local::now().format("merged_%Y%m%d_%H%M%S.pdf").to_string();
+            args.output_dir.as_ref().map(|dir| dir.join(name))
+        })
+        .or_else(|| args.append_to.clone())
+        .unwrap_or_else(|| PathBuf::from("merged_output.pdf"));
+    let to_stdout = args.to_stdout();
+
+    // Status messages go to stderr, so that stdout only ever carries the PDF (with --output -)
+    // or the JSON summary.
+    let mut status: Box<dyn Write> = if args.json { Box::new(io::sink()) } else { Box::new(io::stderr()) };
+
+    if to_stdout && args.chunk_pages.is_some() {
+        args.fail("--chunk-pages can't write to standard output".to_string());
+    }
+    if !to_stdout && !args.dry_run && !args.dry_run_tree && !args.force && args.chunk_pages.is_none() && output_path.exists() {
+        args.fail(format!(
+            "Output file {:?} already exists; use --force to overwrite it",
+            output_path.display()
+        ));
+    }
 
-    if let Some(mut merged_document) = merge_pdf(documents) {
-        // Save the merged PDF
-        merged_document.save_to(&mut output_file)?;
+    let titles = match &args.titles {
+        Some(path) => read_titles(path).unwrap_or_else(|err| args.fail(format!("Invalid input: {}", err))),
+        None => Vec::new(),
+    };
+
+    let inputs = select_inputs(&args.input, args.append_to.as_deref(), &mut status)
+        .unwrap_or_else(|err| args.fail_with_code(err.message, err.code));
+    let whole = |index: usize| args.input.is_whole(index, inputs.len());
+
+    if args.interleave && inputs.len() != 2 {
+        args.fail(format!("--interleave takes exactly two inputs, got {}", inputs.len()));
+    }
+
+    let mut scripted = Vec::new();
+    // For --find-duplicates: the index in `summary.duplicate_pages` of each page content hash.
+    let mut page_hashes = HashMap::new();
+    // How many loaded inputs declare each PDF version, logged under -v.
+    let mut versions = BTreeMap::<String, usize>::new();
+    let mut summary = Summary { status: "ok", ..Summary::default() };
+
+    let watermark_content = match (&args.watermark, &args.watermark_image) {
+        (Some(text), _) => Some(WatermarkContent::Text(text.clone())),
+        (None, Some(path)) if !args.dry_run => {
+            let image = fs::read(path)
+                .map_err(|err| err.to_string())
+                .and_then(|data| WatermarkImage::from_bytes(&data))
+                .unwrap_or_else(|err| args.fail(format!("Invalid watermark image {:?}: {}", path.display(), err)));
+            Some(WatermarkContent::Image(Box::new(image)))
+        }
+        _ => None,
+    };
 
-        println!("PDFs merged into {:?}", output_path.display());
+    let options = MergeOptions {
+        pdf_version: args.pdf_version.unwrap_or_default(),
+        match_input_version: args.match_input_version && args.pdf_version.is_none(),
+        bookmarks: if args.no_bookmarks { BookmarkMode::None } else { args.bookmark_mode },
+        metadata: Metadata {
+            title: args.title.clone(),
+            author: args.author.clone(),
+            subject: args.subject.clone(),
+            keywords: args.keywords.clone(),
+        },
+        lang: args.lang.clone(),
+        dedup: args.dedup,
+        dedup_pages: args.dedup_pages,
+        separator: args.separator,
+        generated_page_size: args.gen_page_size,
+        page_numbers: args.page_numbers.then_some(args.page_numbers_pos),
+        keep_forms: args.keep_forms,
+        keep_attachments: args.keep_attachments,
+        keep_first_catalog_metadata: args.keep_first_catalog_metadata,
+        retain_structure_tree: args.retain_structure_tree,
+        interleave: match (args.interleave, args.interleave_reverse_second) {
+            (false, _) => None,
+            (true, false) => Some(Interleave::Alternate),
+            (true, true) => Some(Interleave::ReverseSecond),
+        },
+        toc: args.toc,
+        rotate: args.rotate.unwrap_or_default(),
+        auto_rotate: args.auto_rotate,
+        compress: !(args.no_compress || args.pretty),
+        strip_js: args.strip_js,
+        page_size: args.page_size,
+        watermark: watermark_content.map(|content| Watermark {
+            content,
+            opacity: args.watermark_opacity,
+            angle: args.watermark_angle,
+        }),
+        open_action: args.open_action,
+        xmp: args.xmp,
+        flatten: args.flatten,
+        // The inputs are already sorted.
+        sort: None,
+        strict: args.strict,
+        page_hook: None,
+        normalize_resources: args.normalize_resources,
+        nup: args.nup.map(|grid| NUp { margin: args.nup_margin, ..grid }),
+    };
+
+    let progress = if args.quiet || args.dry_run || args.dry_run_tree || args.json {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(inputs.len() as u64)
+    };
+    progress.set_style(
+        ProgressStyle::with_template("[{bar:40}] {pos}/{len} {wide_msg}")?.progress_chars("=> "),
+    );
+
+    let load = |input: &Input| {
+        progress.set_message(input.path.display().to_string());
+        let loaded = load_document(&input.path, args.password_for(&input.path), Duration::from_secs(args.timeout));
+        progress.inc(1);
+        loaded
+    };
+    let loaded: Box<dyn Iterator<Item = Result<Document, String>>> = if args.low_memory {
+        // Each input is loaded only when the merge takes it.
+        Box::new(inputs.iter().map(load))
+    } else {
+        // Loading is I/O bound, so do it in parallel; `collect` keeps the input order.
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(args.jobs.unwrap_or(0))
+            .build()?;
+        let loaded = pool.install(|| inputs.par_iter().map(load).collect::<Vec<_>>());
+        progress.finish_and_clear();
+        Box::new(loaded.into_iter())
+    };
+
+    let documents = inputs.iter().zip(loaded).enumerate().filter_map(|(index, (input, loaded))| {
+        let path = &input.path;
+        if !args.dry_run {
+            info!("Merging: {:?}", path.display());
+        }
+
+        let doc = match loaded {
+            Ok(mut doc) => {
+                let page_count = doc.get_pages().len();
+                let too_few = args.input.min_pages.is_some_and(|min| page_count < min);
+                let too_many = args.input.max_pages.is_some_and(|max| page_count > max);
+                if (too_few || too_many) && !whole(index) {
+                    info!("Skipping {:?}: it has {} page(s)", path.display(), page_count);
+                    return None;
+                }
+                *versions.entry(doc.version.clone()).or_default() += 1;
+                if let Some(selection) = &input.pages {
+                    if let Err(err) = pages::select_pages(&mut doc, selection) {
+                        args.fail(format!("Invalid input: {:?}: {}", path.display(), err));
+                    }
+                }
+                // Documents without pages are skipped by the merge.
+                if args.first_page_only && !whole(index) && doc.page_iter().next().is_some() {
+                    let first = PageSelection(vec![pages::PageRange { start: 1, end: Some(1) }]);
+                    let _ = pages::select_pages(&mut doc, &first);
+                }
+                pages::rotate_pages(&mut doc, input.rotation);
+                doc
+            }
+            Err(err) if args.skip_errors => {
+                warn!("Skipping {:?}: {}", path.display(), err);
+                summary.skipped.push(SkippedInput { path: path.display().to_string(), error: err });
+                return None;
+            }
+            Err(err) if args.placeholder_on_error => {
+                warn!("Replacing {:?} with a placeholder page: {}", path.display(), err);
+                let message = format!("Could not load {}: {}", path.display(), err);
+                let doc = placeholder_document(&message, args.gen_page_size.unwrap_or_default());
+                summary.replaced.push(SkippedInput { path: path.display().to_string(), error: err });
+                doc
+            }
+            Err(err) => args.fail(format!("Failed to load {:?}: {}", path.display(), err)),
+        };
+
+        if !args.strip_js && has_javascript(&doc) {
+            scripted.push(path.display().to_string());
+        }
+
+        let page_count = doc.get_pages().len();
+        summary.total_pages += page_count;
+        let bytes = fs::metadata(path).ok().filter(|_| !is_stdin(path)).map(|metadata| metadata.len());
+        summary.input_bytes += bytes.unwrap_or(0);
+        summary.inputs.push(InputSummary { path: path.display().to_string(), pages: page_count, bytes });
+        if args.find_duplicates {
+            // The kept pages are the first ones the input's `:RANGES` select.
+            let selected = (1..).filter(|&page| input.pages.as_ref().is_none_or(|pages| pages.contains(page)));
+            for (page_id, page) in doc.page_iter().zip(selected) {
+                let content = doc.get_page_content(page_id).unwrap_or_default();
+                if content.is_empty() {
+                    continue;
+                }
+                let group = *page_hashes.entry(Sha256::digest(&content)).or_insert_with(|| {
+                    summary.duplicate_pages.push(Vec::new());
+                    summary.duplicate_pages.len() - 1
+                });
+                summary.duplicate_pages[group].push(PageRef { path: path.display().to_string(), page });
+            }
+        }
+
+        let title = match title_for(&titles, path) {
+            Some(title) => title.to_string(),
+            None if is_stdin(path) => String::new(),
+            None => path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+        };
+        Some((title, doc))
+    });
+
+    if args.dry_run {
+        documents.for_each(drop);
+        progress.finish_and_clear();
+        log_versions(&versions);
+
+        for input in &summary.inputs {
+            writeln!(status, "{:>6} page(s)  {:?}", input.pages, input.path)?;
+        }
+        writeln!(status, "{:>6} page(s)  total in {} file(s)", summary.total_pages, summary.inputs.len())?;
+        if !summary.skipped.is_empty() {
+            writeln!(status, "Skipped {} file(s) that could not be loaded.", summary.skipped.len())?;
+        }
+        if !summary.replaced.is_empty() {
+            writeln!(status, "Replaced {} file(s) that could not be loaded with a placeholder page.", summary.replaced.len())?;
+        }
+        if !scripted.is_empty() {
+            warn!("JavaScript found in {}; use --strip-js to remove it", scripted.join(", "));
+        }
+        if args.find_duplicates {
+            report_duplicates(&mut status, &mut summary.duplicate_pages)?;
+        }
+        if args.json {
+            args.print_json(&summary);
+        }
+        return Ok(());
+    }
+
+    let merged = match &args.append_to {
+        Some(base_path) => {
+            let base = load_document(base_path, args.password_for(base_path), Duration::from_secs(args.timeout))
+                .unwrap_or_else(|err| args.fail(format!("Failed to load {:?}: {}", base_path.display(), err)));
+            append_pdf(base, documents, &options)
+        }
+        None if args.passes_through(&inputs) => {
+            // Loading may still skip the input, which the merge reports.
+            match <[_; 1]>::try_from(documents.collect::<Vec<_>>()) {
+                Ok([(_, doc)]) => {
+                    info!(
+                        "Writing {:?} as it is, without a bookmark for it: it is the only input and nothing changes it",
+                        inputs[0].path.display()
+                    );
+                    Ok(pass_through(doc, options.compress))
+                }
+                Err(documents) => merge_pdf_streaming(documents, &options),
+            }
+        }
+        None => merge_pdf_streaming(documents, &options),
+    };
+    progress.finish_and_clear();
+    log_versions(&versions);
+
+    if !scripted.is_empty() {
+        warn!("JavaScript found in {}; use --strip-js to remove it", scripted.join(", "));
+    }
+    let skipped = summary.skipped.len();
+    let replaced = summary.replaced.len();
+
+    let mut merged_document = match merged {
+        Ok(document) => document,
+        Err(err) => args.fail_with_code(format!("Failed to merge PDFs: {}", err), merge_exit_code(&err)),
+    };
+
+    if args.dry_run_tree {
+        let outline = merge_pdf::outline_tree(&merged_document);
+        if outline.is_empty() {
+            writeln!(status, "The merged PDF would have no outline.")?;
+        }
+        print_outline(&mut io::stdout().lock(), &outline, 0)?;
+        return Ok(());
+    }
+
+    // Every page the merged PDF should have, including generated ones, for --verify.
+    let expected_pages = merged_document.get_pages().len();
+
+    // Save the merged PDF
+    let reloaded = if let Some(chunk) = args.chunk_pages {
+        let parts = merge_pdf::split_pdf(&merged_document, chunk)
+            .unwrap_or_else(|err| args.fail(format!("Failed to split the merged PDF: {}", err)));
+        let paths = (1..=parts.len()).map(|number| part_path(&output_path, number)).collect::<Vec<_>>();
+        if !args.force {
+            if let Some(path) = paths.iter().find(|path| path.exists()) {
+                args.fail(format!("Output file {:?} already exists; use --force to overwrite it", path.display()));
+            }
+        }
+        if let Some(dir) = &args.output_dir {
+            if let Err(err) = fs::create_dir_all(dir) {
+                args.fail(format!("Failed to create the output folder {:?}: {}", dir.display(), err));
+            }
+        }
+        let mut output_bytes = 0;
+        for (mut part, path) in parts.into_iter().zip(&paths) {
+            let mut output_file = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, File::create(path)?);
+            args.save_to(&mut part, &mut output_file)?;
+            output_file.flush()?;
+            output_bytes += fs::metadata(path).map_or(0, |metadata| metadata.len());
+            info!("Wrote {:?}", path.display());
+            summary.chunks.push(path.display().to_string());
+        }
+        summary.output_bytes = Some(output_bytes);
+        writeln!(status, "PDFs merged into {} file(s) of at most {} page(s)", paths.len(), chunk)?;
+        None
+    } else if to_stdout {
+        let mut bytes = Vec::new();
+        args.save_to(&mut merged_document, &mut bytes)?;
+        io::stdout().lock().write_all(&bytes)?;
+        summary.output_bytes = Some(bytes.len() as u64);
+        summary.output_sha256 = args.print_hash.then(|| format!("{:x}", Sha256::digest(&bytes)));
+        writeln!(status, "PDFs merged into standard output")?;
+        args.verify.then(|| Document::load_mem(&bytes))
+    } else {
+        if let Some(dir) = &args.output_dir {
+            if let Err(err) = fs::create_dir_all(dir) {
+                args.fail(format!("Failed to create the output folder {:?}: {}", dir.display(), err));
+            }
+        }
+        // lopdf writes each object in many small pieces, so buffer them.
+        let hashing = HashingWriter { inner: File::create(&output_path)?, hasher: args.print_hash.then(Sha256::new) };
+        let mut output_file = BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, hashing);
+        args.save_to(&mut merged_document, &mut output_file)?;
+        let hashing = output_file.into_inner().map_err(|err| err.into_error())?;
+        summary.output_sha256 = hashing.hasher.map(|hasher| format!("{:x}", hasher.finalize()));
+        summary.output_bytes = fs::metadata(&output_path).ok().map(|metadata| metadata.len());
+        writeln!(status, "PDFs merged into {:?}", output_path.display())?;
+        args.verify.then(|| Document::load(&output_path))
+    };
+
+    if let Some(reloaded) = reloaded {
+        let verified = reloaded
+            .map_err(|err| format!("it can't be loaded: {}", err))
+            .and_then(|doc| verify_output(&doc, expected_pages));
+        match verified {
+            Ok(()) => writeln!(status, "Verified {} page(s) in the merged PDF", expected_pages)?,
+            Err(err) => args.fail_with_code(
+                format!("Verification of the merged PDF failed: {}", err),
+                VERIFY_FAILED_EXIT_CODE,
+            ),
+        }
+    }
+    if args.chunk_pages.is_none() {
+        summary.output = Some(output_path.display().to_string());
+    }
+    if let Some(hash) = &summary.output_sha256 {
+        writeln!(status, "SHA-256: {}", hash)?;
+    }
+
+    // How the output compares to the inputs, with --verify or -v.
+    if let Some(output_bytes) = summary.output_bytes.filter(|_| summary.input_bytes > 0) {
+        let mut report = format!(
+            "{} byte(s) in, {} byte(s) out: {:.1}% of the input size",
+            summary.input_bytes,
+            output_bytes,
+            output_bytes as f64 * 100.0 / summary.input_bytes as f64
+        );
+        if output_bytes > summary.input_bytes && !args.dedup {
+            report.push_str("; --dedup may help by sharing fonts and images between inputs");
+        }
+        if args.verify {
+            writeln!(status, "{}", report)?;
+        } else {
+            info!("{}", report);
+        }
+    }
+
+    if skipped > 0 {
+        writeln!(status, "Skipped {} file(s) that could not be loaded.", skipped)?;
+    }
+    if replaced > 0 {
+        writeln!(status, "Replaced {} file(s) that could not be loaded with a placeholder page.", replaced)?;
+    }
+    if args.find_duplicates {
+        report_duplicates(&mut status, &mut summary.duplicate_pages)?;
     }
-    else {
-        println!("Failed to merge PDFs.");
+    if args.json {
+        args.print_json(&summary);
     }
 
     Ok(())
@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+use crate::destinations::deref_dict;
+
+/// The entries of a name tree gathered from several documents, each under a unique name.
+#[derive(Default)]
+pub(crate) struct NameTree {
+    entries: Vec<(Vec<u8>, Object)>,
+    names: HashSet<Vec<u8>>,
+}
+
+impl NameTree {
+    /// Add `value` under `name`, or under `name_2`, `name_3` and so on if an earlier entry has
+    /// it, as a name tree maps each name to one value. Returns the name it was added under.
+    pub(crate) fn insert(&mut self, name: Vec<u8>, value: Object) -> Vec<u8> {
+        let mut unique = name.clone();
+        let mut n = 1;
+        while self.names.contains(&unique) {
+            n += 1;
+            unique = [name.as_slice(), format!("_{}", n).as_bytes()].concat();
+        }
+
+        self.names.insert(unique.clone());
+        self.entries.push((unique.clone(), value));
+        unique
+    }
+
+    /// A single-node name tree holding every entry, or `None` if there are none.
+    pub(crate) fn into_dictionary(mut self) -> Option<Dictionary> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        // Name tree keys must be in sorted order.
+        self.entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let names = self
+            .entries
+            .into_iter()
+            .flat_map(|(name, value)| [Object::string_literal(name), value])
+            .collect::<Vec<_>>();
+
+        let mut tree = Dictionary::new();
+        tree.set("Names", names);
+        Some(tree)
+    }
+}
+
+/// Every name and value in the name tree rooted at `node`, in tree order.
+pub(crate) fn leaves(doc: &Document, node: &Object) -> Vec<(Vec<u8>, Object)> {
+    let mut leaves = Vec::new();
+    collect_leaves(doc, node, &mut leaves, &mut HashSet::new());
+    leaves
+}
+
+fn collect_leaves(doc: &Document, node: &Object, leaves: &mut Vec<(Vec<u8>, Object)>, visited: &mut HashSet<ObjectId>) {
+    if let Ok(id) = node.as_reference() {
+        if !visited.insert(id) {
+            return;
+        }
+    }
+    let Ok(node) = deref_dict(doc, node) else {
+        return;
+    };
+
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        for pair in names.chunks(2) {
+            if let [Object::String(name, _), value] = pair {
+                leaves.push((name.clone(), value.clone()));
+            }
+        }
+    }
+
+    if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+        for kid in kids {
+            collect_leaves(doc, kid, leaves, visited);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use log::{debug, warn};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::{pages, renumber};
+
+/// The most columns or rows an n-up grid can have.
+const MAX_CELLS: u32 = 8;
+
+/// A grid to place several pages on each sheet of the merged PDF, parsed from
+/// `COLUMNSxROWS` such as `2x2`.
+///
+/// Pages fill the grid left to right, then top to bottom, each scaled down to fit its cell
+/// without distortion and centred in it. Each sheet takes the size of its first page, turned
+/// landscape or portrait, whichever lets that page be drawn larger.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NUp {
+    pub columns: u32,
+    pub rows: u32,
+    /// The space around and between the cells, in points.
+    pub margin: f32,
+}
+
+impl NUp {
+    fn cells(self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+}
+
+impl fmt::Display for NUp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.columns, self.rows)
+    }
+}
+
+impl FromStr for NUp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid grid {:?}: expected COLUMNSxROWS such as 2x2, up to {}x{}", s, MAX_CELLS, MAX_CELLS);
+        let (columns, rows) = s.trim().split_once(['x', 'X']).ok_or_else(invalid)?;
+        let count = |value: &str| {
+            value
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|count| (1..=MAX_CELLS).contains(count))
+                .ok_or_else(invalid)
+        };
+        let (columns, rows) = (count(columns)?, count(rows)?);
+        if columns * rows == 1 {
+            return Err(format!("invalid grid {:?}: it must hold more than one page", s));
+        }
+        Ok(NUp { columns, rows, margin: 0.0 })
+    }
+}
+
+/// Place the pages of `doc` on sheets of `nup.columns` by `nup.rows` pages, in page order.
+///
+/// Each page becomes a form XObject drawn in its cell, with its rotation applied, and its
+/// annotations move to the sheet along with it. References to a page, such as those of
+/// bookmarks and links, are changed to its sheet. Page labels no longer fit the sheets and are
+/// dropped. Returns the number of sheets.
+pub(crate) fn impose_pages(doc: &mut Document, nup: NUp) -> usize {
+    let Some(root_id) = doc.catalog().and_then(|catalog| catalog.get(b"Pages")).and_then(Object::as_reference).ok()
+    else {
+        return 0;
+    };
+    let page_ids = doc.page_iter().collect::<Vec<_>>();
+
+    let mut sheets = Vec::new();
+    let mut sheet_of = HashMap::new();
+    let mut removed = Vec::new();
+    for group in page_ids.chunks(nup.cells()) {
+        let placed = group.iter().map(|&page_id| Placed::new(doc, page_id)).collect::<Vec<_>>();
+        let (width, height) = sheet_size(&placed[0], nup);
+        let mut cell_width = (width - nup.margin * (nup.columns + 1) as f32) / nup.columns as f32;
+        let mut cell_height = (height - nup.margin * (nup.rows + 1) as f32) / nup.rows as f32;
+        let mut margin = nup.margin;
+        if cell_width <= 0.0 || cell_height <= 0.0 {
+            warn!("An n-up margin of {} leaves no room for the pages; using none", nup.margin);
+            (cell_width, cell_height, margin) = (width / nup.columns as f32, height / nup.rows as f32, 0.0);
+        }
+
+        let mut xobjects = Dictionary::new();
+        let mut content = Vec::new();
+        let mut annots = Vec::new();
+        for (index, page) in placed.iter().enumerate() {
+            let (column, row) = (index as u32 % nup.columns, index as u32 / nup.columns);
+            let scale = (cell_width / page.width).min(cell_height / page.height);
+            // Cells are counted from the top, PDF coordinates from the bottom.
+            let x = margin + column as f32 * (cell_width + margin) + (cell_width - page.width * scale) / 2.0;
+            let y = height - (margin + row as f32 * (cell_height + margin)) - cell_height
+                + (cell_height - page.height * scale) / 2.0;
+            let matrix = page.matrix(scale, x, y);
+
+            let name = format!("MergePdfPage{}", index + 1);
+            let form = doc.add_object(page.form(doc));
+            xobjects.set(name.as_bytes(), form);
+            let cm = matrix.iter().map(|value| format!("{:.4}", value)).collect::<Vec<_>>().join(" ");
+            content.extend_from_slice(format!("q {} cm /{} Do Q\n", cm, name).as_bytes());
+            annots.extend(moved_annotations(doc, page.id, &matrix));
+            removed.push(page.id);
+            removed.extend(page.contents.iter().copied());
+        }
+
+        let content_id = doc.add_object(Stream::new(Dictionary::new(), content));
+        let sheet_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => root_id,
+            "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+            "Resources" => dictionary! { "XObject" => xobjects },
+            "Contents" => content_id,
+        });
+        if !annots.is_empty() {
+            for annot in &annots {
+                if let Ok(annot) = annot.as_reference().and_then(|id| doc.get_dictionary_mut(id)) {
+                    annot.set("P", sheet_id);
+                }
+            }
+            if let Ok(sheet) = doc.get_dictionary_mut(sheet_id) {
+                sheet.set("Annots", annots);
+            }
+        }
+        sheet_of.extend(group.iter().map(|&page_id| (page_id, sheet_id)));
+        sheets.push(sheet_id);
+    }
+
+    // Intermediate page tree nodes would only hold the removed pages.
+    let nodes = doc
+        .objects
+        .iter()
+        .filter(|(id, object)| **id != root_id && object.type_name().ok() == Some("Pages"))
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+    for id in removed.into_iter().chain(nodes) {
+        doc.objects.remove(&id);
+    }
+    if let Ok(root) = doc.get_dictionary_mut(root_id) {
+        root.set("Kids", sheets.iter().copied().map(Object::Reference).collect::<Vec<_>>());
+        root.set("Count", sheets.len() as i64);
+    }
+    // Including objects nothing references any more, so none is left pointing at a removed page.
+    for object in doc.objects.values_mut() {
+        renumber::renumber_references(object, &sheet_of);
+    }
+    if let Ok(catalog) = doc.catalog_mut() {
+        if catalog.remove(b"PageLabels").is_some() {
+            debug!("Dropped the page labels, which don't fit the n-up sheets");
+        }
+    }
+    sheets.len()
+}
+
+/// A page about to be placed on a sheet, with its size as shown, after its rotation.
+struct Placed {
+    id: ObjectId,
+    media_box: [f32; 4],
+    rotation: i64,
+    width: f32,
+    height: f32,
+    contents: Vec<ObjectId>,
+}
+
+impl Placed {
+    fn new(doc: &Document, id: ObjectId) -> Self {
+        let media_box = pages::media_box(doc, id);
+        let rotation = pages::inherited_attribute(doc, id, b"Rotate")
+            .and_then(|rotate| rotate.as_i64().ok())
+            .unwrap_or(0)
+            .rem_euclid(360);
+        // Degenerate boxes get a size, so they can't divide by zero.
+        let (width, height) = ((media_box[2] - media_box[0]).abs().max(1.0), (media_box[3] - media_box[1]).abs().max(1.0));
+        let (width, height) = if rotation % 180 == 0 { (width, height) } else { (height, width) };
+        let contents = match doc.get_dictionary(id).and_then(|page| page.get(b"Contents")) {
+            Ok(Object::Reference(id)) => vec![*id],
+            Ok(Object::Array(contents)) => contents.iter().filter_map(|content| content.as_reference().ok()).collect(),
+            _ => Vec::new(),
+        };
+        Placed { id, media_box, rotation, width, height, contents }
+    }
+
+    /// The page's content and resources as a form XObject in its own coordinates.
+    fn form(&self, doc: &Document) -> Stream {
+        let mut dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => self.media_box.iter().map(|&value| value.into()).collect::<Vec<Object>>(),
+        };
+        if let Some(resources) = pages::inherited_attribute(doc, self.id, b"Resources") {
+            dict.set("Resources", resources.clone());
+        }
+        Stream::new(dict, doc.get_page_content(self.id).unwrap_or_default())
+    }
+
+    /// The matrix that draws the page turned by its rotation, scaled by `scale` and with the
+    /// lower left corner of what is shown at `(x, y)`.
+    fn matrix(&self, scale: f32, x: f32, y: f32) -> [f32; 6] {
+        let [llx, lly, urx, ury] = self.media_box;
+        let (width, height) = (urx - llx, ury - lly);
+        // From the media box moved to the origin to the page as shown, turned clockwise.
+        let [a, b, c, d, e, f] = match self.rotation {
+            90 => [0.0, -1.0, 1.0, 0.0, 0.0, width],
+            180 => [-1.0, 0.0, 0.0, -1.0, width, height],
+            270 => [0.0, 1.0, -1.0, 0.0, height, 0.0],
+            _ => [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+        };
+        [
+            scale * a,
+            scale * b,
+            scale * c,
+            scale * d,
+            scale * (e - a * llx - c * lly) + x,
+            scale * (f - b * llx - d * lly) + y,
+        ]
+    }
+}
+
+/// The sheet size for a group starting with `first`: its size, or turned the other way if
+/// that lets it be drawn larger in the grid.
+fn sheet_size(first: &Placed, nup: NUp) -> (f32, f32) {
+    let scale = |(width, height): (f32, f32)| {
+        let cell_width = (width - nup.margin * (nup.columns + 1) as f32) / nup.columns as f32;
+        let cell_height = (height - nup.margin * (nup.rows + 1) as f32) / nup.rows as f32;
+        (cell_width / first.width).min(cell_height / first.height)
+    };
+    let upright = (first.width, first.height);
+    let turned = (first.height, first.width);
+    if scale(turned) > scale(upright) {
+        turned
+    } else {
+        upright
+    }
+}
+
+/// The page's annotations, with their `Rect` moved through `matrix` onto the sheet. Those
+/// written inline in the page are added to the document, so the sheet can refer to them.
+fn moved_annotations(doc: &mut Document, page_id: ObjectId, matrix: &[f32; 6]) -> Vec<Object> {
+    let annots = match doc.get_dictionary(page_id).and_then(|page| page.get(b"Annots")) {
+        Ok(Object::Array(annots)) => annots.clone(),
+        Ok(Object::Reference(id)) => match doc.get_object(*id) {
+            Ok(Object::Array(annots)) => annots.clone(),
+            _ => return Vec::new(),
+        },
+        _ => return Vec::new(),
+    };
+
+    let mut moved = Vec::new();
+    for annot in annots {
+        let id = match annot {
+            Object::Reference(id) => id,
+            Object::Dictionary(dict) => doc.add_object(dict),
+            _ => continue,
+        };
+        let Ok(annot) = doc.get_dictionary_mut(id) else {
+            continue;
+        };
+        let rect = annot
+            .get(b"Rect")
+            .and_then(Object::as_array)
+            .map(|rect| rect.iter().filter_map(|value| value.as_float().ok()).collect::<Vec<_>>());
+        if let Ok(&[x1, y1, x2, y2]) = rect.as_deref() {
+            let [a, b, c, d, e, f] = *matrix;
+            let corners = [(x1, y1), (x1, y2), (x2, y1), (x2, y2)].map(|(x, y)| (a * x + c * y + e, b * x + d * y + f));
+            let xs = corners.map(|(x, _)| x);
+            let ys = corners.map(|(_, y)| y);
+            let min = |values: [f32; 4]| values.into_iter().fold(f32::INFINITY, f32::min);
+            let max = |values: [f32; 4]| values.into_iter().fold(f32::NEG_INFINITY, f32::max);
+            annot.set("Rect", vec![min(xs).into(), min(ys).into(), max(xs).into(), max(ys).into()]);
+        }
+        moved.push(Object::Reference(id));
+    }
+    moved
+}
@@ -0,0 +1,200 @@
+use std::collections::{HashMap, HashSet};
+
+use lopdf::{Bookmark, Dictionary, Document, Object, ObjectId};
+
+use crate::destinations::{deref_dict, destination_page};
+
+/// Copy the outline tree of `source` into `target` as children of bookmark `parent`, or at the
+/// top level if `parent` is `None`.
+///
+/// `source` must already be renumbered into the ID space of `target`, so page references can be
+/// used as-is. Items whose destination cannot be resolved to a page, or whose page has been
+/// removed, point at `fallback` instead.
+///
+/// Returns the IDs of the source outline objects, which are superseded by the copied bookmarks.
+pub(crate) fn copy_outline(
+    source: &Document,
+    target: &mut Document,
+    parent: Option<u32>,
+    fallback: ObjectId,
+) -> HashSet<ObjectId> {
+    let mut visited = HashSet::new();
+    let Ok(outlines) = source.catalog().and_then(|catalog| catalog.get(b"Outlines")) else {
+        return visited;
+    };
+
+    if let Ok(id) = outlines.as_reference() {
+        visited.insert(id);
+    }
+    if let Ok(first) = deref_dict(source, outlines).and_then(|outlines| outlines.get(b"First")) {
+        copy_items(source, target, first, parent, fallback, &mut visited);
+    }
+    visited
+}
+
+/// Walk a `First`/`Next` chain of outline items, recursing into each item's children.
+fn copy_items(
+    source: &Document,
+    target: &mut Document,
+    first: &Object,
+    parent: Option<u32>,
+    fallback: ObjectId,
+    visited: &mut HashSet<ObjectId>,
+) {
+    let mut next = Some(first);
+
+    while let Some(object) = next.take() {
+        // Guard against malformed outlines that link back to an earlier item.
+        if let Ok(id) = object.as_reference() {
+            if !visited.insert(id) {
+                break;
+            }
+        }
+
+        let item = match deref_dict(source, object) {
+            Ok(item) => item,
+            Err(_) => break,
+        };
+
+        let title = item
+            .get(b"Title")
+            .and_then(|title| source.dereference(title))
+            .and_then(|(_, title)| lopdf::decode_text_string(title))
+            .unwrap_or_default();
+        let page = item_page(source, item)
+            .filter(|page| source.objects.contains_key(page))
+            .unwrap_or(fallback);
+
+        let bookmark = Bookmark::new(title, item_color(item), item_format(item), page);
+        let id = target.add_bookmark(bookmark, parent);
+
+        if let Ok(child) = item.get(b"First") {
+            copy_items(source, target, child, Some(id), page, visited);
+        }
+
+        next = item.get(b"Next").ok();
+    }
+}
+
+/// The page an outline item jumps to, either through `Dest` or a `GoTo` action.
+fn item_page(doc: &Document, item: &Dictionary) -> Option<ObjectId> {
+    if let Ok(dest) = item.get(b"Dest") {
+        return destination_page(doc, dest);
+    }
+
+    let action = item.get(b"A").and_then(|action| deref_dict(doc, action)).ok()?;
+    if action.get(b"S").and_then(Object::as_name).ok()? != b"GoTo" {
+        return None;
+    }
+    destination_page(doc, action.get(b"D").ok()?)
+}
+
+fn item_color(item: &Dictionary) -> [f32; 3] {
+    let mut color = [0.0, 0.0, 0.0];
+    if let Ok(components) = item.get(b"C").and_then(Object::as_array) {
+        for (slot, component) in color.iter_mut().zip(components) {
+            *slot = component.as_float().unwrap_or(0.0);
+        }
+    }
+    color
+}
+
+fn item_format(item: &Dictionary) -> u32 {
+    item.get(b"F")
+        .and_then(Object::as_i64)
+        .map(|flags| (flags & 0b11) as u32)
+        .unwrap_or(0)
+}
+
+/// An item of a document's outline, as `outline_tree` reads it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlineItem {
+    pub title: String,
+    /// The 1-based number of the page the item jumps to, if it jumps to a page of the document.
+    pub page: Option<u32>,
+    pub children: Vec<OutlineItem>,
+}
+
+/// The outline of `doc` as a tree of titles and page numbers, such as the bookmarks a merge
+/// built, in outline order. Empty if `doc` has no outline.
+pub fn outline_tree(doc: &Document) -> Vec<OutlineItem> {
+    let numbers = doc.get_pages().into_iter().map(|(number, id)| (id, number)).collect::<HashMap<_, _>>();
+    let mut visited = HashSet::new();
+    let first = doc
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Outlines"))
+        .and_then(|outlines| deref_dict(doc, outlines))
+        .and_then(|outlines| outlines.get(b"First"));
+    match first {
+        Ok(first) => tree_items(doc, first, &numbers, &mut visited),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Read a `First`/`Next` chain of outline items and their children.
+fn tree_items(
+    doc: &Document,
+    first: &Object,
+    numbers: &HashMap<ObjectId, u32>,
+    visited: &mut HashSet<ObjectId>,
+) -> Vec<OutlineItem> {
+    let mut items = Vec::new();
+    let mut next = Some(first);
+
+    while let Some(object) = next.take() {
+        if let Ok(id) = object.as_reference() {
+            if !visited.insert(id) {
+                break;
+            }
+        }
+        let Ok(item) = deref_dict(doc, object) else {
+            break;
+        };
+
+        let title = item
+            .get(b"Title")
+            .and_then(|title| doc.dereference(title))
+            .and_then(|(_, title)| lopdf::decode_text_string(title))
+            .unwrap_or_default();
+        let page = item_page(doc, item).and_then(|page| numbers.get(&page).copied());
+        let children = match item.get(b"First") {
+            Ok(child) => tree_items(doc, child, numbers, visited),
+            Err(_) => Vec::new(),
+        };
+        items.push(OutlineItem { title, page, children });
+
+        next = item.get(b"Next").ok();
+    }
+    items
+}
+
+/// Rewrite non-ASCII outline titles as UTF-16BE text strings.
+///
+/// `build_outline` writes titles as raw UTF-8 bytes, which viewers read as Latin-1.
+pub(crate) fn encode_titles(doc: &mut Document, outline: ObjectId) {
+    let mut pending = vec![outline];
+    let mut visited = HashSet::new();
+
+    while let Some(id) = pending.pop() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let Ok(item) = doc.get_dictionary_mut(id) else {
+            continue;
+        };
+
+        if let Ok(Object::String(title, _)) = item.get(b"Title") {
+            if let Ok(title) = std::str::from_utf8(title) {
+                if !title.is_ascii() {
+                    item.set("Title", lopdf::text_string(title));
+                }
+            }
+        }
+
+        for key in [b"First".as_slice(), b"Next".as_slice()] {
+            if let Ok(next) = item.get(key).and_then(Object::as_reference) {
+                pending.push(next);
+            }
+        }
+    }
+}
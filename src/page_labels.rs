@@ -0,0 +1,139 @@
+use std::collections::{HashMap, HashSet};
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+
+use crate::destinations::deref_dict;
+
+/// A page's label, as defined by a `/PageLabels` range of its document.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PageLabel {
+    /// The document and the range within it the label comes from, so that ranges that happen
+    /// to share a style are kept apart.
+    range: (usize, usize),
+    /// The range's style and prefix, without its start number.
+    style: Dictionary,
+    /// The page's number within its range.
+    number: i64,
+}
+
+/// The label of each page of `doc`, in page order, or `None` if it doesn't define page labels.
+///
+/// A page before the first range is left unlabeled.
+pub(crate) fn page_labels(doc: &Document) -> Option<Vec<Option<PageLabel>>> {
+    let tree = doc.catalog().ok()?.get(b"PageLabels").ok()?;
+    let mut ranges = Vec::new();
+    collect_ranges(doc, tree, &mut ranges, &mut HashSet::new());
+    if ranges.is_empty() {
+        return None;
+    }
+    ranges.sort_by_key(|(start, _)| *start);
+
+    let page_count = doc.get_pages().len() as i64;
+    let labels = (0..page_count)
+        .map(|index| {
+            let range = ranges.iter().rposition(|(start, _)| *start <= index)?;
+            let (start, style) = &ranges[range];
+            let mut style = style.clone();
+            let first = style.get(b"St").and_then(Object::as_i64).unwrap_or(1);
+            style.remove(b"St");
+            Some(PageLabel { range: (0, range), style, number: first + index - start })
+        })
+        .collect();
+    Some(labels)
+}
+
+/// A `/PageLabels` number tree giving each page in turn its label; unlabeled pages are
+/// numbered with their position in `labels`, starting at 1.
+pub(crate) fn number_tree(labels: impl IntoIterator<Item = Option<PageLabel>>) -> Dictionary {
+    let mut nums = Vec::new();
+    let mut previous: Option<(Option<(usize, usize)>, i64)> = None;
+
+    for (position, label) in labels.into_iter().enumerate() {
+        let (range, style, number) = match label {
+            Some(label) => (Some(label.range), label.style, label.number),
+            None => (None, dictionary! { "S" => "D" }, position as i64 + 1),
+        };
+
+        // A page continues the previous page's range when it is the next page of that range.
+        let continues = previous.is_some_and(|(previous_range, previous_number)| {
+            previous_range == range && previous_number + 1 == number
+        });
+        if !continues {
+            let mut style = style;
+            if number != 1 {
+                style.set("St", number);
+            }
+            nums.push(Object::Integer(position as i64));
+            nums.push(Object::Dictionary(style));
+        }
+        previous = Some((range, number));
+    }
+
+    dictionary! { "Nums" => nums }
+}
+
+/// The page labels of the merged documents, combined into one `/PageLabels` number tree.
+#[derive(Default)]
+pub(crate) struct PageLabels {
+    labels: HashMap<ObjectId, PageLabel>,
+    documents: usize,
+}
+
+impl PageLabels {
+    /// Take over the page labels of `doc`, which must already be renumbered into the merged
+    /// document's ID space.
+    pub(crate) fn add(&mut self, doc: &Document) {
+        let document = self.documents;
+        self.documents += 1;
+        let Some(labels) = page_labels(doc) else {
+            return;
+        };
+
+        for (page_id, label) in doc.get_pages().into_values().zip(labels) {
+            if let Some(mut label) = label {
+                label.range.0 = document;
+                self.labels.insert(page_id, label);
+            }
+        }
+    }
+
+    /// The number tree labeling the pages of `page_order` with their labels in their own
+    /// documents, or `None` if no document had page labels.
+    ///
+    /// Pages from documents without labels, and generated pages, are numbered with their
+    /// position in the merged document.
+    pub(crate) fn into_number_tree(mut self, page_order: &[ObjectId]) -> Option<Dictionary> {
+        if self.labels.is_empty() {
+            return None;
+        }
+        Some(number_tree(page_order.iter().map(|page_id| self.labels.remove(page_id))))
+    }
+}
+
+/// Every `(start page index, range style)` pair in a number tree, in tree order.
+fn collect_ranges(doc: &Document, node: &Object, ranges: &mut Vec<(i64, Dictionary)>, visited: &mut HashSet<ObjectId>) {
+    if let Ok(id) = node.as_reference() {
+        if !visited.insert(id) {
+            return;
+        }
+    }
+    let Ok(node) = deref_dict(doc, node) else {
+        return;
+    };
+
+    if let Ok(nums) = node.get(b"Nums").and_then(Object::as_array) {
+        for pair in nums.chunks(2) {
+            if let [Object::Integer(start), style] = pair {
+                if let Ok(style) = deref_dict(doc, style) {
+                    ranges.push((*start, style.clone()));
+                }
+            }
+        }
+    }
+
+    if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+        for kid in kids {
+            collect_ranges(doc, kid, ranges, visited);
+        }
+    }
+}
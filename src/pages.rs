@@ -0,0 +1,368 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+
+use crate::page_labels;
+
+/// An inclusive range of 1-based page numbers. `end` of `None` means "to the last page".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRange {
+    pub start: u32,
+    pub end: Option<u32>,
+}
+
+impl fmt::Display for PageRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.end {
+            Some(end) if end == self.start => write!(f, "{}", self.start),
+            Some(end) => write!(f, "{}-{}", self.start, end),
+            None => write!(f, "{}-", self.start),
+        }
+    }
+}
+
+impl FromStr for PageRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| match n.trim().parse::<u32>() {
+            Ok(0) => Err("page numbers start at 1".to_string()),
+            Ok(n) => Ok(n),
+            Err(_) => Err(format!("invalid page number {:?}", n)),
+        };
+
+        let range = match s.split_once('-') {
+            Some((start, "")) => PageRange { start: parse(start)?, end: None },
+            Some((start, end)) => PageRange { start: parse(start)?, end: Some(parse(end)?) },
+            None => {
+                let page = parse(s)?;
+                PageRange { start: page, end: Some(page) }
+            }
+        };
+
+        if range.end.is_some_and(|end| end < range.start) {
+            return Err(format!("page range {} is backwards", s));
+        }
+        Ok(range)
+    }
+}
+
+/// A comma-separated list of page ranges, e.g. `1-3,7,10-`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageSelection(pub Vec<PageRange>);
+
+impl PageSelection {
+    /// Whether the 1-based page `page` is selected.
+    pub fn contains(&self, page: u32) -> bool {
+        self.0
+            .iter()
+            .any(|range| page >= range.start && range.end.is_none_or(|end| page <= end))
+    }
+}
+
+impl fmt::Display for PageSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranges = self.0.iter().map(PageRange::to_string).collect::<Vec<_>>();
+        write!(f, "{}", ranges.join(","))
+    }
+}
+
+impl FromStr for PageSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',').map(str::parse).collect::<Result<_, _>>().map(PageSelection)
+    }
+}
+
+/// A page range that reaches past the end of its document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageOutOfRange {
+    pub range: PageRange,
+    pub page_count: usize,
+}
+
+impl fmt::Display for PageOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "page range {} is out of range (document has {} pages)", self.range, self.page_count)
+    }
+}
+
+impl std::error::Error for PageOutOfRange {}
+
+/// Remove every page of `doc` that `selection` does not include.
+///
+/// Kept pages stay in document order; listing a page twice does not duplicate it. Page labels
+/// are renumbered so each kept page keeps its label.
+pub fn select_pages(doc: &mut Document, selection: &PageSelection) -> Result<(), PageOutOfRange> {
+    let page_count = doc.get_pages().len();
+
+    for range in &selection.0 {
+        let last = range.end.unwrap_or(range.start);
+        if last as usize > page_count {
+            return Err(PageOutOfRange { range: *range, page_count });
+        }
+    }
+
+    let labels = page_labels::page_labels(doc);
+    let unselected = (1..=page_count as u32)
+        .filter(|&page| !selection.contains(page))
+        .collect::<Vec<_>>();
+    doc.delete_pages(&unselected);
+
+    // Page labels are assigned by page index, so they have to follow the kept pages.
+    if let Some(labels) = labels {
+        let kept = (1..).zip(labels).filter(|(page, _)| selection.contains(*page)).map(|(_, label)| label);
+        let tree = page_labels::number_tree(kept);
+        if let Ok(catalog) = doc.catalog_mut() {
+            catalog.set("PageLabels", tree);
+        }
+    }
+
+    Ok(())
+}
+
+/// A clockwise page rotation: 0, 90, 180 or 270 degrees.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rotation(u16);
+
+impl Rotation {
+    /// The rotation for `degrees`, which must be a multiple of 90. Negative values turn
+    /// counter-clockwise, so `-90` is the same as `270`.
+    pub fn from_degrees(degrees: i64) -> Option<Rotation> {
+        (degrees % 90 == 0).then(|| Rotation(degrees.rem_euclid(360) as u16))
+    }
+
+    pub fn degrees(self) -> u16 {
+        self.0
+    }
+
+    /// Add this rotation to the `Rotate` value of a page dictionary.
+    pub(crate) fn apply(self, page: &mut Dictionary) {
+        if self.0 == 0 {
+            return;
+        }
+        let current = page.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0);
+        page.set("Rotate", (current + i64::from(self.0)).rem_euclid(360));
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .parse()
+            .ok()
+            .and_then(Rotation::from_degrees)
+            .ok_or_else(|| format!("invalid rotation {:?}: expected a multiple of 90 degrees", s))
+    }
+}
+
+/// Which way round a page is displayed.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    /// Taller than wide.
+    Portrait,
+    /// Wider than tall.
+    Landscape,
+}
+
+impl Orientation {
+    /// Turn a self-contained page dictionary a quarter turn clockwise if it is displayed the
+    /// other way round, taking its `Rotate` into account. Square pages are left as they are.
+    ///
+    /// Returns whether the page was turned.
+    pub(crate) fn apply(self, page: &mut Dictionary) -> bool {
+        let Some([llx, lly, urx, ury]) = page
+            .get(b"MediaBox")
+            .and_then(Object::as_array)
+            .ok()
+            .and_then(|values| values.iter().map(|value| value.as_float().ok()).collect::<Option<Vec<_>>>())
+            .and_then(|values| <[f32; 4]>::try_from(values).ok())
+        else {
+            return false;
+        };
+
+        let (mut width, mut height) = ((urx - llx).abs(), (ury - lly).abs());
+        if page.get(b"Rotate").and_then(Object::as_i64).unwrap_or(0).rem_euclid(180) == 90 {
+            (width, height) = (height, width);
+        }
+        let turn = match self {
+            Orientation::Portrait => width > height,
+            Orientation::Landscape => height > width,
+        };
+        if turn {
+            Rotation(90).apply(page);
+        }
+        turn
+    }
+}
+
+/// Turn every page of `doc` by `rotation`, on top of any rotation it already has.
+pub fn rotate_pages(doc: &mut Document, rotation: Rotation) {
+    if rotation.degrees() == 0 {
+        return;
+    }
+    for page_id in doc.get_pages().into_values() {
+        let Ok(mut page) = doc.get_dictionary(page_id).cloned() else {
+            continue;
+        };
+        if !page.has(b"Rotate") {
+            if let Some(rotate) = inherited_attribute(doc, page_id, b"Rotate") {
+                page.set("Rotate", rotate.clone());
+            }
+        }
+        rotation.apply(&mut page);
+        doc.objects.insert(page_id, Object::Dictionary(page));
+    }
+}
+
+/// The object IDs of every page of `doc`, in document order: the first ID is page 1.
+///
+/// IDs returned for a merged document stay valid as long as it isn't renumbered, so they
+/// can be used to stamp or annotate particular pages after merging.
+pub fn page_ids(doc: &Document) -> Vec<ObjectId> {
+    doc.get_pages().into_values().collect()
+}
+
+/// Page attributes that a page may inherit from its ancestors in the page tree.
+pub(crate) const INHERITABLE_ATTRIBUTES: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+/// A copy of the page dictionary with every inherited attribute set on the page itself, so the
+/// page no longer depends on the page tree it came from.
+pub(crate) fn with_inherited_attributes(doc: &Document, page_id: ObjectId) -> Dictionary {
+    let mut page = doc.get_dictionary(page_id).cloned().unwrap_or_default();
+
+    if let Ok(parent) = page.get(b"Parent").and_then(Object::as_reference) {
+        for key in INHERITABLE_ATTRIBUTES {
+            if page.has(key) {
+                continue;
+            }
+            // Copy the value as written, so shared resources stay shared by reference.
+            if let Some(value) = ancestor_attribute(doc, parent, key) {
+                page.set(key, value.clone());
+            }
+        }
+    }
+
+    page
+}
+
+/// Catalog entries `extract_pages` leaves out: the page tree and outline it doesn't copy, page
+/// labels numbered for the whole document, and a structure tree that reaches every page.
+const EXTRACTED_CATALOG_SKIPS: [&[u8]; 4] = [b"Pages", b"Outlines", b"PageLabels", b"StructTreeRoot"];
+
+/// A document of `page_ids` of `doc`, in that order, under a page tree of their own, holding
+/// only the objects those pages and the rest of `doc`'s catalog reach without going through
+/// another page or the page tree. Objects keep their IDs from `doc`.
+///
+/// Unlike cloning `doc` and deleting the other pages, this copies only what the pages use, so
+/// taking a few pages at a time out of a long document doesn't copy all of it each time.
+/// References to pages left out, such as those of links, are left dangling.
+pub(crate) fn extract_pages(doc: &Document, page_ids: &[ObjectId]) -> Document {
+    let mut part = Document::with_version(doc.version.clone());
+    let pages_id = (doc.max_id + 1, 0);
+    let catalog_id = (doc.max_id + 2, 0);
+    part.max_id = doc.max_id + 2;
+
+    let mut catalog = doc.catalog().cloned().unwrap_or_default();
+    for key in EXTRACTED_CATALOG_SKIPS {
+        catalog.remove(key);
+    }
+    catalog.set("Type", "Catalog");
+    catalog.set("Pages", pages_id);
+
+    let kept = page_ids.iter().copied().collect::<HashSet<_>>();
+    let mut pending = Vec::new();
+    collect_references(&Object::Dictionary(catalog.clone()), &mut pending);
+    for &page_id in page_ids {
+        let mut page = with_inherited_attributes(doc, page_id);
+        page.set("Parent", pages_id);
+        collect_references(&Object::Dictionary(page.clone()), &mut pending);
+        part.objects.insert(page_id, Object::Dictionary(page));
+    }
+
+    while let Some(id) = pending.pop() {
+        if part.objects.contains_key(&id) {
+            continue;
+        }
+        let Some(object) = doc.objects.get(&id) else {
+            continue;
+        };
+        let other_page = match object.type_name() {
+            Ok("Page") => !kept.contains(&id),
+            Ok("Pages") => true,
+            _ => false,
+        };
+        if other_page {
+            continue;
+        }
+        collect_references(object, &mut pending);
+        part.objects.insert(id, object.clone());
+    }
+
+    let kids = page_ids.iter().copied().map(Object::Reference).collect::<Vec<_>>();
+    part.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Count" => kids.len() as i64,
+            "Kids" => kids,
+        }),
+    );
+    part.objects.insert(catalog_id, Object::Dictionary(catalog));
+    part.trailer.set("Root", catalog_id);
+    part
+}
+
+/// Push the ID of every object `object` refers to.
+fn collect_references(object: &Object, references: &mut Vec<ObjectId>) {
+    match object {
+        Object::Reference(id) => references.push(*id),
+        Object::Array(items) => items.iter().for_each(|item| collect_references(item, references)),
+        Object::Dictionary(dict) => dict.iter().for_each(|(_, value)| collect_references(value, references)),
+        Object::Stream(stream) => stream.dict.iter().for_each(|(_, value)| collect_references(value, references)),
+        _ => {}
+    }
+}
+
+/// Look up an inheritable page attribute such as `MediaBox`, following `Parent` links up the
+/// page tree.
+pub(crate) fn inherited_attribute<'a>(doc: &'a Document, page_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let value = ancestor_attribute(doc, page_id, key)?;
+    doc.dereference(value).ok().map(|(_, value)| value)
+}
+
+fn ancestor_attribute<'a>(doc: &'a Document, mut node_id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    // The depth limit guards against cycles in malformed page trees.
+    for _ in 0..256 {
+        let node = doc.get_dictionary(node_id).ok()?;
+        if let Ok(value) = node.get(key) {
+            return Some(value);
+        }
+        node_id = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+    }
+    None
+}
+
+/// The page's `MediaBox` as `[llx, lly, urx, ury]`, or US Letter if it has none.
+pub fn media_box(doc: &Document, page_id: ObjectId) -> [f32; 4] {
+    let mut media_box = [0.0, 0.0, 612.0, 792.0];
+    if let Some(Object::Array(values)) = inherited_attribute(doc, page_id, b"MediaBox") {
+        if values.len() == 4 {
+            for (slot, value) in media_box.iter_mut().zip(values) {
+                *slot = value.as_float().unwrap_or(*slot);
+            }
+        }
+    }
+    media_box
+}
@@ -0,0 +1,94 @@
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, Stream};
+
+use crate::toc::win_ansi;
+use crate::PaperSize;
+
+const FONT_NAME: &str = "MergePdfPlaceholder";
+const FONT_SIZE: f32 = 12.0;
+const LEADING: f32 = 16.0;
+const MARGIN: f32 = 72.0;
+/// Lines are broken at spaces to stay within the margins of a Letter or A4 page.
+const MAX_LINE_CHARS: usize = 80;
+
+/// A one-page document of `size` reading `message`, to stand in for an input that couldn't
+/// be loaded.
+///
+/// The text is set in Helvetica, from the top left corner.
+pub fn placeholder_document(message: &str, size: PaperSize) -> Document {
+    let mut doc = Document::with_version("1.5");
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+
+    let mut operations = vec![
+        Operation::new("BT", vec![]),
+        Operation::new("Tf", vec![Object::Name(FONT_NAME.into()), FONT_SIZE.into()]),
+        Operation::new("TL", vec![LEADING.into()]),
+        Operation::new("Td", vec![MARGIN.into(), (size.height - MARGIN).into()]),
+    ];
+    for line in wrap(message) {
+        operations.push(Operation::new("Tj", vec![Object::string_literal(win_ansi(&line))]));
+        operations.push(Operation::new("T*", vec![]));
+    }
+    operations.push(Operation::new("ET", vec![]));
+    let content = Content { operations }.encode().unwrap_or_default();
+    let content_id = doc.add_object(Stream::new(dictionary! {}, content));
+
+    let pages_id = doc.new_object_id();
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => size.media_box(),
+        "Contents" => content_id,
+        "Resources" => dictionary! {
+            "Font" => dictionary! { FONT_NAME => font_id },
+        },
+    });
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+    doc
+}
+
+/// Break `text` into lines of at most `MAX_LINE_CHARS` characters, at spaces where possible.
+fn wrap(text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word = word.to_string();
+        // Words longer than a line, such as paths, are broken wherever they have to be.
+        while word.chars().count() > MAX_LINE_CHARS {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            let rest = word.split_off(word.char_indices().nth(MAX_LINE_CHARS).map_or(word.len(), |(i, _)| i));
+            lines.push(std::mem::replace(&mut word, rest));
+        }
+
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > MAX_LINE_CHARS {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
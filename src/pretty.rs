@@ -0,0 +1,107 @@
+use std::io::{self, Write};
+
+use lopdf::{Document, Object};
+
+use crate::linearize::{write_name, write_object};
+
+/// Trailer entries that only mean something next to a cross-reference stream or an earlier
+/// revision, neither of which the readable layout writes.
+const STALE_TRAILER_KEYS: [&[u8]; 8] = [b"Prev", b"XRefStm", b"Type", b"W", b"Index", b"Length", b"Filter", b"DecodeParms"];
+
+/// Write `doc` laid out for reading: every object uncompressed and in its own section, in
+/// object number order, with one dictionary entry per line, followed by a classic
+/// cross-reference table. There are no object streams or cross-reference streams.
+///
+/// Streams whose filters lopdf can't undo, such as JPEG images, are written as they are.
+pub(crate) fn save_pretty<W: Write>(doc: &Document, target: &mut W) -> io::Result<()> {
+    let mut output = format!("%PDF-{}\n", doc.version).into_bytes();
+    let size = doc.objects.keys().map(|(number, _)| number + 1).max().unwrap_or(1);
+    let mut entries = vec![None; size as usize];
+
+    for (&(number, generation), object) in &doc.objects {
+        if matches!(object.type_name().ok(), Some("ObjStm" | "XRef" | "Linearized")) {
+            continue;
+        }
+        output.push(b'\n');
+        if let Ok(type_name) = object.type_name() {
+            output.extend_from_slice(format!("% {}\n", type_name).as_bytes());
+        }
+        entries[number as usize] = Some((output.len(), generation));
+        output.extend_from_slice(format!("{} {} obj\n", number, generation).as_bytes());
+        write_pretty(&mut output, &uncompressed(object), 0);
+        output.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref_offset = output.len();
+    output.extend_from_slice(format!("xref\n0 {}\n", size).as_bytes());
+    for entry in &entries {
+        let line = match entry {
+            Some((offset, generation)) => format!("{:010} {:05} n\r\n", offset, generation),
+            None => "0000000000 65535 f\r\n".to_string(),
+        };
+        output.extend_from_slice(line.as_bytes());
+    }
+    let mut trailer = doc.trailer.clone();
+    STALE_TRAILER_KEYS.iter().for_each(|key| {
+        trailer.remove(key);
+    });
+    trailer.set("Size", i64::from(size));
+    output.extend_from_slice(b"trailer\n");
+    write_pretty(&mut output, &Object::Dictionary(trailer), 0);
+    output.extend_from_slice(format!("\nstartxref\n{}\n%%EOF\n", xref_offset).as_bytes());
+    target.write_all(&output)
+}
+
+/// `object`, decompressed if it is a compressed stream lopdf can decompress.
+fn uncompressed(object: &Object) -> Object {
+    let mut object = object.clone();
+    if let Object::Stream(stream) = &mut object {
+        if stream.dict.has(b"Filter") {
+            if let Ok(content) = stream.decompressed_content() {
+                stream.dict.remove(b"Filter");
+                stream.dict.remove(b"DecodeParms");
+                stream.set_content(content);
+            }
+        }
+    }
+    object
+}
+
+/// Serialize `object` with dictionaries, and arrays holding dictionaries, spread over one
+/// line per entry, indented by `depth`.
+fn write_pretty(bytes: &mut Vec<u8>, object: &Object, depth: usize) {
+    let indent = "  ".repeat(depth + 1);
+    match object {
+        Object::Dictionary(dict) if !dict.is_empty() => {
+            bytes.extend_from_slice(b"<<\n");
+            for (key, value) in dict.iter() {
+                bytes.extend_from_slice(indent.as_bytes());
+                write_name(bytes, key);
+                bytes.push(b' ');
+                write_pretty(bytes, value, depth + 1);
+                bytes.push(b'\n');
+            }
+            bytes.extend_from_slice("  ".repeat(depth).as_bytes());
+            bytes.extend_from_slice(b">>");
+        }
+        Object::Array(items) if items.iter().any(|item| matches!(item, Object::Dictionary(_))) => {
+            bytes.extend_from_slice(b"[\n");
+            for item in items {
+                bytes.extend_from_slice(indent.as_bytes());
+                write_pretty(bytes, item, depth + 1);
+                bytes.push(b'\n');
+            }
+            bytes.extend_from_slice("  ".repeat(depth).as_bytes());
+            bytes.push(b']');
+        }
+        Object::Stream(stream) => {
+            let mut dict = stream.dict.clone();
+            dict.set("Length", stream.content.len() as i64);
+            write_pretty(bytes, &Object::Dictionary(dict), depth);
+            bytes.extend_from_slice(b"\nstream\n");
+            bytes.extend_from_slice(&stream.content);
+            bytes.extend_from_slice(b"\nendstream");
+        }
+        _ => write_object(bytes, object),
+    }
+}
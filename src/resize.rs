@@ -0,0 +1,203 @@
+use std::fmt;
+use std::str::FromStr;
+
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::pages;
+
+/// The size of the pages the merge generates (separators, the table of contents and
+/// placeholders), in points. Parsed from a standard paper size such as `a4`, or `WIDTHxHEIGHT`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaperSize {
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Standard paper sizes, portrait, by the name they are parsed from.
+const PAPER_SIZES: [(&str, PaperSize); 6] = [
+    ("a3", PaperSize { width: 841.89, height: 1190.55 }),
+    ("a4", PaperSize { width: 595.28, height: 841.89 }),
+    ("a5", PaperSize { width: 419.53, height: 595.28 }),
+    ("letter", PaperSize::LETTER),
+    ("legal", PaperSize { width: 612.0, height: 1008.0 }),
+    ("tabloid", PaperSize { width: 792.0, height: 1224.0 }),
+];
+
+/// The largest page side PDF readers are required to support, 200 inches.
+const MAX_SIDE: f32 = 14400.0;
+
+impl PaperSize {
+    /// 8.5 x 11 in, the size used when nothing else is given.
+    pub const LETTER: PaperSize = PaperSize { width: 612.0, height: 792.0 };
+
+    /// A `MediaBox` of this size.
+    pub(crate) fn media_box(self) -> Object {
+        vec![0.into(), 0.into(), self.width.into(), self.height.into()].into()
+    }
+}
+
+impl Default for PaperSize {
+    fn default() -> Self {
+        PaperSize::LETTER
+    }
+}
+
+impl fmt::Display for PaperSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match PAPER_SIZES.iter().find(|(_, size)| size == self) {
+            Some((name, _)) => f.write_str(name),
+            None => write!(f, "{}x{}", self.width, self.height),
+        }
+    }
+}
+
+impl FromStr for PaperSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some((_, size)) = PAPER_SIZES.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+            return Ok(*size);
+        }
+
+        let names = PAPER_SIZES.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ");
+        let invalid = || format!("invalid page size {:?}: expected {} or WIDTHxHEIGHT in points", s, names);
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let side = |value: &str| {
+            value
+                .trim()
+                .parse::<f32>()
+                .ok()
+                .filter(|side| *side > 0.0 && *side <= MAX_SIDE)
+                .ok_or_else(invalid)
+        };
+        Ok(PaperSize { width: side(width)?, height: side(height)? })
+    }
+}
+
+/// A common size to give every page of the merged PDF.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageSize {
+    /// 210 x 297 mm.
+    A4,
+    /// 8.5 x 11 in.
+    Letter,
+    /// The widest page width and the tallest page height found in the merged PDF.
+    FitLargest,
+}
+
+/// Boxes that are given in the page's old coordinates and would no longer line up.
+const PAGE_BOXES: [&[u8]; 4] = [b"CropBox", b"BleedBox", b"TrimBox", b"ArtBox"];
+
+/// Give every page of `doc` the same `MediaBox`, scaling its content to fit and centring it.
+///
+/// A4 and Letter pages are turned landscape for pages that are wider than they are tall, so
+/// sideways pages aren't shrunk to fit a portrait sheet. Content is never distorted: pages with
+/// a different aspect ratio get margins.
+pub(crate) fn normalize_page_sizes(doc: &mut Document, size: PageSize) {
+    let pages = doc.get_pages().into_values().collect::<Vec<_>>();
+    let boxes = pages.iter().map(|&page_id| pages::media_box(doc, page_id)).collect::<Vec<_>>();
+
+    let largest = boxes.iter().fold((0.0f32, 0.0f32), |(width, height), [llx, lly, urx, ury]| {
+        (width.max(urx - llx), height.max(ury - lly))
+    });
+
+    for (page_id, [llx, lly, urx, ury]) in pages.into_iter().zip(boxes) {
+        let (width, height) = (urx - llx, ury - lly);
+        if width <= 0.0 || height <= 0.0 {
+            continue;
+        }
+
+        let (target_width, target_height) = match size {
+            PageSize::A4 => orient((595.28, 841.89), width, height),
+            PageSize::Letter => orient((612.0, 792.0), width, height),
+            PageSize::FitLargest => largest,
+        };
+        let scale = (target_width / width).min(target_height / height);
+        let dx = (target_width - width * scale) / 2.0 - llx * scale;
+        let dy = (target_height - height * scale) / 2.0 - lly * scale;
+
+        transform_content(doc, page_id, [scale, 0.0, 0.0, scale, dx, dy]);
+        transform_annotations(doc, page_id, scale, dx, dy);
+
+        if let Ok(page) = doc.get_dictionary_mut(page_id) {
+            page.set(
+                "MediaBox",
+                vec![0.into(), 0.into(), target_width.into(), target_height.into()],
+            );
+            for key in PAGE_BOXES {
+                page.remove(key);
+            }
+        }
+    }
+}
+
+/// `(width, height)` of a portrait sheet, turned landscape if the page is.
+fn orient((short, long): (f32, f32), width: f32, height: f32) -> (f32, f32) {
+    if width > height {
+        (long, short)
+    } else {
+        (short, long)
+    }
+}
+
+/// Draw the page's existing content through the matrix `cm`.
+fn transform_content(doc: &mut Document, page_id: ObjectId, cm: [f32; 6]) {
+    let existing = match doc.get_dictionary(page_id).and_then(|page| page.get(b"Contents")) {
+        Ok(Object::Reference(id)) => vec![Object::Reference(*id)],
+        Ok(Object::Array(contents)) => contents.clone(),
+        _ => return,
+    };
+
+    let matrix = cm.iter().map(|value| format!("{:.4}", value)).collect::<Vec<_>>().join(" ");
+    let before = doc.add_object(Stream::new(Dictionary::new(), format!("q {} cm\n", matrix).into_bytes()));
+    let after = doc.add_object(Stream::new(Dictionary::new(), b"\nQ\n".to_vec()));
+
+    let mut contents = Vec::with_capacity(existing.len() + 2);
+    contents.push(before.into());
+    contents.extend(existing);
+    contents.push(after.into());
+
+    if let Ok(page) = doc.get_dictionary_mut(page_id) {
+        page.set("Contents", contents);
+    }
+}
+
+/// Move the page's annotations along with its content.
+fn transform_annotations(doc: &mut Document, page_id: ObjectId, scale: f32, dx: f32, dy: f32) {
+    let transform = |annot: &mut Dictionary| {
+        if let Ok(Object::Array(rect)) = annot.get_mut(b"Rect") {
+            for (index, value) in rect.iter_mut().enumerate() {
+                if let Ok(v) = value.as_float() {
+                    let offset = if index % 2 == 0 { dx } else { dy };
+                    *value = (v * scale + offset).into();
+                }
+            }
+        }
+    };
+
+    let annots = match doc.get_dictionary(page_id).and_then(|page| page.get(b"Annots")) {
+        Ok(Object::Array(annots)) => annots.clone(),
+        Ok(Object::Reference(id)) => match doc.get_object(*id) {
+            Ok(Object::Array(annots)) => annots.clone(),
+            _ => return,
+        },
+        _ => return,
+    };
+
+    for annot in annots {
+        if let Ok(id) = annot.as_reference() {
+            if let Ok(annot) = doc.get_dictionary_mut(id) {
+                transform(annot);
+            }
+        }
+    }
+    // Annotations written inline in the page's own array.
+    if let Ok(Object::Array(annots)) = doc.get_dictionary_mut(page_id).and_then(|page| page.get_mut(b"Annots")) {
+        for annot in annots.iter_mut() {
+            if let Object::Dictionary(annot) = annot {
+                transform(annot);
+            }
+        }
+    }
+}
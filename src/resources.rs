@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use log::debug;
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::destinations::deref_dict;
+use crate::pages;
+
+/// The resource categories content streams refer to by name.
+const CATEGORIES: [&[u8]; 7] = [b"Font", b"XObject", b"ExtGState", b"ColorSpace", b"Pattern", b"Shading", b"Properties"];
+
+/// Rename page resources so that every name stands for the same resource on every page of
+/// `doc`, as if all the pages drew from one resource dictionary.
+///
+/// Pages are taken in page order, and the first resource to use a name keeps it; a page whose
+/// resource of the same category and name is a different one gets it renamed `name_2`,
+/// `name_3` and so on, in a resource dictionary of its own, and its content rewritten into one
+/// new content stream to match. A page is left as it is if its content can't be parsed, for
+/// example because it has an inline image, or if it draws a form XObject without resources of
+/// its own, which would use the page's.
+///
+/// Returns the number of resources renamed.
+pub(crate) fn normalize_resource_names(doc: &mut Document) -> usize {
+    // The resource each category and name stands for, as the first page with it uses it.
+    let mut owners = HashMap::<(&[u8], Vec<u8>), Object>::new();
+    let page_ids = doc.page_iter().collect::<Vec<_>>();
+    let mut content_users = HashMap::<ObjectId, usize>::new();
+    for page_id in &page_ids {
+        for id in content_ids(doc, *page_id).unwrap_or_default() {
+            *content_users.entry(id).or_default() += 1;
+        }
+    }
+
+    let mut renamed = 0;
+    for page_id in page_ids {
+        let Some(resources) = pages::inherited_attribute(doc, page_id, b"Resources")
+            .and_then(|resources| deref_dict(doc, resources).ok())
+            .cloned()
+        else {
+            continue;
+        };
+        let categories = CATEGORIES
+            .iter()
+            .filter_map(|&category| {
+                let dict = resources.get(category).and_then(|dict| deref_dict(doc, dict)).ok()?;
+                Some((category, dict.clone()))
+            })
+            .collect::<Vec<_>>();
+
+        let mut renames = HashMap::<(&[u8], Vec<u8>), Vec<u8>>::new();
+        for (category, dict) in &categories {
+            for (name, value) in dict.iter() {
+                match owners.get(&(*category, name.clone())) {
+                    Some(owner) if owner != value => {
+                        let free = |candidate: &Vec<u8>| match owners.get(&(*category, candidate.clone())) {
+                            Some(owner) => owner == value,
+                            None => !dict.has(candidate),
+                        };
+                        let new_name = (2..)
+                            .map(|suffix| [name.as_slice(), format!("_{}", suffix).as_bytes()].concat())
+                            .find(free)
+                            .expect("some suffix is free");
+                        renames.insert((*category, name.clone()), new_name);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let rewritten = if renames.is_empty() { false } else { rename_in_page(doc, page_id, &renames, &content_users) };
+        if rewritten {
+            let mut resources = resources;
+            for (category, dict) in &categories {
+                let mut renamed_dict = Dictionary::new();
+                for (name, value) in dict.iter() {
+                    let name = renames.get(&(*category, name.clone())).unwrap_or(name);
+                    renamed_dict.set(name.clone(), value.clone());
+                }
+                resources.set(category.to_vec(), renamed_dict);
+            }
+            if let Ok(page) = doc.get_dictionary_mut(page_id) {
+                page.set("Resources", resources);
+            }
+            debug!("Renamed {} resource(s) of page {:?}", renames.len(), page_id);
+            renamed += renames.len();
+        } else if !renames.is_empty() {
+            debug!("Can't rename the resources of page {:?}; its content is left as it is", page_id);
+        }
+
+        for (category, dict) in &categories {
+            for (name, value) in dict.iter() {
+                let name = if rewritten { renames.get(&(*category, name.clone())).unwrap_or(name) } else { name };
+                owners.entry((*category, name.clone())).or_insert_with(|| value.clone());
+            }
+        }
+    }
+    renamed
+}
+
+/// The content streams of the page, or `None` if `Contents` is neither a reference nor an
+/// array of them.
+fn content_ids(doc: &Document, page_id: ObjectId) -> Option<Vec<ObjectId>> {
+    match doc.get_dictionary(page_id).ok()?.get(b"Contents") {
+        Ok(Object::Reference(id)) => Some(vec![*id]),
+        Ok(Object::Array(contents)) => contents.iter().map(|content| content.as_reference().ok()).collect(),
+        Ok(_) => None,
+        Err(_) => Some(Vec::new()),
+    }
+}
+
+/// Rewrite the page's content with `renames` into a new content stream, removing the old
+/// streams no other page uses. Returns `false`, leaving the page as it is, if it can't be.
+fn rename_in_page(
+    doc: &mut Document,
+    page_id: ObjectId,
+    renames: &HashMap<(&[u8], Vec<u8>), Vec<u8>>,
+    content_users: &HashMap<ObjectId, usize>,
+) -> bool {
+    if draws_forms_without_resources(doc, page_id) {
+        return false;
+    }
+    let Some(ids) = content_ids(doc, page_id) else {
+        return false;
+    };
+    // The streams of a page form one content stream, split anywhere between tokens.
+    let mut bytes = Vec::new();
+    for id in &ids {
+        let Ok(stream) = doc.get_object(*id).and_then(Object::as_stream) else {
+            return false;
+        };
+        let content = if stream.dict.has(b"Filter") {
+            match stream.decompressed_content() {
+                Ok(content) => content,
+                Err(_) => return false,
+            }
+        } else {
+            stream.content.clone()
+        };
+        bytes.extend(content);
+        bytes.push(b'\n');
+    }
+    let Ok(mut content) = Content::decode(&bytes) else {
+        return false;
+    };
+
+    for operation in &mut content.operations {
+        let (category, operand): (&[u8], usize) = match operation.operator.as_str() {
+            "Tf" => (b"Font", 0),
+            "Do" => (b"XObject", 0),
+            "gs" => (b"ExtGState", 0),
+            "cs" | "CS" => (b"ColorSpace", 0),
+            "scn" | "SCN" => (b"Pattern", operation.operands.len().saturating_sub(1)),
+            "sh" => (b"Shading", 0),
+            "BDC" | "DP" => (b"Properties", 1),
+            _ => continue,
+        };
+        if let Some(Object::Name(name)) = operation.operands.get_mut(operand) {
+            if let Some(new_name) = renames.get(&(category, name.clone())) {
+                *name = new_name.clone();
+            }
+        }
+    }
+    let Ok(encoded) = content.encode() else {
+        return false;
+    };
+
+    let new_id = doc.add_object(Stream::new(Dictionary::new(), encoded));
+    if let Ok(page) = doc.get_dictionary_mut(page_id) {
+        page.set("Contents", new_id);
+    }
+    for id in ids {
+        if content_users.get(&id) == Some(&1) {
+            doc.objects.remove(&id);
+        }
+    }
+    true
+}
+
+/// Whether the page draws a form XObject that has no resources of its own.
+fn draws_forms_without_resources(doc: &Document, page_id: ObjectId) -> bool {
+    let Some(xobjects) = pages::inherited_attribute(doc, page_id, b"Resources")
+        .and_then(|resources| deref_dict(doc, resources).ok())
+        .and_then(|resources| resources.get(b"XObject").and_then(|xobjects| deref_dict(doc, xobjects)).ok())
+    else {
+        return false;
+    };
+    xobjects.iter().any(|(_, xobject)| {
+        let Ok((_, Object::Stream(stream))) = doc.dereference(xobject) else {
+            return false;
+        };
+        stream.dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Form".as_slice()) && !stream.dict.has(b"Resources")
+    })
+}
@@ -0,0 +1,112 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The order in which collected input files are merged.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// By path, case-insensitive, with numbers compared by value (also accepted as `natural`).
+    #[default]
+    #[value(alias = "natural")]
+    Name,
+    /// Like `name`, but descending.
+    NameDesc,
+    /// By modification time, oldest first.
+    Mtime,
+    /// By file size, smallest first.
+    Size,
+}
+
+/// Sort `paths` in place according to `order`.
+///
+/// Ties (equal mtime or size) fall back to name order so the result is stable across platforms.
+pub fn sort_paths(paths: &mut [PathBuf], order: SortOrder) {
+    match order {
+        SortOrder::Name => paths.sort_by(|a, b| name_cmp(a, b)),
+        SortOrder::NameDesc => paths.sort_by(|a, b| name_cmp(b, a)),
+        SortOrder::Mtime => paths.sort_by_cached_key(|path| {
+            let modified = fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (modified, NameKey(path.clone()))
+        }),
+        SortOrder::Size => paths.sort_by_cached_key(|path| {
+            let size = fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+            (size, NameKey(path.clone()))
+        }),
+    }
+}
+
+/// Wraps a path so it orders by `name_cmp` inside sort keys.
+#[derive(PartialEq, Eq)]
+struct NameKey(PathBuf);
+
+impl PartialOrd for NameKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for NameKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        name_cmp(&self.0, &other.0)
+    }
+}
+
+fn name_cmp(a: &Path, b: &Path) -> Ordering {
+    let a = a.to_string_lossy();
+    let b = b.to_string_lossy();
+    natural_cmp(&a.to_lowercase(), &b.to_lowercase()).then_with(|| a.cmp(&b))
+}
+
+/// Compare two strings, treating each run of ASCII digits as a single number.
+///
+/// `file2` sorts before `file10`. When two runs have the same value, the one with fewer
+/// leading zeros sorts first, so `file2` comes before `file02`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    let mut zeros = Ordering::Equal;
+
+    loop {
+        match (a.peek(), b.peek()) {
+            (None, None) => return zeros,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_digits(&mut a);
+                let y = take_digits(&mut b);
+                let x_trimmed = x.trim_start_matches('0');
+                let y_trimmed = y.trim_start_matches('0');
+
+                let ordering = x_trimmed
+                    .len()
+                    .cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                if zeros == Ordering::Equal {
+                    zeros = x.len().cmp(&y.len());
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.cmp(y);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
@@ -0,0 +1,158 @@
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+
+use crate::pages;
+
+/// Where page numbers are drawn.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PageNumberPosition {
+    #[default]
+    BottomCenter,
+    BottomRight,
+}
+
+const FONT_NAME: &[u8] = b"MergePdfPageNumber";
+const FONT_SIZE: f32 = 10.0;
+const MARGIN: f32 = 30.0;
+
+/// Stamp "Page N of M" onto every page of `doc`.
+pub(crate) fn stamp_page_numbers(doc: &mut Document, position: PageNumberPosition) {
+    let pages = doc.get_pages();
+    let total = pages.len();
+    let font_id = add_standard_font(doc, "Helvetica");
+
+    for (&number, &page_id) in &pages {
+        let text = format!("Page {} of {}", number, total);
+        let [llx, lly, urx, _] = pages::media_box(doc, page_id);
+        let width = helvetica_width(&text, FONT_SIZE);
+        let x = match position {
+            PageNumberPosition::BottomCenter => (llx + urx - width) / 2.0,
+            PageNumberPosition::BottomRight => urx - MARGIN - width,
+        };
+        let y = lly + MARGIN;
+
+        let content = format!(
+            "BT /{} {} Tf {:.2} {:.2} Td ({}) Tj ET",
+            String::from_utf8_lossy(FONT_NAME),
+            FONT_SIZE,
+            x,
+            y,
+            text
+        );
+        add_font_resource(doc, page_id, FONT_NAME, font_id);
+        overlay(doc, page_id, content.into_bytes());
+    }
+}
+
+/// Add a non-embedded standard 14 font to `doc`.
+pub(crate) fn add_standard_font(doc: &mut Document, base_font: &str) -> ObjectId {
+    doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => base_font,
+        "Encoding" => "WinAnsiEncoding",
+    })
+}
+
+/// Draw `content` on top of the existing page content.
+///
+/// The existing content is wrapped in `q`/`Q` so any graphics state it leaves behind (a scaled
+/// CTM, a fill colour) does not leak into the overlay.
+pub(crate) fn overlay(doc: &mut Document, page_id: ObjectId, content: Vec<u8>) {
+    let existing = match doc.get_dictionary(page_id).and_then(|page| page.get(b"Contents")) {
+        Ok(Object::Reference(id)) => vec![Object::Reference(*id)],
+        Ok(Object::Array(contents)) => contents.clone(),
+        _ => Vec::new(),
+    };
+
+    let mut contents = Vec::with_capacity(existing.len() + 2);
+    if !existing.is_empty() {
+        contents.push(doc.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec())).into());
+        contents.extend(existing);
+        let mut restore = b"\nQ\n".to_vec();
+        restore.extend(content);
+        contents.push(doc.add_object(Stream::new(Dictionary::new(), restore)).into());
+    } else {
+        contents.push(doc.add_object(Stream::new(Dictionary::new(), content)).into());
+    }
+
+    if let Ok(page) = doc.get_dictionary_mut(page_id) {
+        page.set("Contents", contents);
+    }
+}
+
+/// Register `font_id` as `/name` in the page's font resources.
+pub(crate) fn add_font_resource(doc: &mut Document, page_id: ObjectId, name: &[u8], font_id: ObjectId) {
+    add_resource(doc, page_id, b"Font", name, font_id.into());
+}
+
+/// Register `value` under `/category /name` in the page's resources.
+///
+/// Resources inherited from the page tree are copied onto the page first, so that adding an
+/// entry does not hide the inherited ones.
+pub(crate) fn add_resource(doc: &mut Document, page_id: ObjectId, category: &[u8], name: &[u8], value: Object) {
+    let resources = match doc.get_dictionary(page_id).and_then(|page| page.get(b"Resources")) {
+        Ok(Object::Reference(id)) => Some(*id),
+        Ok(_) => None,
+        Err(_) => {
+            let inherited = match pages::inherited_attribute(doc, page_id, b"Resources") {
+                Some(Object::Dictionary(dict)) => dict.clone(),
+                _ => Dictionary::new(),
+            };
+            if let Ok(page) = doc.get_dictionary_mut(page_id) {
+                page.set("Resources", inherited);
+            }
+            None
+        }
+    };
+
+    // Resolve the category dictionary, which may itself be shared by reference.
+    let category_ref = {
+        let dict = match resources {
+            Some(id) => doc.get_dictionary(id).ok(),
+            None => doc
+                .get_dictionary(page_id)
+                .and_then(|page| page.get(b"Resources"))
+                .and_then(Object::as_dict)
+                .ok(),
+        };
+        dict.and_then(|dict| dict.get(category).and_then(Object::as_reference).ok())
+    };
+
+    let target = match category_ref {
+        Some(id) => doc.get_dictionary_mut(id).ok(),
+        None => {
+            let dict = match resources {
+                Some(id) => doc.get_dictionary_mut(id).ok(),
+                None => doc
+                    .get_dictionary_mut(page_id)
+                    .and_then(|page| page.get_mut(b"Resources"))
+                    .and_then(Object::as_dict_mut)
+                    .ok(),
+            };
+            dict.and_then(|dict| {
+                if !dict.has(category) {
+                    dict.set(category, Dictionary::new());
+                }
+                dict.get_mut(category).and_then(Object::as_dict_mut).ok()
+            })
+        }
+    };
+
+    if let Some(target) = target {
+        target.set(name, value);
+    }
+}
+
+/// Width of `text` set in Helvetica at `size`, for the characters used in page labels.
+pub(crate) fn helvetica_width(text: &str, size: f32) -> f32 {
+    let units: u32 = text
+        .chars()
+        .map(|c| match c {
+            ' ' | 'f' => 278,
+            'P' => 667,
+            // Digits and the lowercase letters of "age" and "of".
+            _ => 556,
+        })
+        .sum();
+    units as f32 * size / 1000.0
+}
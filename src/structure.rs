@@ -0,0 +1,189 @@
+use std::collections::{BTreeMap, HashSet};
+
+use log::debug;
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+
+use crate::destinations::deref_dict;
+use crate::name_tree;
+
+/// The logical structure of the merged documents, combined into one `/StructTreeRoot`.
+///
+/// Each document's top-level structure elements become children of the merged root, in merge
+/// order. Marked content IDs are numbered per page, so they stay valid as they are; what has
+/// to change are the keys of each document's parent tree, which link pages, annotations and
+/// form XObjects to their structure elements, as they would clash between documents.
+#[derive(Default)]
+pub(crate) struct StructureTree {
+    /// The first document's root object, which the merged root replaces.
+    root: Option<ObjectId>,
+    kids: Vec<Object>,
+    parent_tree: BTreeMap<i64, Object>,
+    next_key: i64,
+    role_map: Dictionary,
+    class_map: Dictionary,
+    ids: Vec<(Vec<u8>, Object)>,
+    id_names: HashSet<Vec<u8>>,
+}
+
+impl StructureTree {
+    /// Take over the structure tree of `doc`, which must already be renumbered into the merged
+    /// document's ID space.
+    ///
+    /// The `StructParents` and `StructParent` keys of `doc`'s objects are moved past those of
+    /// the documents added before it. Role and class names, and element IDs, that an earlier
+    /// document already defines keep the earlier definition.
+    pub(crate) fn add(&mut self, doc: &mut Document) {
+        let Ok(root_id) = doc
+            .catalog()
+            .and_then(|catalog| catalog.get(b"StructTreeRoot"))
+            .and_then(Object::as_reference)
+        else {
+            return;
+        };
+        let Ok(root) = doc.get_dictionary(root_id).cloned() else {
+            return;
+        };
+        let merged_root = *self.root.get_or_insert(root_id);
+
+        // `K` is a single element or an array of them.
+        let kids = match root.get(b"K") {
+            Ok(kids) => match doc.dereference(kids) {
+                Ok((_, Object::Array(kids))) => kids.clone(),
+                _ => vec![kids.clone()],
+            },
+            Err(_) => Vec::new(),
+        };
+        for kid in kids {
+            match kid {
+                Object::Reference(id) => {
+                    if let Ok(element) = doc.get_dictionary_mut(id) {
+                        element.set("P", merged_root);
+                    }
+                    self.kids.push(kid);
+                }
+                Object::Dictionary(mut element) => {
+                    element.set("P", merged_root);
+                    self.kids.push(Object::Dictionary(element));
+                }
+                _ => {}
+            }
+        }
+
+        let offset = self.next_key;
+        let mut next_key = root.get(b"ParentTreeNextKey").and_then(Object::as_i64).unwrap_or(0);
+        if let Ok(parent_tree) = root.get(b"ParentTree") {
+            let mut entries = Vec::new();
+            collect_numbers(doc, parent_tree, &mut entries, &mut HashSet::new());
+            for (key, value) in entries {
+                next_key = next_key.max(key + 1);
+                self.parent_tree.insert(key + offset, value);
+            }
+        }
+        if offset != 0 {
+            shift_struct_parents(doc, offset);
+        }
+        self.next_key += next_key;
+
+        for (key, map) in [(b"RoleMap".as_slice(), &mut self.role_map), (b"ClassMap", &mut self.class_map)] {
+            if let Ok(entries) = root.get(key).and_then(|entries| deref_dict(doc, entries)) {
+                for (name, value) in entries.iter() {
+                    if !map.has(name) {
+                        map.set(name.clone(), value.clone());
+                    }
+                }
+            }
+        }
+
+        if let Ok(tree) = root.get(b"IDTree") {
+            for (id, element) in name_tree::leaves(doc, tree) {
+                if self.id_names.insert(id.clone()) {
+                    self.ids.push((id, element));
+                } else {
+                    debug!("Dropping duplicate structure element ID {:?}", String::from_utf8_lossy(&id));
+                }
+            }
+        }
+
+        // The merged root takes the place of the first document's; the others are superseded.
+        if root_id != merged_root {
+            doc.objects.remove(&root_id);
+        }
+    }
+
+    /// The ID and dictionary of the merged structure tree root, or `None` if no document had
+    /// a structure tree.
+    pub(crate) fn into_root(mut self) -> Option<(ObjectId, Dictionary)> {
+        let id = self.root?;
+
+        let nums = self
+            .parent_tree
+            .into_iter()
+            .flat_map(|(key, value)| [Object::Integer(key), value])
+            .collect::<Vec<_>>();
+        let mut root = dictionary! {
+            "Type" => "StructTreeRoot",
+            "K" => self.kids,
+            "ParentTree" => dictionary! { "Nums" => nums },
+            "ParentTreeNextKey" => self.next_key,
+        };
+        if !self.role_map.is_empty() {
+            root.set("RoleMap", self.role_map);
+        }
+        if !self.class_map.is_empty() {
+            root.set("ClassMap", self.class_map);
+        }
+        if !self.ids.is_empty() {
+            // Name tree keys must be in sorted order.
+            self.ids.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let names = self
+                .ids
+                .into_iter()
+                .flat_map(|(name, element)| [Object::string_literal(name), element])
+                .collect::<Vec<_>>();
+            root.set("IDTree", dictionary! { "Names" => names });
+        }
+        Some((id, root))
+    }
+}
+
+/// Every `(key, value)` pair in the number tree rooted at `node`, in tree order.
+fn collect_numbers(doc: &Document, node: &Object, entries: &mut Vec<(i64, Object)>, visited: &mut HashSet<ObjectId>) {
+    if let Ok(id) = node.as_reference() {
+        if !visited.insert(id) {
+            return;
+        }
+    }
+    let Ok(node) = deref_dict(doc, node) else {
+        return;
+    };
+
+    if let Ok(nums) = node.get(b"Nums").and_then(Object::as_array) {
+        for pair in nums.chunks(2) {
+            if let [Object::Integer(key), value] = pair {
+                entries.push((*key, value.clone()));
+            }
+        }
+    }
+
+    if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+        for kid in kids {
+            collect_numbers(doc, kid, entries, visited);
+        }
+    }
+}
+
+/// Add `offset` to the parent tree key of every page, annotation and form XObject of `doc`.
+fn shift_struct_parents(doc: &mut Document, offset: i64) {
+    for object in doc.objects.values_mut() {
+        let dict = match object {
+            Object::Dictionary(dict) => dict,
+            Object::Stream(stream) => &mut stream.dict,
+            _ => continue,
+        };
+        for key in [b"StructParents".as_slice(), b"StructParent"] {
+            if let Ok(Object::Integer(parent_key)) = dict.get_mut(key) {
+                *parent_key += offset;
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Dictionary, Object, ObjectId, Stream};
+
+use crate::PaperSize;
+
+const FONT_NAME: &str = "MergePdfToc";
+const HEADING_SIZE: f32 = 18.0;
+const FONT_SIZE: f32 = 11.0;
+const LEADING: f32 = 14.0;
+const MARGIN: f32 = 72.0;
+const LINES_PER_PAGE: usize = 40;
+/// Longer titles are cut short so they don't run into the page numbers.
+const MAX_TITLE_CHARS: usize = 70;
+
+/// How many pages a table of contents with `entries` lines takes.
+pub(crate) fn page_count(entries: usize) -> usize {
+    entries.div_ceil(LINES_PER_PAGE).max(1)
+}
+
+/// How many objects a table of contents with `entries` lines takes: a font, and a page and a
+/// content stream per page.
+pub(crate) fn object_count(entries: usize) -> usize {
+    1 + 2 * page_count(entries)
+}
+
+/// The objects making up a table of contents.
+pub(crate) struct TocPages {
+    /// The page dictionaries, still missing their `Parent`.
+    pub(crate) pages: Vec<(ObjectId, Dictionary)>,
+    /// The font and content streams the pages use.
+    pub(crate) objects: Vec<(ObjectId, Object)>,
+}
+
+/// Build the table of contents pages, listing each title with the page number it starts on.
+///
+/// Objects are numbered from `next_id`, which is advanced past them. The pages are `size`.
+pub(crate) fn toc_pages(entries: &[(String, usize)], size: PaperSize, next_id: &mut u32) -> TocPages {
+    let mut take_id = || {
+        let id = (*next_id, 0);
+        *next_id += 1;
+        id
+    };
+
+    let font_id = take_id();
+    let mut objects = vec![(
+        font_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => "WinAnsiEncoding",
+        }),
+    )];
+    let mut pages = Vec::new();
+
+    // An empty table still gets its heading page.
+    let chunks: Vec<&[(String, usize)]> = if entries.is_empty() {
+        vec![&[]]
+    } else {
+        entries.chunks(LINES_PER_PAGE).collect()
+    };
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let mut operations = Vec::new();
+        let mut y = size.height - MARGIN;
+        if index == 0 {
+            text(&mut operations, HEADING_SIZE, MARGIN, y, "Contents");
+        }
+        y -= 2.0 * LEADING;
+
+        for (title, page) in chunk {
+            let number = page.to_string();
+            text(&mut operations, FONT_SIZE, MARGIN, y, &truncate(title));
+            let x = size.width - MARGIN - crate::stamp::helvetica_width(&number, FONT_SIZE);
+            text(&mut operations, FONT_SIZE, x, y, &number);
+            y -= LEADING;
+        }
+
+        let content = Content { operations }.encode().unwrap_or_default();
+        let content_id = take_id();
+        objects.push((content_id, Object::Stream(Stream::new(Dictionary::new(), content))));
+
+        pages.push((
+            take_id(),
+            dictionary! {
+                "Type" => "Page",
+                "MediaBox" => size.media_box(),
+                "Contents" => content_id,
+                "Resources" => dictionary! {
+                    "Font" => dictionary! { FONT_NAME => font_id },
+                },
+            },
+        ));
+    }
+
+    TocPages { pages, objects }
+}
+
+fn text(operations: &mut Vec<Operation>, size: f32, x: f32, y: f32, text: &str) {
+    operations.push(Operation::new("BT", vec![]));
+    operations.push(Operation::new("Tf", vec![Object::Name(FONT_NAME.into()), size.into()]));
+    operations.push(Operation::new("Td", vec![x.into(), y.into()]));
+    operations.push(Operation::new("Tj", vec![Object::string_literal(win_ansi(text))]));
+    operations.push(Operation::new("ET", vec![]));
+}
+
+fn truncate(title: &str) -> String {
+    if title.chars().count() <= MAX_TITLE_CHARS {
+        return title.to_string();
+    }
+    let mut title = title.chars().take(MAX_TITLE_CHARS - 3).collect::<String>();
+    title.push_str("...");
+    title
+}
+
+/// Encode `text` for a WinAnsi font; characters outside Latin-1 become `?`.
+pub(crate) fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars().map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?')).collect()
+}
@@ -0,0 +1,35 @@
+use lopdf::{Document, Object, ObjectId};
+
+/// A reference to an object that isn't in the document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DanglingReference {
+    /// The object holding the reference, or `None` for the trailer.
+    pub from: Option<ObjectId>,
+    /// The missing object.
+    pub to: ObjectId,
+}
+
+/// Every reference in `doc`, trailer included, that points at an object `doc` doesn't have.
+///
+/// Readers treat such references as `null`, so a page may lose its content or fonts without
+/// any error; after a merge they point to objects that failed to be carried over.
+pub fn dangling_references(doc: &Document) -> Vec<DanglingReference> {
+    let mut dangling = Vec::new();
+    for (_, value) in doc.trailer.iter() {
+        collect(doc, None, value, &mut dangling);
+    }
+    for (&id, object) in &doc.objects {
+        collect(doc, Some(id), object, &mut dangling);
+    }
+    dangling
+}
+
+fn collect(doc: &Document, from: Option<ObjectId>, object: &Object, dangling: &mut Vec<DanglingReference>) {
+    match object {
+        Object::Reference(to) if !doc.objects.contains_key(to) => dangling.push(DanglingReference { from, to: *to }),
+        Object::Array(items) => items.iter().for_each(|item| collect(doc, from, item, dangling)),
+        Object::Dictionary(dict) => dict.iter().for_each(|(_, value)| collect(doc, from, value, dangling)),
+        Object::Stream(stream) => stream.dict.iter().for_each(|(_, value)| collect(doc, from, value, dangling)),
+        _ => {}
+    }
+}
@@ -0,0 +1,174 @@
+use image::{GenericImageView, ImageFormat};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, ObjectId, Stream};
+
+use crate::{pages, stamp};
+
+const RESOURCE_NAME: &[u8] = b"MergePdfWatermark";
+/// Text watermarks span about this share of the page diagonal.
+const TEXT_SPAN: f32 = 0.6;
+const MAX_FONT_SIZE: f32 = 150.0;
+/// Image watermarks are this share of the page width at most.
+const IMAGE_SPAN: f32 = 0.5;
+
+/// A semi-transparent text or image drawn across the middle of every page.
+#[derive(Debug, Clone)]
+pub struct Watermark {
+    pub content: WatermarkContent,
+    /// From 0 (invisible) to 1 (opaque).
+    pub opacity: f32,
+    /// Counter-clockwise, in degrees.
+    pub angle: f32,
+}
+
+/// What a watermark shows.
+#[derive(Debug, Clone)]
+pub enum WatermarkContent {
+    /// Text set in gray Helvetica Bold.
+    Text(String),
+    Image(Box<WatermarkImage>),
+}
+
+/// A decoded PNG or JPEG, ready to embed.
+#[derive(Debug, Clone)]
+pub struct WatermarkImage {
+    image: Stream,
+    mask: Option<Stream>,
+    width: u32,
+    height: u32,
+}
+
+impl WatermarkImage {
+    /// Decode a PNG or JPEG file's contents. JPEGs are embedded as they are; other images are
+    /// re-encoded, with any transparency kept as a soft mask.
+    pub fn from_bytes(data: &[u8]) -> Result<WatermarkImage, String> {
+        let decoded = image::load_from_memory(data).map_err(|err| err.to_string())?;
+        let (width, height) = decoded.dimensions();
+        let image_dict = |color_space: &str| {
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => width,
+                "Height" => height,
+                "ColorSpace" => color_space,
+                "BitsPerComponent" => 8,
+            }
+        };
+
+        if image::guess_format(data).is_ok_and(|format| format == ImageFormat::Jpeg) {
+            let color_space = if decoded.color().has_color() { "DeviceRGB" } else { "DeviceGray" };
+            let mut dict = image_dict(color_space);
+            dict.set("Filter", "DCTDecode");
+            let image = Stream::new(dict, data.to_vec());
+            return Ok(WatermarkImage { image, mask: None, width, height });
+        }
+
+        let mut image = Stream::new(image_dict("DeviceRGB"), decoded.to_rgb8().into_raw());
+        let _ = image.compress();
+        let mask = decoded.color().has_alpha().then(|| {
+            let alpha = decoded.to_rgba8().pixels().map(|pixel| pixel[3]).collect();
+            let mut mask = Stream::new(image_dict("DeviceGray"), alpha);
+            let _ = mask.compress();
+            mask
+        });
+        Ok(WatermarkImage { image, mask, width, height })
+    }
+}
+
+/// Draw `watermark` over every page of `doc`, generated pages included.
+pub(crate) fn apply_watermark(doc: &mut Document, watermark: &Watermark) {
+    let opacity = watermark.opacity.clamp(0.0, 1.0);
+    let state_id = doc.add_object(dictionary! {
+        "Type" => "ExtGState",
+        "ca" => opacity,
+        "CA" => opacity,
+    });
+
+    let resource = match &watermark.content {
+        WatermarkContent::Text(_) => (b"Font".as_slice(), stamp::add_standard_font(doc, "Helvetica-Bold")),
+        WatermarkContent::Image(image) => (b"XObject".as_slice(), add_image(doc, image)),
+    };
+
+    for page_id in doc.get_pages().into_values() {
+        let [llx, lly, urx, ury] = pages::media_box(doc, page_id);
+        let (width, height) = (urx - llx, ury - lly);
+        let (cos, sin) = (watermark.angle.to_radians().cos(), watermark.angle.to_radians().sin());
+
+        // Rotate about the centre of the page.
+        let mut operations = vec![
+            Operation::new("q", vec![]),
+            Operation::new("gs", vec![Object::Name(RESOURCE_NAME.to_vec())]),
+            Operation::new(
+                "cm",
+                vec![
+                    cos.into(),
+                    sin.into(),
+                    (-sin).into(),
+                    cos.into(),
+                    (llx + width / 2.0).into(),
+                    (lly + height / 2.0).into(),
+                ],
+            ),
+        ];
+        match &watermark.content {
+            WatermarkContent::Text(text) => text_operations(&mut operations, text, width.hypot(height)),
+            WatermarkContent::Image(image) => image_operations(&mut operations, image, width, height),
+        }
+        operations.push(Operation::new("Q", vec![]));
+
+        stamp::add_resource(doc, page_id, b"ExtGState", RESOURCE_NAME, state_id.into());
+        stamp::add_resource(doc, page_id, resource.0, RESOURCE_NAME, resource.1.into());
+        stamp::overlay(doc, page_id, Content { operations }.encode().unwrap_or_default());
+    }
+}
+
+/// Centred text, sized to span part of the page diagonal.
+fn text_operations(operations: &mut Vec<Operation>, text: &str, diagonal: f32) {
+    let unit_width = stamp::helvetica_width(text, 1.0).max(f32::EPSILON);
+    let size = (diagonal * TEXT_SPAN / unit_width).min(MAX_FONT_SIZE);
+    let encoded = text.chars().map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?')).collect::<Vec<_>>();
+
+    operations.extend([
+        Operation::new("g", vec![0.5.into()]),
+        Operation::new("BT", vec![]),
+        Operation::new("Tf", vec![Object::Name(RESOURCE_NAME.to_vec()), size.into()]),
+        // Cap height is roughly 0.7em, so this centres the letters vertically.
+        Operation::new("Td", vec![(-unit_width * size / 2.0).into(), (-0.35 * size).into()]),
+        Operation::new("Tj", vec![Object::string_literal(encoded)]),
+        Operation::new("ET", vec![]),
+    ]);
+}
+
+/// The image, centred and scaled to fit half the page width, keeping its aspect ratio.
+fn image_operations(operations: &mut Vec<Operation>, image: &WatermarkImage, width: f32, height: f32) {
+    let aspect = image.height as f32 / image.width.max(1) as f32;
+    let mut image_width = width * IMAGE_SPAN;
+    if image_width * aspect > height * IMAGE_SPAN {
+        image_width = height * IMAGE_SPAN / aspect;
+    }
+    let image_height = image_width * aspect;
+
+    operations.extend([
+        Operation::new(
+            "cm",
+            vec![
+                image_width.into(),
+                0.into(),
+                0.into(),
+                image_height.into(),
+                (-image_width / 2.0).into(),
+                (-image_height / 2.0).into(),
+            ],
+        ),
+        Operation::new("Do", vec![Object::Name(RESOURCE_NAME.to_vec())]),
+    ]);
+}
+
+fn add_image(doc: &mut Document, image: &WatermarkImage) -> ObjectId {
+    let mut stream = image.image.clone();
+    if let Some(mask) = &image.mask {
+        let mask_id = doc.add_object(mask.clone());
+        stream.dict.set("SMask", mask_id);
+    }
+    doc.add_object(stream)
+}
@@ -0,0 +1,88 @@
+use chrono::{Local, SecondsFormat};
+use log::debug;
+use lopdf::{dictionary, Document, Object, ObjectId, Stream};
+
+use crate::Metadata;
+
+/// What the merged PDF's XMP metadata stream (the catalog's `/Metadata`) holds.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XmpMetadata {
+    /// The XMP of the first document that has well-formed XMP.
+    First,
+    /// A fresh packet with the merged PDF's title, author, subject and keywords.
+    Generate,
+}
+
+/// The ID of the document's XMP metadata stream, if it has one and it is well-formed XML.
+pub(crate) fn valid_metadata(doc: &Document) -> Option<ObjectId> {
+    let id = doc.catalog().ok()?.get(b"Metadata").ok()?.as_reference().ok()?;
+    let stream = doc.get_object(id).ok()?.as_stream().ok()?;
+    let content = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+
+    match std::str::from_utf8(&content).map_err(|err| err.to_string()).and_then(|xml| {
+        roxmltree::Document::parse(xml).map(|_| ()).map_err(|err| err.to_string())
+    }) {
+        Ok(()) => Some(id),
+        Err(err) => {
+            debug!("Ignoring malformed XMP metadata: {}", err);
+            None
+        }
+    }
+}
+
+/// A minimal XMP packet describing `metadata`, as an uncompressed metadata stream.
+pub(crate) fn generate(metadata: &Metadata) -> Object {
+    let now = Local::now().to_rfc3339_opts(SecondsFormat::Secs, false);
+
+    let mut properties = String::new();
+    if let Some(title) = &metadata.title {
+        properties.push_str(&format!(
+            "   <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n",
+            escape(title)
+        ));
+    }
+    if let Some(author) = &metadata.author {
+        properties.push_str(&format!(
+            "   <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n",
+            escape(author)
+        ));
+    }
+    if let Some(subject) = &metadata.subject {
+        properties.push_str(&format!(
+            "   <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n",
+            escape(subject)
+        ));
+    }
+    if let Some(keywords) = &metadata.keywords {
+        properties.push_str(&format!("   <pdf:Keywords>{}</pdf:Keywords>\n", escape(keywords)));
+    }
+
+    let packet = format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+            " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+            "  <rdf:Description rdf:about=\"\"\n",
+            "    xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n",
+            "    xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\"\n",
+            "    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\">\n",
+            "{}",
+            "   <pdf:Producer>merge_pdf</pdf:Producer>\n",
+            "   <xmp:CreateDate>{}</xmp:CreateDate>\n",
+            "   <xmp:ModifyDate>{}</xmp:ModifyDate>\n",
+            "  </rdf:Description>\n",
+            " </rdf:RDF>\n",
+            "</x:xmpmeta>\n",
+            "<?xpacket end=\"w\"?>"
+        ),
+        properties, now, now
+    );
+
+    // Left uncompressed so tools that don't parse PDF can still find the packet.
+    let dict = dictionary! { "Type" => "Metadata", "Subtype" => "XML" };
+    Object::Stream(Stream::new(dict, packet.into_bytes()).with_compression(false))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,512 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use lopdf::Document;
+use merge_pdf::{merge_pdf, MergeOptions};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+/// `(level, title, page)` for every outline entry.
+fn outline(doc: &Document) -> Vec<(usize, String, usize)> {
+    doc.get_toc()
+        .unwrap()
+        .toc
+        .into_iter()
+        .map(|entry| (entry.level, entry.title, entry.page))
+        .collect()
+}
+
+/// `(title, page)` for every top-level outline entry, the bookmarks of the inputs.
+fn top_level(doc: &Document) -> Vec<(String, usize)> {
+    outline(doc)
+        .into_iter()
+        .filter(|(level, _, _)| *level == 1)
+        .map(|(_, title, page)| (title, page))
+        .collect()
+}
+
+fn merge_pdf_command() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_merge_pdf"))
+}
+
+/// Run the command line with the arguments `args` adds, quietly writing `merged.pdf` in `dir`
+/// over any earlier one, and load what it wrote.
+fn run_merge(dir: &Path, args: impl FnOnce(&mut Command) -> &mut Command) -> Document {
+    let output = dir.join("merged.pdf");
+    let status = args(merge_pdf_command().arg("--output").arg(&output).args(["--quiet", "--force"]))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    Document::load(output).unwrap()
+}
+
+#[test]
+fn merged_file_reloads_with_every_page_and_bookmark() {
+    let documents = ["two_pages", "outline"]
+        .into_iter()
+        .map(|name| (name.to_string(), Document::load(fixture(&format!("{}.pdf", name))).unwrap()))
+        .collect();
+    let mut merged = merge_pdf(documents, &MergeOptions::default()).unwrap();
+
+    let output = tempfile::NamedTempFile::new().unwrap();
+    merged.save_to(&mut output.as_file()).unwrap();
+    let reloaded = Document::load(output.path()).unwrap();
+
+    assert_eq!(reloaded.get_pages().len(), 5);
+    assert_eq!(top_level(&reloaded), [("two_pages".to_string(), 1), ("outline".to_string(), 3)]);
+    // The second fixture's own outline is kept underneath its bookmark.
+    assert!(outline(&reloaded).iter().any(|(level, _, page)| *level > 1 && *page >= 3));
+}
+
+#[test]
+fn command_line_merge_writes_a_loadable_pdf() {
+    let dir = tempfile::tempdir().unwrap();
+    let merged = run_merge(dir.path(), |command| {
+        command
+            .arg("--files")
+            .arg(fixture("outline.pdf"))
+            .arg(format!("{}:2", fixture("two_pages.pdf").display()))
+    });
+    assert_eq!(merged.get_pages().len(), 4);
+    assert_eq!(outline(&merged)[0], (1, "outline".to_string(), 1));
+}
+
+#[test]
+fn empty_folder_fails_without_writing_an_output_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("merged.pdf");
+
+    let result = merge_pdf_command()
+        .arg("--folder")
+        .arg(dir.path())
+        .arg("--output")
+        .arg(&output)
+        .output()
+        .unwrap();
+
+    assert_eq!(result.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&result.stderr).contains("No PDF files found in"));
+    assert!(!output.exists());
+}
+
+#[test]
+fn exit_codes_tell_failures_apart() {
+    let dir = tempfile::tempdir().unwrap();
+    let broken = dir.path().join("broken.pdf");
+    fs::write(&broken, "not a PDF").unwrap();
+    let exit_code = |args: &[&str]| {
+        merge_pdf_command()
+            .arg("--files")
+            .arg(&broken)
+            .arg("--output")
+            .arg(dir.path().join("merged.pdf"))
+            .args(args)
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+
+    assert_eq!(exit_code(&[]), Some(1));
+    assert_eq!(exit_code(&["--no-such-option"]), Some(1));
+    // Skipping the only input leaves nothing to merge.
+    assert_eq!(exit_code(&["--skip-errors"]), Some(2));
+}
+
+#[test]
+fn dash_reads_an_input_from_standard_input() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("merged.pdf");
+
+    let mut child = merge_pdf_command()
+        .arg("--files")
+        .arg(fixture("two_pages.pdf"))
+        .arg("-")
+        .arg("--output")
+        .arg(&output)
+        .arg("--quiet")
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(&fs::read(fixture("outline.pdf")).unwrap()).unwrap();
+    assert!(child.wait().unwrap().success());
+
+    let merged = Document::load(&output).unwrap();
+    assert_eq!(merged.get_pages().len(), 5);
+    assert_eq!(outline(&merged)[1], (1, "Page_3".to_string(), 3));
+}
+
+#[test]
+fn status_messages_stay_off_stdout() {
+    let dir = tempfile::tempdir().unwrap();
+    let run = |output: &Path| {
+        merge_pdf_command()
+            .arg("--files")
+            .arg(fixture("two_pages.pdf"))
+            .arg(fixture("outline.pdf"))
+            .arg("--output")
+            .arg(output)
+            .args(["-v", "--verify", "--print-hash", "--limit", "2"])
+            .output()
+            .unwrap()
+    };
+
+    let piped = run(Path::new("-"));
+    assert!(piped.status.success());
+    assert!(piped.stdout.starts_with(b"%PDF-") && piped.stdout.ends_with(b"%%EOF"));
+    assert_eq!(Document::load_mem(&piped.stdout).unwrap().get_pages().len(), 5);
+    let messages = String::from_utf8_lossy(&piped.stderr);
+    assert!(messages.contains("PDFs merged into standard output"));
+    assert!(messages.contains("SHA-256: "));
+
+    let saved = run(&dir.path().join("merged.pdf"));
+    assert!(saved.status.success());
+    assert!(saved.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&saved.stderr).contains("Verified 5 page(s)"));
+}
+
+#[test]
+fn url_inputs_are_downloaded() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/outline.pdf", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).unwrap();
+        let body = fs::read(fixture("outline.pdf")).unwrap();
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\nContent-Length: {}\r\n\r\n", body.len()).unwrap();
+        stream.write_all(&body).unwrap();
+    });
+
+    let dir = tempfile::tempdir().unwrap();
+    let merged = run_merge(dir.path(), |command| command.arg("--files").arg(fixture("two_pages.pdf")).arg(&url));
+    server.join().unwrap();
+    assert_eq!(merged.get_pages().len(), 5);
+    assert_eq!(outline(&merged)[1], (1, "outline".to_string(), 3));
+}
+
+#[test]
+fn placeholder_page_stands_in_for_an_unloadable_input() {
+    let dir = tempfile::tempdir().unwrap();
+    let broken = dir.path().join("broken.pdf");
+    fs::write(&broken, b"not a PDF").unwrap();
+
+    let merged = run_merge(dir.path(), |command| {
+        command
+            .arg("--files")
+            .arg(fixture("two_pages.pdf"))
+            .arg(&broken)
+            .arg(fixture("outline.pdf"))
+            .arg("--placeholder-on-error")
+    });
+    assert_eq!(merged.get_pages().len(), 6);
+    let text = merged.extract_text(&[3]).unwrap();
+    assert!(text.contains("Could not load") && text.contains("broken.pdf"), "{:?}", text);
+    assert_eq!(outline(&merged)[1], (1, "broken".to_string(), 3));
+}
+
+#[test]
+fn zip_archive_is_merged_like_a_folder() {
+    let dir = tempfile::tempdir().unwrap();
+    let archive = dir.path().join("bundle.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+    for (name, fixture_name) in [("part10.pdf", "outline.pdf"), ("nested/part2.pdf", "two_pages.pdf")] {
+        zip.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+        zip.write_all(&fs::read(fixture(fixture_name)).unwrap()).unwrap();
+    }
+    zip.start_file("readme.txt", zip::write::SimpleFileOptions::default()).unwrap();
+    zip.finish().unwrap();
+
+    let merged = run_merge(dir.path(), |command| command.arg("--folder").arg(&archive));
+    assert_eq!(merged.get_pages().len(), 5);
+    // Entries are sorted by name like files in a folder, nested ones included.
+    assert_eq!(outline(&merged)[0], (1, "part2".to_string(), 1));
+}
+
+#[test]
+fn repeated_command_line_merges_write_identical_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let merge = |name: &str| {
+        let output = dir.path().join(name);
+        let status = merge_pdf_command()
+            .arg("--files")
+            .arg(fixture("outline.pdf"))
+            .arg(fixture("two_pages.pdf"))
+            .arg(fixture("outline.pdf"))
+            .args(["--dedup", "--toc", "--page-numbers", "--quiet", "--output"])
+            .arg(&output)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        fs::read(output).unwrap()
+    };
+
+    assert!(merge("first.pdf") == merge("second.pdf"));
+}
+
+#[test]
+fn cover_and_back_pages_surround_the_sorted_inputs() {
+    let dir = tempfile::tempdir().unwrap();
+    let folder = dir.path().join("in");
+    fs::create_dir(&folder).unwrap();
+    fs::copy(fixture("two_pages.pdf"), folder.join("a.pdf")).unwrap();
+    fs::copy(fixture("two_pages.pdf"), folder.join("b.pdf")).unwrap();
+
+    let merged = run_merge(dir.path(), |command| {
+        command
+            .arg("--folder")
+            .arg(&folder)
+            .args(["--reverse", "--limit", "1", "--first-page-only"])
+            .arg("--cover")
+            .arg(fixture("outline.pdf"))
+            .arg("--back")
+            .arg(fixture("two_pages.pdf"))
+    });
+    assert_eq!(merged.get_pages().len(), 6);
+    assert_eq!(
+        top_level(&merged),
+        [("outline".to_string(), 1), ("b".to_string(), 4), ("two_pages".to_string(), 5)]
+    );
+    // The cover's own outline is kept underneath its bookmark.
+    assert!(outline(&merged).iter().any(|(level, _, page)| *level > 1 && *page <= 3));
+}
+
+#[test]
+fn find_duplicates_reports_identical_pages_by_file_and_page() {
+    let output = merge_pdf_command()
+        .arg("--files")
+        .arg(fixture("two_pages.pdf"))
+        .arg(fixture("outline.pdf"))
+        .arg(format!("{}:2", fixture("two_pages.pdf").display()))
+        .args(["--find-duplicates", "--dry-run", "--json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let two_pages = fixture("two_pages.pdf").display().to_string();
+    assert_eq!(
+        summary["duplicate_pages"],
+        serde_json::json!([[{ "path": two_pages, "page": 2 }, { "path": two_pages, "page": 2 }]])
+    );
+}
+
+#[test]
+fn positional_folders_and_files_merge_in_the_order_given() {
+    let dir = tempfile::tempdir().unwrap();
+    let chapters = dir.path().join("chapters");
+    fs::create_dir(&chapters).unwrap();
+    fs::copy(fixture("two_pages.pdf"), chapters.join("chapter_10.pdf")).unwrap();
+    fs::copy(fixture("two_pages.pdf"), chapters.join("chapter_2.pdf")).unwrap();
+
+    let merged = run_merge(dir.path(), |command| {
+        command
+            .arg(fixture("outline.pdf"))
+            .arg(&chapters)
+            .arg(format!("{}:1", fixture("two_pages.pdf").display()))
+    });
+    assert_eq!(
+        top_level(&merged),
+        [
+            ("outline".to_string(), 1),
+            ("chapter_2".to_string(), 4),
+            ("chapter_10".to_string(), 6),
+            ("two_pages".to_string(), 8),
+        ]
+    );
+}
+
+#[test]
+fn page_count_range_skips_inputs_outside_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let merged_pages = |args: &[&str]| {
+        run_merge(dir.path(), |command| {
+            command.arg("--files").arg(fixture("two_pages.pdf")).arg(fixture("outline.pdf")).args(args)
+        })
+        .get_pages()
+        .len()
+    };
+
+    assert_eq!(merged_pages(&["--min-pages", "3"]), 3);
+    assert_eq!(merged_pages(&["--max-pages", "2"]), 2);
+    assert_eq!(merged_pages(&["--min-pages", "2", "--max-pages", "3"]), 5);
+}
+
+#[test]
+fn chunk_pages_writes_numbered_parts() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("merged.pdf");
+
+    let status = merge_pdf_command()
+        .arg("--files")
+        .arg(fixture("two_pages.pdf"))
+        .arg(fixture("outline.pdf"))
+        .arg("--output")
+        .arg(&output)
+        .args(["--chunk-pages", "2", "--quiet"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    assert!(!output.exists());
+    let pages = (1..=3)
+        .map(|number| {
+            let part = Document::load(dir.path().join(format!("merged_part{}.pdf", number))).unwrap();
+            part.get_pages().len()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(pages, [2, 2, 1]);
+    assert!(!dir.path().join("merged_part4.pdf").exists());
+}
+
+#[test]
+fn order_file_puts_the_listed_files_first() {
+    let dir = tempfile::tempdir().unwrap();
+    let folder = dir.path().join("scans");
+    fs::create_dir(&folder).unwrap();
+    for name in ["a.pdf", "b.pdf", "c.pdf"] {
+        fs::copy(fixture("two_pages.pdf"), folder.join(name)).unwrap();
+    }
+    fs::write(folder.join(".order"), "# cover first\nc.pdf\n\nmissing.pdf\n").unwrap();
+    let output = dir.path().join("merged.pdf");
+
+    let result = merge_pdf_command()
+        .arg("--folder")
+        .arg(&folder)
+        .arg("--output")
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(result.status.success());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("\"missing.pdf\", which doesn't exist"));
+
+    let merged = Document::load(&output).unwrap();
+    let titles = top_level(&merged).into_iter().map(|(title, _)| title).collect::<Vec<_>>();
+    assert_eq!(titles, ["c", "a", "b"]);
+}
+
+#[test]
+fn since_and_until_keep_files_modified_within_the_window() {
+    let dir = tempfile::tempdir().unwrap();
+    let folder = dir.path().join("scans");
+    fs::create_dir(&folder).unwrap();
+    for (name, day) in [("january.pdf", 15), ("june.pdf", 165), ("december.pdf", 350)] {
+        let path = folder.join(name);
+        fs::copy(fixture("two_pages.pdf"), &path).unwrap();
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs((54 * 365 + 13 + day) * 86_400);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+    }
+    let merged_titles = |args: &[&str]| {
+        let merged = run_merge(dir.path(), |command| {
+            command.arg("--folder").arg(&folder).args(["--sort", "mtime"]).args(args)
+        });
+        top_level(&merged).into_iter().map(|(title, _)| title).collect::<Vec<_>>()
+    };
+
+    assert_eq!(merged_titles(&["--since", "2024-01-01", "--until", "2024-06-30"]), ["january", "june"]);
+    assert_eq!(merged_titles(&["--since", "2024-06-01T00:00:00Z"]), ["june", "december"]);
+    assert_eq!(merged_titles(&["--until", "2024-06-14T00:00:00Z"]), ["january", "june"]);
+}
+
+#[test]
+fn single_input_is_written_as_it_is_unless_something_changes_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.pdf");
+    let mut doc = Document::load(fixture("outline.pdf")).unwrap();
+    doc.catalog_mut().unwrap().set("PageLayout", "TwoColumnLeft");
+    doc.save(&input).unwrap();
+    let source_outline = outline(&doc);
+
+    let merged = |args: &[&str]| run_merge(dir.path(), |command| command.arg("--files").arg(&input).args(args));
+    let page_layout = |doc: &Document| doc.catalog().unwrap().get(b"PageLayout").ok().cloned();
+
+    let copied = merged(&[]);
+    assert_eq!(copied.get_pages().len(), 3);
+    assert_eq!(page_layout(&copied), Some("TwoColumnLeft".into()));
+    assert_eq!(outline(&copied), source_outline);
+
+    let retitled = merged(&["--title", "Report"]);
+    assert_eq!(retitled.get_pages().len(), 3);
+    assert_eq!(page_layout(&retitled), None);
+    assert_ne!(outline(&retitled), source_outline);
+    // Any bookmark mode but the default rebuilds the outline.
+    let sequential = merged(&["--bookmark-mode", "sequential"]);
+    assert_eq!(page_layout(&sequential), None);
+    assert_eq!(outline(&sequential)[0], (1, "Page_1".to_string(), 1));
+    assert!(merged(&["--no-bookmarks"]).get_toc().is_err());
+}
+
+#[test]
+fn count_sums_the_pages_a_folder_merge_would_take() {
+    let count = |args: &[&str]| {
+        let output = merge_pdf_command()
+            .arg("count")
+            .arg(fixture(""))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!(count(&[]), "5\n");
+    assert_eq!(count(&["--exclude", "outline.pdf"]), "2\n");
+    // Only what the merge would take: outline.pdf comes first by name, two_pages.pdf has two pages.
+    assert_eq!(count(&["--limit", "1"]), "3\n");
+    assert_eq!(count(&["--min-pages", "3"]), "3\n");
+    assert_eq!(count(&["--cover", fixture("two_pages.pdf").to_str().unwrap(), "--max-pages", "2"]), "4\n");
+    assert_eq!(merge_pdf::count_pages_in_folder(fixture(""), &merge_pdf::FolderOptions::new()), Ok(5));
+}
+
+#[test]
+fn dry_run_tree_prints_the_outline_without_writing_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_path = dir.path().join("merged.pdf");
+    let output = merge_pdf_command()
+        .arg("--files")
+        .arg(fixture("two_pages.pdf"))
+        .arg(fixture("outline.pdf"))
+        .arg("--output")
+        .arg(&output_path)
+        .arg("--dry-run-tree")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!output_path.exists());
+
+    let tree = "two_pages (page 1)\n\
+                outline (page 3)\n  \
+                  B section 1 (page 3)\n    \
+                    B sub (page 3)\n  \
+                  B section 2 (page 4)\n  \
+                  B section 3 (page 5)\n";
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), tree);
+}
+
+#[test]
+fn globs_in_files_are_expanded_in_name_order() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let merged = run_merge(dir.path(), |command| command.arg("--files").arg(fixture("*.pdf")));
+    assert_eq!(top_level(&merged), [("outline".to_string(), 1), ("two_pages".to_string(), 4)]);
+
+    let status = merge_pdf_command()
+        .arg("--files")
+        .arg(fixture("missing_*.pdf"))
+        .arg("--output")
+        .arg(dir.path().join("nothing.pdf"))
+        .output()
+        .unwrap()
+        .status;
+    assert_eq!(status.code(), Some(1));
+    let merged = run_merge(dir.path(), |command| {
+        command.arg("--files").arg(fixture("missing_*.pdf")).arg(fixture("two_pages.pdf")).arg("--allow-empty-glob")
+    });
+    assert_eq!(merged.get_pages().len(), 2);
+}